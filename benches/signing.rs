@@ -0,0 +1,64 @@
+//! Signing throughput: `cargo bench`, or `cargo bench --features parallel` to sign batches on
+//! all cores.
+#[macro_use]
+extern crate criterion;
+extern crate ethereum_tx_sign;
+extern crate secp256k1;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use ethereum_tx_sign::{sign_batch, FeeMarketTransaction, PrivateKey, Transaction};
+use secp256k1::{Message, Secp256k1, SecretKey};
+
+fn transactions(count: u128) -> Vec<FeeMarketTransaction> {
+    (0..count)
+        .map(|nonce| FeeMarketTransaction {
+            chain: 1,
+            nonce,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            value: 10u128.pow(18),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn single(c: &mut Criterion) {
+    let tx = &transactions(1)[0];
+    let hash = tx.hash();
+    let key = PrivateKey::from_slice(&[0x35; 32]).unwrap();
+
+    let mut group = c.benchmark_group("single");
+    // what every signature used to cost: a new context for each one
+    group.bench_function("context per signature", |b| {
+        b.iter(|| {
+            let secp = Secp256k1::signing_only();
+            let key = SecretKey::from_slice(&[0x35; 32]).unwrap();
+            secp.sign_ecdsa_recoverable(&Message::from_slice(&hash).unwrap(), &key)
+        })
+    });
+    group.bench_function("shared context", |b| b.iter(|| tx.ecdsa(&key).unwrap()));
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    let key = PrivateKey::from_slice(&[0x35; 32]).unwrap();
+    let mut group = c.benchmark_group("batch");
+    for &count in [100, 1000].iter() {
+        let txs = transactions(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("one by one", count), &txs, |b, txs| {
+            b.iter(|| {
+                txs.iter()
+                    .map(|tx| tx.sign(&tx.ecdsa(&key).unwrap()))
+                    .collect::<Vec<_>>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("sign_batch", count), &txs, |b, txs| {
+            b.iter(|| sign_batch(txs, &key).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, single, batch);
+criterion_main!(benches);
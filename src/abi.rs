@@ -0,0 +1,131 @@
+//! Solidity ABI encoding of the contract calls the crate builds.
+
+/// An ABI value. Integers, addresses and fixed size byte arrays are all one `Word`.
+pub enum Token<'a> {
+    Word([u8; 32]),
+    Bytes(&'a [u8]),
+    Tuple(Vec<Token<'a>>),
+    Array(Vec<Token<'a>>),
+}
+
+impl<'a> Token<'a> {
+    pub fn uint(value: u128) -> Self {
+        let mut word = [0u8; 32];
+        word[16..].copy_from_slice(&value.to_be_bytes());
+        Token::Word(word)
+    }
+
+    pub fn address(address: &[u8; 20]) -> Self {
+        let mut word = [0u8; 32];
+        word[12..].copy_from_slice(address);
+        Token::Word(word)
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match *self {
+            Token::Word(_) => false,
+            Token::Bytes(_) | Token::Array(_) => true,
+            Token::Tuple(ref tokens) => tokens.iter().any(Token::is_dynamic),
+        }
+    }
+
+    /// Size of the token in the head of the enclosing tuple.
+    fn head_len(&self) -> usize {
+        match *self {
+            Token::Tuple(ref tokens) if !self.is_dynamic() => {
+                tokens.iter().map(Token::head_len).sum()
+            }
+            _ => 32,
+        }
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match *self {
+            Token::Word(ref word) => out.extend_from_slice(word),
+            Token::Bytes(bytes) => {
+                out.extend_from_slice(&word(bytes.len()));
+                out.extend_from_slice(bytes);
+                out.resize(out.len() + bytes.len().div_ceil(32) * 32 - bytes.len(), 0);
+            }
+            Token::Tuple(ref tokens) => encode_tuple(tokens, out),
+            Token::Array(ref tokens) => {
+                out.extend_from_slice(&word(tokens.len()));
+                encode_tuple(tokens, out);
+            }
+        }
+    }
+}
+
+/// Input data for a call to the function with the given selector.
+pub fn encode_call(selector: [u8; 4], arguments: &[Token]) -> Vec<u8> {
+    let mut out = selector.to_vec();
+    encode_tuple(arguments, &mut out);
+    out
+}
+
+/// `abi.encode` of the tokens.
+pub fn encode(tokens: &[Token]) -> Vec<u8> {
+    let mut out = vec![];
+    encode_tuple(tokens, &mut out);
+    out
+}
+
+fn encode_tuple(tokens: &[Token], out: &mut Vec<u8>) {
+    // dynamic tokens are placed after the head, which holds their offsets
+    let head_len: usize = tokens.iter().map(Token::head_len).sum();
+    let mut tail = vec![];
+    for token in tokens.iter() {
+        if token.is_dynamic() {
+            out.extend_from_slice(&word(head_len + tail.len()));
+            token.encode_into(&mut tail);
+        } else {
+            token.encode_into(out);
+        }
+    }
+    out.extend_from_slice(&tail);
+}
+
+fn word(value: usize) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+    word
+}
+
+#[cfg(test)]
+mod test {
+    use abi::{encode_call, Token};
+
+    #[test]
+    fn test_encode_dynamic() {
+        // f(uint256,(bytes,uint256)[],bytes) with 1, [("0x1234", 2)], "0x"
+        let data = encode_call(
+            [0xaa, 0xbb, 0xcc, 0xdd],
+            &[
+                Token::uint(1),
+                Token::Array(vec![Token::Tuple(vec![
+                    Token::Bytes(&[0x12, 0x34]),
+                    Token::uint(2),
+                ])]),
+                Token::Bytes(&[]),
+            ],
+        );
+        let expected = [
+            "aabbccdd",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000120",
+            // array length and offset of its only element
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            // tuple: offset of bytes, uint, then the bytes
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "1234000000000000000000000000000000000000000000000000000000000000",
+            // empty bytes
+            "0000000000000000000000000000000000000000000000000000000000000000",
+        ]
+        .concat();
+        assert_eq!(expected, hex::encode(data));
+    }
+}
@@ -0,0 +1,349 @@
+//! The secp256k1 implementation that keys are checked, transactions signed and signers
+//! recovered with: the libsecp256k1 C library, or the pure-Rust `k256` crate with the `k256`
+//! feature, which takes precedence when both are enabled. Both sign deterministically
+//! according to RFC 6979, so they produce the same signatures.
+//!
+//! Signatures cross this module as the 64 byte `r || s` form and a recovery ID, and public
+//! keys as their uncompressed 65 byte SEC1 encoding.
+
+use std::fmt;
+
+#[cfg(not(any(feature = "secp256k1", feature = "k256")))]
+compile_error!("enable the `secp256k1` (default) or `k256` feature");
+
+#[cfg(not(feature = "k256"))]
+pub(crate) use self::libsecp256k1::*;
+#[cfg(feature = "k256")]
+pub(crate) use self::rust::*;
+
+/// A key or signature rejected by the secp256k1 implementation, whichever one is enabled.
+///
+/// The underlying error is available through [`source`](std::error::Error::source).
+#[derive(Debug)]
+pub struct Secp256k1Error(Repr);
+
+#[derive(Debug)]
+enum Repr {
+    #[cfg(feature = "secp256k1")]
+    Libsecp256k1(secp256k1::Error),
+    #[cfg(feature = "k256")]
+    K256(k256::ecdsa::Error),
+}
+
+impl fmt::Display for Secp256k1Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            #[cfg(feature = "secp256k1")]
+            Repr::Libsecp256k1(ref err) => write!(f, "{}", err),
+            #[cfg(feature = "k256")]
+            Repr::K256(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Secp256k1Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.0 {
+            #[cfg(feature = "secp256k1")]
+            Repr::Libsecp256k1(ref err) => Some(err),
+            #[cfg(feature = "k256")]
+            Repr::K256(ref err) => Some(err),
+        }
+    }
+}
+
+#[cfg(feature = "secp256k1")]
+impl From<secp256k1::Error> for Secp256k1Error {
+    fn from(error: secp256k1::Error) -> Self {
+        Secp256k1Error(Repr::Libsecp256k1(error))
+    }
+}
+
+#[cfg(feature = "k256")]
+impl From<k256::ecdsa::Error> for Secp256k1Error {
+    fn from(error: k256::ecdsa::Error) -> Self {
+        Secp256k1Error(Repr::K256(error))
+    }
+}
+
+#[cfg(not(feature = "k256"))]
+mod libsecp256k1 {
+    use secp256k1::ecdsa::{RecoverableSignature, RecoveryId, Signature};
+    use secp256k1::{All, Message, PublicKey, Secp256k1};
+    use std::fmt;
+    use std::sync::OnceLock;
+    use zeroize::Zeroize;
+
+    use super::Secp256k1Error as Error;
+
+    pub(crate) fn invalid_signature() -> Error {
+        secp256k1::Error::InvalidSignature.into()
+    }
+
+    /// Context shared by every signature, recovery and key derivation. Creating a context
+    /// precomputes tables, which costs far more than signing, so it is done once. It is
+    /// randomized when created, blinding signing against timing and power side channels.
+    fn context() -> &'static Secp256k1<All> {
+        static CONTEXT: OnceLock<Secp256k1<All>> = OnceLock::new();
+        CONTEXT.get_or_init(|| {
+            let mut context = Secp256k1::new();
+            let mut seed = [0u8; 32];
+            // without a seed the context is still correct, just not blinded
+            if getrandom::getrandom(&mut seed).is_ok() {
+                context.seeded_randomize(&seed);
+            }
+            seed.zeroize();
+            context
+        })
+    }
+
+    /// A checked private key, erased when dropped.
+    pub(crate) struct SecretKey(secp256k1::SecretKey);
+
+    impl SecretKey {
+        pub(crate) fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+            Ok(SecretKey(secp256k1::SecretKey::from_slice(bytes)?))
+        }
+
+        pub(crate) fn public_key(&self) -> [u8; 65] {
+            PublicKey::from_secret_key(context(), &self.0).serialize_uncompressed()
+        }
+
+        /// `r || s` of `hash`, with a low `s`, and the recovery ID.
+        pub(crate) fn sign(&self, hash: &[u8; 32]) -> Result<([u8; 64], u8), Error> {
+            let msg = Message::from_slice(hash)?;
+            let (recovery_id, rs) = context()
+                .sign_ecdsa_recoverable(&msg, &self.0)
+                .serialize_compact();
+            Ok((rs, recovery_id.to_i32() as u8))
+        }
+    }
+
+    impl Drop for SecretKey {
+        fn drop(&mut self) {
+            self.0.non_secure_erase();
+        }
+    }
+
+    impl fmt::Debug for SecretKey {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "SecretKey(..)")
+        }
+    }
+
+    /// Uncompressed public key of the account that signed `hash`.
+    pub(crate) fn recover(
+        hash: &[u8; 32],
+        rs: &[u8; 64],
+        recovery_id: u8,
+    ) -> Result<[u8; 65], Error> {
+        let recovery_id = RecoveryId::from_i32(recovery_id as i32)?;
+        let signature = RecoverableSignature::from_compact(rs, recovery_id)?;
+        let public_key = context().recover_ecdsa(&Message::from_slice(hash)?, &signature)?;
+        Ok(public_key.serialize_uncompressed())
+    }
+
+    /// `r || s` of an ASN.1 DER signature, with `s` normalised to the lower half of the
+    /// curve order.
+    pub(crate) fn der_to_rs(der: &[u8]) -> Result<[u8; 64], Error> {
+        let mut signature = Signature::from_der(der)?;
+        signature.normalize_s();
+        Ok(signature.serialize_compact())
+    }
+
+    /// `rs` with `s` normalised to the lower half of the curve order.
+    pub(crate) fn normalize_rs(rs: &[u8; 64]) -> Result<[u8; 64], Error> {
+        let mut signature = Signature::from_compact(rs)?;
+        signature.normalize_s();
+        Ok(signature.serialize_compact())
+    }
+
+    /// Uncompressed form of a SEC1 encoded (33 or 65 byte) public key.
+    pub(crate) fn parse_public_key(sec1: &[u8]) -> Result<[u8; 65], Error> {
+        Ok(PublicKey::from_slice(sec1)?.serialize_uncompressed())
+    }
+}
+
+#[cfg(feature = "k256")]
+mod rust {
+    use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+    use std::fmt;
+
+    use super::Secp256k1Error as Error;
+
+    pub(crate) fn invalid_signature() -> Error {
+        k256::ecdsa::Error::new().into()
+    }
+
+    /// A checked private key, which `k256` erases when it is dropped.
+    pub(crate) struct SecretKey(SigningKey);
+
+    impl SecretKey {
+        pub(crate) fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+            // `k256` pads keys as short as 24 bytes, which libsecp256k1 rejects
+            if bytes.len() != 32 {
+                return Err(k256::ecdsa::Error::new().into());
+            }
+            Ok(SecretKey(SigningKey::from_slice(bytes)?))
+        }
+
+        pub(crate) fn public_key(&self) -> [u8; 65] {
+            uncompressed(self.0.verifying_key())
+        }
+
+        /// `r || s` of `hash`, with a low `s`, and the recovery ID.
+        pub(crate) fn sign(&self, hash: &[u8; 32]) -> Result<([u8; 64], u8), Error> {
+            let (signature, recovery_id) = self.0.sign_prehash_recoverable(hash)?;
+            let mut rs = [0u8; 64];
+            rs.copy_from_slice(&signature.to_bytes());
+            Ok((rs, recovery_id.to_byte()))
+        }
+    }
+
+    impl fmt::Debug for SecretKey {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "SecretKey(..)")
+        }
+    }
+
+    fn uncompressed(public_key: &VerifyingKey) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes.copy_from_slice(public_key.to_encoded_point(false).as_bytes());
+        bytes
+    }
+
+    /// Uncompressed public key of the account that signed `hash`.
+    pub(crate) fn recover(
+        hash: &[u8; 32],
+        rs: &[u8; 64],
+        recovery_id: u8,
+    ) -> Result<[u8; 65], Error> {
+        let mut recovery_id = RecoveryId::from_byte(recovery_id).ok_or_else(invalid_signature)?;
+        let mut signature = Signature::from_slice(rs)?;
+        // unlike libsecp256k1, `k256` refuses to recover from a high `s`; n - s recovers the
+        // same key with the other y-parity
+        if let Some(normalized) = signature.normalize_s() {
+            signature = normalized;
+            recovery_id = RecoveryId::new(!recovery_id.is_y_odd(), recovery_id.is_x_reduced());
+        }
+        let public_key = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id)?;
+        Ok(uncompressed(&public_key))
+    }
+
+    /// `r || s` of an ASN.1 DER signature, with `s` normalised to the lower half of the
+    /// curve order.
+    pub(crate) fn der_to_rs(der: &[u8]) -> Result<[u8; 64], Error> {
+        normalized(Signature::from_der(der)?)
+    }
+
+    /// `rs` with `s` normalised to the lower half of the curve order.
+    pub(crate) fn normalize_rs(rs: &[u8; 64]) -> Result<[u8; 64], Error> {
+        normalized(Signature::from_slice(rs)?)
+    }
+
+    fn normalized(signature: Signature) -> Result<[u8; 64], Error> {
+        let signature = signature.normalize_s().unwrap_or(signature);
+        let mut rs = [0u8; 64];
+        rs.copy_from_slice(&signature.to_bytes());
+        Ok(rs)
+    }
+
+    /// Uncompressed form of a SEC1 encoded (33 or 65 byte) public key.
+    pub(crate) fn parse_public_key(sec1: &[u8]) -> Result<[u8; 65], Error> {
+        Ok(uncompressed(&VerifyingKey::from_sec1_bytes(sec1)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use backend::{der_to_rs, normalize_rs, parse_public_key, recover, SecretKey};
+    use secp256k1::{Message, PublicKey, Secp256k1};
+    use {keccak256_hash, EcdsaSig, Error, LegacyTransaction, Transaction};
+
+    const SECP256K1_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36,
+        0x41, 0x41,
+    ];
+
+    /// Whichever backend is enabled, it must agree with libsecp256k1 byte for byte.
+    #[test]
+    fn test_matches_libsecp256k1() {
+        let secp = Secp256k1::new();
+        for i in 0..64u8 {
+            let private_key = keccak256_hash(&[i]);
+            let hash = keccak256_hash(&[i, i]);
+            let key = SecretKey::from_slice(&private_key).unwrap();
+            let expected_key = secp256k1::SecretKey::from_slice(&private_key).unwrap();
+            let expected_public_key =
+                PublicKey::from_secret_key(&secp, &expected_key).serialize_uncompressed();
+            assert_eq!(expected_public_key, key.public_key());
+
+            let msg = Message::from_slice(&hash).unwrap();
+            let (expected_id, expected_rs) = secp
+                .sign_ecdsa_recoverable(&msg, &expected_key)
+                .serialize_compact();
+            let (rs, recovery_id) = key.sign(&hash).unwrap();
+            assert_eq!(expected_rs, rs);
+            assert_eq!(expected_id.to_i32() as u8, recovery_id);
+            assert_eq!(
+                expected_public_key,
+                recover(&hash, &rs, recovery_id).unwrap()
+            );
+
+            // a high `s` (n - s, with the other y-parity) recovers the same key
+            let mut high_s = rs;
+            let mut borrow = 0i16;
+            for i in (0..32).rev() {
+                let d = SECP256K1_ORDER[i] as i16 - rs[32 + i] as i16 - borrow;
+                borrow = (d < 0) as i16;
+                high_s[32 + i] = (d + 256 * borrow) as u8;
+            }
+            assert_eq!(
+                expected_public_key,
+                recover(&hash, &high_s, recovery_id ^ 1).unwrap()
+            );
+
+            let der = secp.sign_ecdsa(&msg, &expected_key).serialize_der();
+            assert_eq!(rs, der_to_rs(&der).unwrap());
+            let compressed = PublicKey::from_secret_key(&secp, &expected_key).serialize();
+            assert_eq!(expected_public_key, parse_public_key(&compressed).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let order = hex::decode("fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141");
+        assert!(SecretKey::from_slice(&order.unwrap()).is_err());
+        assert!(SecretKey::from_slice(&[0u8; 32]).is_err());
+        assert!(SecretKey::from_slice(&[0x35; 31]).is_err());
+        let err = SecretKey::from_slice(&[0u8; 32]).unwrap_err();
+        assert!(std::error::Error::source(&err).is_some());
+        #[cfg(feature = "secp256k1")]
+        match Error::from(secp256k1::Error::InvalidSecretKey) {
+            Error::Secp256k1(_) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        // r and s above the curve order
+        assert!(normalize_rs(&[0xff; 64]).is_err());
+        assert!(der_to_rs(&[0x30, 0x00]).is_err());
+        assert!(parse_public_key(&[0x02; 33][..32]).is_err());
+        // the recovery ID is checked before the signature is used
+        let (rs, _) = SecretKey::from_slice(&[0x35; 32])
+            .unwrap()
+            .sign(&[0x11; 32])
+            .unwrap();
+        assert!(recover(&[0x11; 32], &rs, 4).is_err());
+
+        match LegacyTransaction::default().ecdsa(&[0u8; 32]) {
+            Err(Error::Secp256k1(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        let ecdsa = EcdsaSig {
+            v: 0,
+            r: vec![0; 32],
+            s: vec![1],
+        };
+        assert!(ecdsa.recover([0x11; 32], None).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use {EcdsaSig, Error, ToPrivateKey, Transaction};
+
+/// Compute the signatures of many transactions with one key, which is checked once. With the
+/// `parallel` feature the transactions are signed on all cores.
+///
+/// The signatures are those [`Transaction::ecdsa`] computes, in the order of `txs`.
+/// ```
+/// use ethereum_tx_sign::{ecdsa_batch, LegacyTransaction, Transaction};
+///
+/// let txs: Vec<LegacyTransaction> = (0..3)
+///     .map(|nonce| LegacyTransaction { chain: 1, nonce, gas: 21000, ..Default::default() })
+///     .collect();
+/// let signatures = ecdsa_batch(&txs, &[0x35; 32]).unwrap();
+/// assert_eq!(txs[2].ecdsa(&[0x35; 32]).unwrap(), signatures[2]);
+/// ```
+pub fn ecdsa_batch<T, K>(txs: &[T], private_key: &K) -> Result<Vec<EcdsaSig>, Error>
+where
+    T: Transaction + Sync,
+    K: ToPrivateKey + ?Sized,
+{
+    let key = private_key.to_private_key()?.secret_key();
+    let ecdsa = |tx: &T| {
+        let chain = match T::transaction_type() {
+            Some(_) => None,
+            None => Some(tx.chain()),
+        };
+        EcdsaSig::sign_hash(tx.hash(), &key, chain)
+    };
+
+    #[cfg(feature = "parallel")]
+    let signatures = txs.par_iter().map(ecdsa).collect();
+    #[cfg(not(feature = "parallel"))]
+    let signatures = txs.iter().map(ecdsa).collect();
+
+    signatures
+}
+
+/// Sign and encode many transactions with one key, as [`ecdsa_batch`] does.
+pub fn sign_batch<T, K>(txs: &[T], private_key: &K) -> Result<Vec<Vec<u8>>, Error>
+where
+    T: Transaction + Sync,
+    K: ToPrivateKey + ?Sized,
+{
+    let signatures = ecdsa_batch(txs, private_key)?;
+    Ok(txs
+        .iter()
+        .zip(signatures.iter())
+        .map(|(tx, ecdsa)| tx.sign(ecdsa))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use batch::{ecdsa_batch, sign_batch};
+    use {Error, FeeMarketTransaction, LegacyTransaction, PrivateKey, Transaction};
+
+    #[test]
+    fn test_batch_matches_single() {
+        let key = PrivateKey::from_slice(&[0x35; 32]).unwrap();
+        let legacy: Vec<LegacyTransaction> = (0..50)
+            .map(|nonce| LegacyTransaction {
+                chain: 5,
+                nonce,
+                gas: 21000,
+                to: Some([0x45; 20]),
+                ..Default::default()
+            })
+            .collect();
+        let signed = sign_batch(&legacy, &key).unwrap();
+        assert_eq!(legacy.len(), signed.len());
+        for (tx, bytes) in legacy.iter().zip(signed.iter()) {
+            assert_eq!(tx.sign(&tx.ecdsa(&key).unwrap()), *bytes);
+        }
+
+        let fee_market: Vec<FeeMarketTransaction> = (0..50)
+            .map(|nonce| FeeMarketTransaction {
+                chain: 1,
+                nonce,
+                gas: 21000,
+                ..Default::default()
+            })
+            .collect();
+        let signatures = ecdsa_batch(&fee_market, &[0x35; 32]).unwrap();
+        for (tx, ecdsa) in fee_market.iter().zip(signatures.iter()) {
+            assert_eq!(key.address(), tx.recover(ecdsa).unwrap());
+        }
+        assert!(sign_batch::<FeeMarketTransaction, _>(&[], &key)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_batch_errors() {
+        match sign_batch(&[LegacyTransaction::default()], &[0u8; 32]) {
+            Err(Error::Secp256k1(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        // chain ID that cannot be encoded in `v`
+        let txs = vec![
+            LegacyTransaction::default(),
+            LegacyTransaction {
+                chain: u64::MAX,
+                ..Default::default()
+            },
+        ];
+        match ecdsa_batch(&txs, &[0x35; 32]) {
+            Err(Error::ChainIdOverflow(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use serde_json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use {Error, ToPrivateKey, Transaction};
+
+/// Chains known without any configuration, in the same format as the files accepted by
+/// [`ChainRegistry::load`].
+const BUILT_IN_CHAINS: &str = include_str!("chains.json");
+
+/// What a chain is called and which transactions it accepts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chain {
+    /// [EIP-155](https://eips.ethereum.org/EIPS/eip-155) chain ID
+    #[serde(rename = "chainId")]
+    pub id: u64,
+    pub name: String,
+    /// Symbol of the currency that gas and `value` are paid in
+    #[serde(rename = "nativeSymbol")]
+    pub native_symbol: String,
+    /// Accepted [EIP-2718](https://eips.ethereum.org/EIPS/eip-2718) transaction types, with
+    /// 0 standing for legacy transactions
+    #[serde(rename = "transactionTypes")]
+    pub transaction_types: Vec<u8>,
+    /// Names of the hard forks that are active, in lowercase
+    #[serde(rename = "hardForks")]
+    #[serde(default)]
+    pub hard_forks: Vec<String>,
+}
+
+impl Chain {
+    /// Whether the chain accepts transactions of the type; `None` is a legacy transaction.
+    pub fn supports(&self, transaction_type: Option<u8>) -> bool {
+        self.transaction_types
+            .contains(&transaction_type.unwrap_or(0))
+    }
+
+    /// Whether the hard fork with the given name is active. Names are case-insensitive.
+    pub fn has_fork(&self, name: &str) -> bool {
+        self.hard_forks.iter().any(|f| f.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug)]
+pub enum ChainError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The chain does not accept transactions of this type.
+    UnsupportedTransactionType {
+        chain: u64,
+        transaction_type: Option<u8>,
+    },
+    Signature(Error),
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChainError::Io(ref err) => write!(f, "failed to read chain file: {}", err),
+            ChainError::Json(ref err) => write!(f, "invalid chain file: {}", err),
+            ChainError::UnsupportedTransactionType {
+                chain,
+                transaction_type,
+            } => write!(
+                f,
+                "chain {} does not accept transactions of type {}",
+                chain,
+                transaction_type.unwrap_or(0)
+            ),
+            ChainError::Signature(ref err) => write!(f, "failed to sign: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+impl From<io::Error> for ChainError {
+    fn from(error: io::Error) -> Self {
+        ChainError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ChainError {
+    fn from(error: serde_json::Error) -> Self {
+        ChainError::Json(error)
+    }
+}
+
+impl From<Error> for ChainError {
+    fn from(error: Error) -> Self {
+        ChainError::Signature(error)
+    }
+}
+
+/// Something that does not prevent signing but that the caller may want to know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainWarning {
+    /// The chain is not in the registry, so nothing could be checked.
+    UnknownChain(u64),
+}
+
+impl fmt::Display for ChainWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChainWarning::UnknownChain(chain) => write!(f, "chain {} is not known", chain),
+        }
+    }
+}
+
+/// Known chains by chain ID. [`ChainRegistry::new`] holds the well-known chains, which
+/// can be extended or overridden from a JSON file.
+///
+/// ```
+/// use ethereum_tx_sign::{ChainError, ChainRegistry, FeeMarketTransaction};
+///
+/// let registry = ChainRegistry::new();
+/// assert_eq!("ETC", registry.get(61).unwrap().native_symbol);
+///
+/// // Ethereum Classic never adopted EIP-1559
+/// let tx = FeeMarketTransaction { chain: 61, gas: 21000, ..Default::default() };
+/// match registry.check(&tx) {
+///     Err(ChainError::UnsupportedTransactionType { .. }) => (),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainRegistry {
+    chains: BTreeMap<u64, Chain>,
+}
+
+impl ChainRegistry {
+    /// A registry with the built-in chains.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .extend_from_json(BUILT_IN_CHAINS)
+            .expect("built-in chains are valid");
+        registry
+    }
+
+    pub fn empty() -> Self {
+        ChainRegistry {
+            chains: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, chain: u64) -> Option<&Chain> {
+        self.chains.get(&chain)
+    }
+
+    pub fn chains(&self) -> impl Iterator<Item = &Chain> {
+        self.chains.values()
+    }
+
+    /// Add a chain, replacing any chain with the same ID.
+    pub fn insert(&mut self, chain: Chain) -> Option<Chain> {
+        self.chains.insert(chain.id, chain)
+    }
+
+    /// Add the chains in a JSON array such as
+    /// `[{"chainId": 1, "name": "Ethereum Mainnet", "nativeSymbol": "ETH", "transactionTypes": [0, 1, 2], "hardForks": ["london"]}]`.
+    /// Chains that are already known are replaced.
+    pub fn extend_from_json(&mut self, json: &str) -> Result<(), ChainError> {
+        let chains: Vec<Chain> = serde_json::from_str(json)?;
+        for chain in chains {
+            self.insert(chain);
+        }
+        Ok(())
+    }
+
+    /// Add the chains from a JSON file in the format of
+    /// [`extend_from_json`](ChainRegistry::extend_from_json).
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<(), ChainError> {
+        self.extend_from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Check that the transaction's chain accepts its type. Unknown chains are
+    /// allowed with a warning.
+    pub fn check<T: Transaction>(&self, tx: &T) -> Result<Vec<ChainWarning>, ChainError> {
+        let chain = match self.get(tx.chain()) {
+            Some(chain) => chain,
+            None => return Ok(vec![ChainWarning::UnknownChain(tx.chain())]),
+        };
+        if !chain.supports(T::transaction_type()) {
+            return Err(ChainError::UnsupportedTransactionType {
+                chain: chain.id,
+                transaction_type: T::transaction_type(),
+            });
+        }
+        Ok(vec![])
+    }
+
+    /// [`check`](ChainRegistry::check) the transaction and sign it. Returns the signed
+    /// transaction and any warnings.
+    pub fn sign<T: Transaction, K: ToPrivateKey + ?Sized>(
+        &self,
+        tx: &T,
+        private_key: &K,
+    ) -> Result<(Vec<u8>, Vec<ChainWarning>), ChainError> {
+        let warnings = self.check(tx)?;
+        let ecdsa = tx.ecdsa(private_key)?;
+        Ok((tx.sign(&ecdsa), warnings))
+    }
+}
+
+impl Default for ChainRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chains::{Chain, ChainError, ChainRegistry, ChainWarning};
+    use std::env;
+    use std::fs;
+    use {AccessListTransaction, FeeMarketTransaction, LegacyTransaction};
+
+    #[test]
+    fn test_built_in_chains() {
+        let registry = ChainRegistry::new();
+        let mainnet = registry.get(1).unwrap();
+        assert_eq!("Ethereum Mainnet", mainnet.name);
+        assert!(mainnet.supports(Some(2)));
+        assert!(mainnet.has_fork("London"));
+        assert!(registry.get(10).unwrap().supports(Some(0x7e)));
+        assert!(registry.chains().all(|chain| chain.supports(None)));
+    }
+
+    #[test]
+    fn test_check() {
+        let registry = ChainRegistry::new();
+        let tx = AccessListTransaction {
+            chain: 30,
+            ..Default::default()
+        };
+        match registry.check(&tx) {
+            Err(ChainError::UnsupportedTransactionType {
+                chain: 30,
+                transaction_type: Some(1),
+            }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        let legacy = LegacyTransaction {
+            chain: 30,
+            ..Default::default()
+        };
+        assert_eq!(0, registry.check(&legacy).unwrap().len());
+
+        let unknown = FeeMarketTransaction {
+            chain: 123_456_789,
+            ..Default::default()
+        };
+        let (signed, warnings) = registry.sign(&unknown, &[0x35; 32]).unwrap();
+        assert_eq!(0x02, signed[0]);
+        assert_eq!(vec![ChainWarning::UnknownChain(123_456_789)], warnings);
+    }
+
+    #[test]
+    fn test_load() {
+        let path = env::temp_dir().join(format!("chains-test-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"[
+                {"chainId": 123456789, "name": "Devnet", "nativeSymbol": "DEV", "transactionTypes": [0]},
+                {"chainId": 61, "name": "Ethereum Classic", "nativeSymbol": "ETC", "transactionTypes": [0, 1, 2]}
+            ]"#,
+        )
+        .unwrap();
+
+        let mut registry = ChainRegistry::new();
+        registry.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            Some(&Chain {
+                id: 123_456_789,
+                name: "Devnet".into(),
+                native_symbol: "DEV".into(),
+                transaction_types: vec![0],
+                hard_forks: vec![],
+            }),
+            registry.get(123_456_789)
+        );
+        assert!(registry.get(61).unwrap().supports(Some(2)));
+        assert!(registry.get(1).is_some());
+
+        match registry.load(&path) {
+            Err(ChainError::Io(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match registry.extend_from_json("{}") {
+            Err(ChainError::Json(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Runner for the `TransactionTests` of [ethereum/tests](https://github.com/ethereum/tests).
+//!
+//! Fixtures are read from the directory in `ETHEREUM_TESTS_TRANSACTION_TESTS`, usually the
+//! `TransactionTests` directory of a checkout, which CI fetches. Otherwise they are read from
+//! `./test/transaction_tests`, which has mainnet transactions, with their hashes and senders
+//! from the chain, and variants of them that the rules reject, in the same format. Each
+//! fixture gives the transaction bytes and, per fork, either the hash, sender and intrinsic
+//! gas or the exception it must be rejected with.
+//!
+//! Only forks whose rules the crate implements are checked; the others are counted as skipped.
+//! A rejected transaction passes only if the reason matches the fixture's exception. Valid
+//! transactions that the crate cannot represent (other transaction types, pre-EIP-155
+//! signatures, or values over 128 bits) are skipped rather than failed, as are rejections with
+//! an exception the crate does not check.
+use rlp::DecoderError;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use {DecodeError, Transaction, TypedTransaction, ValidationError};
+
+/// Forks with the intrinsic gas and initcode rules of [`validate`](::FeeMarketTransaction::validate).
+const FORKS: [&str; 2] = ["Shanghai", "Cancun"];
+/// Chain ID the fixtures are signed for.
+const CHAIN_ID: u64 = 1;
+
+#[derive(Debug)]
+enum Outcome {
+    Pass,
+    Fail(String),
+    Skip(&'static str),
+}
+
+/// The kind of problem an exception name in the fixtures stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rejection {
+    Encoding,
+    TransactionType,
+    Signature,
+    ChainId,
+    Nonce,
+    GasLimit,
+    IntrinsicGas,
+    PriorityFeeAboveMaxFee,
+    Initcode,
+}
+
+/// A rejection and its description.
+type Reason = (Rejection, String);
+
+/// Which rejection an exception name means, if it is one the crate checks. Names are compared
+/// without case and separators, so both `TR_IntrinsicGas` and
+/// `TransactionException.INTRINSIC_GAS_TOO_LOW` are recognised.
+fn expected_rejection(exception: &str) -> Option<Rejection> {
+    let name: String = exception
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let rejection = if name.contains("rlp") || name.contains("leadingzero") {
+        Rejection::Encoding
+    } else if name.contains("typenotsupported") {
+        Rejection::TransactionType
+    } else if name.contains("chainid") {
+        Rejection::ChainId
+    } else if name.contains("signature") || name.contains("vrs") {
+        Rejection::Signature
+    } else if name.contains("nonce") {
+        Rejection::Nonce
+    } else if name.contains("intrinsicgas") {
+        Rejection::IntrinsicGas
+    } else if name.contains("tipgtfeecap") || name.contains("prioritygreaterthanmaxfee") {
+        Rejection::PriorityFeeAboveMaxFee
+    } else if name.contains("initcode") {
+        Rejection::Initcode
+    } else if name.contains("gaslimit") {
+        Rejection::GasLimit
+    } else {
+        return None;
+    };
+    Some(rejection)
+}
+
+fn decode_rejection(err: &DecodeError) -> Rejection {
+    match *err {
+        DecodeError::UnknownType(_) | DecodeError::UnexpectedType(_) => Rejection::TransactionType,
+        DecodeError::InvalidSignature | DecodeError::UnprotectedLegacy => Rejection::Signature,
+        DecodeError::Rlp(_)
+        | DecodeError::Empty
+        | DecodeError::InvalidLength(_)
+        | DecodeError::TrailingBytes => Rejection::Encoding,
+    }
+}
+
+/// Transaction pool rules are not part of the fixtures' expectations.
+fn validation_rejection(err: &ValidationError) -> Option<Rejection> {
+    match *err {
+        ValidationError::ZeroChainId | ValidationError::ChainIdOverflow(_) => {
+            Some(Rejection::ChainId)
+        }
+        ValidationError::NonceOverflow(_) => Some(Rejection::Nonce),
+        ValidationError::GasLimitOverflow(_) => Some(Rejection::GasLimit),
+        ValidationError::IntrinsicGasTooLow { .. } => Some(Rejection::IntrinsicGas),
+        ValidationError::PriorityFeeAboveMaxFee { .. } => Some(Rejection::PriorityFeeAboveMaxFee),
+        ValidationError::InitcodeTooLarge(_) => Some(Rejection::Initcode),
+        ValidationError::TransactionTooLarge(_)
+        | ValidationError::DuplicateAccessListAddress(_)
+        | ValidationError::DuplicateStorageKey { .. } => None,
+    }
+}
+
+/// What the crate makes of a transaction that decoded: hash, sender and intrinsic gas, or
+/// every reason it is invalid.
+fn check(tx: &TypedTransaction) -> Result<([u8; 32], [u8; 20], u128), Vec<Reason>> {
+    let (chain, intrinsic_gas, validation) = match *tx {
+        TypedTransaction::Legacy(ref signed) => (
+            signed.transaction.chain(),
+            signed.transaction.intrinsic_gas(),
+            signed.transaction.validate(),
+        ),
+        TypedTransaction::AccessList(ref signed) => (
+            signed.transaction.chain(),
+            signed.transaction.intrinsic_gas(),
+            signed.transaction.validate(),
+        ),
+        TypedTransaction::FeeMarket(ref signed) => (
+            signed.transaction.chain(),
+            signed.transaction.intrinsic_gas(),
+            signed.transaction.validate(),
+        ),
+        TypedTransaction::Deposit(_) => {
+            return Err(vec![(
+                Rejection::TransactionType,
+                "deposit transaction".into(),
+            )])
+        }
+    };
+
+    let mut errors = vec![];
+    if chain != CHAIN_ID {
+        errors.push((Rejection::ChainId, format!("chain ID {}", chain)));
+    }
+    for err in validation.err().unwrap_or_default() {
+        if let Some(rejection) = validation_rejection(&err) {
+            errors.push((rejection, err.to_string()));
+        }
+    }
+    let sender = tx.sender();
+    if let Err(ref err) = sender {
+        errors.push((Rejection::Signature, format!("{:?}", err)));
+    }
+    match sender {
+        Ok(sender) if errors.is_empty() => Ok((tx.hash(), sender, intrinsic_gas)),
+        _ => Err(errors),
+    }
+}
+
+fn run_fork(tx_bytes: &str, expected: &Value) -> Outcome {
+    let exception = expected.get("exception").and_then(Value::as_str);
+    let bytes = match hex::decode(tx_bytes.trim_start_matches("0x")) {
+        Ok(bytes) => bytes,
+        Err(err) => return rejected(exception, &[(Rejection::Encoding, err.to_string())], None),
+    };
+    let tx = match TypedTransaction::decode(&bytes) {
+        Ok(tx) => tx,
+        Err(err) => {
+            let reasons = [(decode_rejection(&err), err.to_string())];
+            return rejected(exception, &reasons, unsupported(&err));
+        }
+    };
+    let (hash, sender, intrinsic_gas) = match check(&tx) {
+        Ok(checked) => checked,
+        Err(reasons) => return rejected(exception, &reasons, None),
+    };
+    if let Some(exception) = exception {
+        return Outcome::Fail(format!("accepted, expected {}", exception));
+    }
+
+    let hash = format!("0x{}", hex::encode(hash));
+    if !expected["hash"]
+        .as_str()
+        .is_some_and(|h| h.eq_ignore_ascii_case(&hash))
+    {
+        return Outcome::Fail(format!("hash {} expected {}", hash, expected["hash"]));
+    }
+    let sender = format!("0x{}", hex::encode(sender));
+    if !expected["sender"]
+        .as_str()
+        .is_some_and(|s| s.eq_ignore_ascii_case(&sender))
+    {
+        return Outcome::Fail(format!("sender {} expected {}", sender, expected["sender"]));
+    }
+    let expected_gas = expected["intrinsicGas"]
+        .as_str()
+        .and_then(|gas| u128::from_str_radix(gas.trim_start_matches("0x"), 16).ok());
+    if expected_gas.is_some_and(|gas| gas != intrinsic_gas) {
+        return Outcome::Fail(format!(
+            "intrinsic gas {} expected {}",
+            intrinsic_gas, expected["intrinsicGas"]
+        ));
+    }
+    Outcome::Pass
+}
+
+/// Whether rejecting a transaction for `reasons` is what the fixture expects. A transaction the
+/// crate cannot represent is skipped unless it was rejected for the expected reason anyway.
+fn rejected(
+    exception: Option<&str>,
+    reasons: &[Reason],
+    unsupported: Option<&'static str>,
+) -> Outcome {
+    let found = || {
+        let reasons: Vec<&str> = reasons.iter().map(|(_, reason)| reason.as_str()).collect();
+        reasons.join(", ")
+    };
+    let exception = match exception {
+        Some(exception) => exception,
+        None => {
+            return match unsupported {
+                Some(reason) => Outcome::Skip(reason),
+                None => Outcome::Fail(format!("rejected: {}", found())),
+            }
+        }
+    };
+    let expected = match expected_rejection(exception) {
+        Some(expected) => expected,
+        None => return Outcome::Skip("exception the crate does not check"),
+    };
+    if reasons.iter().any(|&(rejection, _)| rejection == expected) {
+        Outcome::Pass
+    } else if let Some(reason) = unsupported {
+        Outcome::Skip(reason)
+    } else {
+        Outcome::Fail(format!("rejected: {}, expected {}", found(), exception))
+    }
+}
+
+/// Why a valid transaction cannot be decoded by the crate, if it is a known limitation.
+fn unsupported(err: &DecodeError) -> Option<&'static str> {
+    match *err {
+        DecodeError::UnknownType(_) => Some("unsupported transaction type"),
+        DecodeError::UnprotectedLegacy => Some("pre-EIP-155 signature"),
+        DecodeError::Rlp(DecoderError::RlpIsTooBig) => Some("value over 128 bits"),
+        _ => None,
+    }
+}
+
+fn json_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|_| panic!("Failed to read: {:?}", dir));
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            json_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "json") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_transaction_tests() {
+    let dir = env::var("ETHEREUM_TESTS_TRANSACTION_TESTS")
+        .unwrap_or_else(|_| "./test/transaction_tests".into());
+    let mut files = vec![];
+    json_files(Path::new(&dir), &mut files);
+    files.sort();
+
+    let (mut passed, mut skipped, mut failures) = (0, BTreeMap::<String, usize>::new(), vec![]);
+    for path in files {
+        let contents = fs::read_to_string(&path).unwrap();
+        let fixtures: Map<String, Value> = serde_json::from_str(&contents).unwrap();
+        for (name, fixture) in fixtures.iter() {
+            let (tx_bytes, results) =
+                match (fixture["txbytes"].as_str(), fixture["result"].as_object()) {
+                    (Some(tx_bytes), Some(results)) => (tx_bytes, results),
+                    _ => {
+                        *skipped.entry("unrecognised format".into()).or_insert(0) += 1;
+                        continue;
+                    }
+                };
+            for (fork, expected) in results.iter() {
+                if !FORKS.contains(&fork.as_str()) {
+                    *skipped.entry(format!("{} rules", fork)).or_insert(0) += 1;
+                    continue;
+                }
+                match run_fork(tx_bytes, expected) {
+                    Outcome::Pass => passed += 1,
+                    Outcome::Skip(reason) => *skipped.entry(reason.into()).or_insert(0) += 1,
+                    Outcome::Fail(reason) => {
+                        failures.push(format!("{:?} {} {}: {}", path, name, fork, reason))
+                    }
+                }
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} passed, {} failed, skipped: {:?}\n{}",
+        passed,
+        failures.len(),
+        skipped,
+        failures.join("\n")
+    );
+    assert!(passed > 0, "no fixtures in {}, skipped: {:?}", dir, skipped);
+}
+
+#[test]
+fn test_wrong_expectations_fail() {
+    let fixtures: Value =
+        serde_json::from_str(&fs::read_to_string("./test/transaction_tests/samples.json").unwrap())
+            .unwrap();
+    let valid = fixtures["legacyMainnet"]["txbytes"].as_str().unwrap();
+    let invalid = fixtures["highS"]["txbytes"].as_str().unwrap();
+
+    let exception = serde_json::json!({"exception": "TR_SignatureS"});
+    match run_fork(valid, &exception) {
+        Outcome::Fail(_) => (),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    let mut expected = fixtures["legacyMainnet"]["result"]["Cancun"].clone();
+    match run_fork(invalid, &expected) {
+        Outcome::Fail(_) => (),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    let intrinsic_gas = serde_json::json!({"exception": "TR_IntrinsicGas"});
+    match run_fork(invalid, &intrinsic_gas) {
+        Outcome::Fail(_) => (),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+    expected["intrinsicGas"] = "0x5209".into();
+    match run_fork(valid, &expected) {
+        Outcome::Fail(_) => (),
+        r => panic!("Unexpected result: {:?}", r),
+    }
+}
@@ -0,0 +1,472 @@
+use rlp::{Decodable, DecoderError, Rlp};
+use std::fmt;
+
+use deposit::{DepositTransaction, DEPOSIT_TX_TYPE};
+use {
+    Access, AccessList, AccessListTransaction, EcdsaSig, Error, FeeMarketTransaction,
+    LegacyTransaction, SignedTransaction, EIP_1559_TYPE, EIP_2930_TYPE,
+};
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Rlp(DecoderError),
+    /// There are no bytes to decode.
+    Empty,
+    /// The EIP-2718 transaction type is not supported.
+    UnknownType(u8),
+    /// The bytes hold a different transaction type than the one requested.
+    UnexpectedType(u8),
+    /// A fixed size field, such as an address, has the wrong length.
+    InvalidLength(&'static str),
+    /// `r` or `s` is not a canonical scalar, or `v` is not valid for the transaction type.
+    InvalidSignature,
+    /// A legacy transaction signed without a chain ID, which cannot be represented.
+    UnprotectedLegacy,
+    /// The transaction is followed by more bytes.
+    TrailingBytes,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Rlp(ref err) => err.fmt(f),
+            DecodeError::Empty => write!(f, "no transaction bytes"),
+            DecodeError::UnknownType(found) => write!(f, "unknown transaction type {}", found),
+            DecodeError::UnexpectedType(found) => {
+                write!(f, "unexpected transaction type {}", found)
+            }
+            DecodeError::InvalidLength(field) => write!(f, "invalid length of `{}`", field),
+            DecodeError::InvalidSignature => write!(f, "invalid signature"),
+            DecodeError::UnprotectedLegacy => {
+                write!(f, "legacy transaction is not replay protected")
+            }
+            DecodeError::TrailingBytes => write!(f, "trailing bytes after transaction"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecoderError> for DecodeError {
+    fn from(error: DecoderError) -> Self {
+        DecodeError::Rlp(error)
+    }
+}
+
+/// Any transaction that can appear in a block, decoded from its
+/// [EIP-2718](https://eips.ethereum.org/EIPS/eip-2718) encoding.
+///
+/// ```
+/// use ethereum_tx_sign::{LegacyTransaction, Transaction, TypedTransaction};
+///
+/// let tx = LegacyTransaction { chain: 1, gas: 21000, ..Default::default() };
+/// let tx_bytes = tx.sign(&tx.ecdsa(&vec![0x35; 32]).unwrap());
+///
+/// match TypedTransaction::decode(&tx_bytes).unwrap() {
+///     TypedTransaction::Legacy(signed) => assert_eq!(tx, signed.transaction),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypedTransaction {
+    Legacy(SignedTransaction<LegacyTransaction>),
+    AccessList(SignedTransaction<AccessListTransaction>),
+    FeeMarket(SignedTransaction<FeeMarketTransaction>),
+    /// OP Stack deposit, which is not signed.
+    Deposit(DepositTransaction),
+}
+
+impl TypedTransaction {
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let tx = match bytes.first() {
+            None => return Err(DecodeError::Empty),
+            Some(&b) if b >= 0xc0 => TypedTransaction::Legacy(LegacyTransaction::decode(bytes)?),
+            Some(&EIP_2930_TYPE) => {
+                TypedTransaction::AccessList(AccessListTransaction::decode(bytes)?)
+            }
+            Some(&EIP_1559_TYPE) => {
+                TypedTransaction::FeeMarket(FeeMarketTransaction::decode(bytes)?)
+            }
+            Some(&DEPOSIT_TX_TYPE) => TypedTransaction::Deposit(DepositTransaction::decode(bytes)?),
+            Some(&found) => return Err(DecodeError::UnknownType(found)),
+        };
+        Ok(tx)
+    }
+
+    /// The EIP-2718 transaction type, `None` for legacy transactions.
+    pub fn transaction_type(&self) -> Option<u8> {
+        match *self {
+            TypedTransaction::Legacy(_) => None,
+            TypedTransaction::AccessList(_) => Some(EIP_2930_TYPE),
+            TypedTransaction::FeeMarket(_) => Some(EIP_1559_TYPE),
+            TypedTransaction::Deposit(_) => Some(DEPOSIT_TX_TYPE),
+        }
+    }
+
+    /// Hash that identifies the transaction on chain.
+    pub fn hash(&self) -> [u8; 32] {
+        match *self {
+            TypedTransaction::Legacy(ref signed) => signed.hash(),
+            TypedTransaction::AccessList(ref signed) => signed.hash(),
+            TypedTransaction::FeeMarket(ref signed) => signed.hash(),
+            TypedTransaction::Deposit(ref deposit) => deposit.hash(),
+        }
+    }
+
+    /// Address of the account that sent the transaction. For deposits it is the `from` field.
+    pub fn sender(&self) -> Result<[u8; 20], Error> {
+        match *self {
+            TypedTransaction::Legacy(ref signed) => signed.sender(),
+            TypedTransaction::AccessList(ref signed) => signed.sender(),
+            TypedTransaction::FeeMarket(ref signed) => signed.sender(),
+            TypedTransaction::Deposit(ref deposit) => Ok(deposit.from),
+        }
+    }
+}
+
+impl LegacyTransaction {
+    /// Decode a signed transaction as returned by [`sign`](::Transaction::sign).
+    pub fn decode(bytes: &[u8]) -> Result<SignedTransaction<Self>, DecodeError> {
+        if let Some(&b) = bytes.first().filter(|b| **b < 0xc0) {
+            return Err(DecodeError::UnexpectedType(b));
+        }
+
+        let rlp = decode_list(bytes, 9)?;
+        let v: u64 = rlp.val_at(6)?;
+        match v {
+            27 | 28 => return Err(DecodeError::UnprotectedLegacy),
+            _ if v < 35 => return Err(DecodeError::InvalidSignature),
+            _ => (),
+        }
+        let tx = LegacyTransaction {
+            chain: (v - 35) / 2,
+            nonce: rlp.val_at(0)?,
+            gas_price: rlp.val_at(1)?,
+            gas: rlp.val_at(2)?,
+            to: option_address(&rlp, 3, "to")?,
+            value: rlp.val_at(4)?,
+            data: rlp.val_at(5)?,
+        };
+        Ok(SignedTransaction::new(tx, signature(&rlp, 6)?))
+    }
+}
+
+impl AccessListTransaction {
+    /// Decode a signed transaction as returned by [`sign`](::Transaction::sign).
+    pub fn decode(bytes: &[u8]) -> Result<SignedTransaction<Self>, DecodeError> {
+        let rlp = decode_list(typed_payload(bytes, EIP_2930_TYPE)?, 11)?;
+        let tx = AccessListTransaction {
+            chain: rlp.val_at(0)?,
+            nonce: rlp.val_at(1)?,
+            gas_price: rlp.val_at(2)?,
+            gas: rlp.val_at(3)?,
+            to: option_address(&rlp, 4, "to")?,
+            value: rlp.val_at(5)?,
+            data: rlp.val_at(6)?,
+            access_list: rlp.val_at(7)?,
+        };
+        Ok(SignedTransaction::new(tx, typed_signature(&rlp, 8)?))
+    }
+}
+
+impl FeeMarketTransaction {
+    /// Decode a signed transaction as returned by [`sign`](::Transaction::sign).
+    pub fn decode(bytes: &[u8]) -> Result<SignedTransaction<Self>, DecodeError> {
+        let rlp = decode_list(typed_payload(bytes, EIP_1559_TYPE)?, 12)?;
+        let tx = FeeMarketTransaction {
+            chain: rlp.val_at(0)?,
+            nonce: rlp.val_at(1)?,
+            max_priority_fee_per_gas: rlp.val_at(2)?,
+            max_fee_per_gas: rlp.val_at(3)?,
+            gas: rlp.val_at(4)?,
+            to: option_address(&rlp, 5, "to")?,
+            value: rlp.val_at(6)?,
+            data: rlp.val_at(7)?,
+            access_list: rlp.val_at(8)?,
+        };
+        Ok(SignedTransaction::new(tx, typed_signature(&rlp, 9)?))
+    }
+}
+
+impl Decodable for AccessList {
+    fn decode(rlp: &Rlp) -> Result<Self, DecoderError> {
+        if !rlp.is_list() {
+            return Err(DecoderError::RlpExpectedToBeList);
+        }
+        let mut access_list = vec![];
+        for item in rlp.iter() {
+            if item.item_count()? != 2 {
+                return Err(DecoderError::RlpIncorrectListLen);
+            }
+            let address: Vec<u8> = item.val_at(0)?;
+            if address.len() != 20 {
+                return Err(DecoderError::Custom("invalid address length"));
+            }
+            let mut storage_keys = vec![];
+            for key in item.at(1)?.iter() {
+                let key: Vec<u8> = key.as_val()?;
+                if key.len() != 32 {
+                    return Err(DecoderError::Custom("invalid storage key length"));
+                }
+                let mut storage_key = [0u8; 32];
+                storage_key.copy_from_slice(&key);
+                storage_keys.push(storage_key);
+            }
+            let mut access = Access {
+                storage_keys,
+                ..Default::default()
+            };
+            access.address.copy_from_slice(&address);
+            access_list.push(access);
+        }
+        Ok(AccessList(access_list))
+    }
+}
+
+/// The RLP payload of a typed transaction of type `tx_type`.
+fn typed_payload(bytes: &[u8], tx_type: u8) -> Result<&[u8], DecodeError> {
+    match bytes.first() {
+        Some(&b) if b == tx_type => Ok(&bytes[1..]),
+        Some(&found) => Err(DecodeError::UnexpectedType(found)),
+        None => Err(DecodeError::Empty),
+    }
+}
+
+/// The RLP list that makes up all of `bytes`, which must have `count` items.
+pub(crate) fn decode_list(bytes: &[u8], count: usize) -> Result<Rlp<'_>, DecodeError> {
+    if bytes.is_empty() {
+        return Err(DecodeError::Empty);
+    }
+    let rlp = Rlp::new(bytes);
+    if !rlp.is_list() {
+        return Err(DecoderError::RlpExpectedToBeList.into());
+    }
+    if rlp.payload_info()?.total() != bytes.len() {
+        return Err(DecodeError::TrailingBytes);
+    }
+    if rlp.item_count()? != count {
+        return Err(DecoderError::RlpIncorrectListLen.into());
+    }
+    Ok(rlp)
+}
+
+pub(crate) fn address(
+    rlp: &Rlp,
+    index: usize,
+    field: &'static str,
+) -> Result<[u8; 20], DecodeError> {
+    let bytes: Vec<u8> = rlp.val_at(index)?;
+    if bytes.len() != 20 {
+        return Err(DecodeError::InvalidLength(field));
+    }
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&bytes);
+    Ok(address)
+}
+
+/// An address that is empty for contract creations.
+pub(crate) fn option_address(
+    rlp: &Rlp,
+    index: usize,
+    field: &'static str,
+) -> Result<Option<[u8; 20]>, DecodeError> {
+    if rlp.at(index)?.is_empty() {
+        return Ok(None);
+    }
+    address(rlp, index, field).map(Some)
+}
+
+/// The `v`, `r` and `s` items starting at `index`. `r` and `s` are left-padded to 32 bytes,
+/// as [`ecdsa`](::Transaction::ecdsa) returns them.
+fn signature(rlp: &Rlp, index: usize) -> Result<EcdsaSig, DecodeError> {
+    let v: u64 = rlp.val_at(index)?;
+    let r = scalar(rlp, index + 1)?;
+    let s = scalar(rlp, index + 2)?;
+    // EIP-2 rejects transactions with s in the upper half of the curve order
+    if s[..] > SECP256K1_HALF_ORDER[..] {
+        return Err(DecodeError::InvalidSignature);
+    }
+    Ok(EcdsaSig { v, r, s })
+}
+
+/// Half the order of the secp256k1 curve, the largest `s` allowed by
+/// [EIP-2](https://eips.ethereum.org/EIPS/eip-2).
+const SECP256K1_HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// Like [`signature`], but `v` must be the `yParity` of a typed transaction.
+fn typed_signature(rlp: &Rlp, index: usize) -> Result<EcdsaSig, DecodeError> {
+    let signature = signature(rlp, index)?;
+    if signature.v > 1 {
+        return Err(DecodeError::InvalidSignature);
+    }
+    Ok(signature)
+}
+
+fn scalar(rlp: &Rlp, index: usize) -> Result<Vec<u8>, DecodeError> {
+    let bytes: Vec<u8> = rlp.val_at(index)?;
+    if bytes.is_empty() || bytes.len() > 32 || bytes[0] == 0 {
+        return Err(DecodeError::InvalidSignature);
+    }
+    let mut scalar = vec![0u8; 32 - bytes.len()];
+    scalar.extend_from_slice(&bytes);
+    Ok(scalar)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+    use std::fs;
+
+    use {AccessListTransaction, DecodeError, EcdsaSig, FeeMarketTransaction, LegacyTransaction};
+    use {Transaction, TypedTransaction};
+
+    const SECP256K1_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36,
+        0x41, 0x41,
+    ];
+
+    /// Decodes `output.bytes` of every fixture and checks it against `input` and `output`.
+    #[test]
+    fn test_decode_fixtures() {
+        let mut paths: Vec<_> = fs::read_dir("./test")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|e| e == "json"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let values: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let bytes = hex::decode(&values["output"]["bytes"].as_str().unwrap()[2..]).unwrap();
+            let signature: EcdsaSig = serde_json::from_value(values["output"].clone()).unwrap();
+            let input = values["input"].clone();
+
+            // this fixture encodes `r` with a leading zero, which nodes reject as well
+            if path.ends_with("random_legacy_leading_zeroes_003.json") {
+                match TypedTransaction::decode(&bytes) {
+                    Err(DecodeError::InvalidSignature) => continue,
+                    r => panic!("Unexpected result: {:?}", r),
+                }
+            }
+
+            let decoded = TypedTransaction::decode(&bytes).unwrap();
+            let (encoded, decoded_signature) = match decoded {
+                TypedTransaction::Legacy(ref signed) => {
+                    let tx: LegacyTransaction = serde_json::from_value(input).unwrap();
+                    assert_eq!(tx, signed.transaction, "{:?}", path);
+                    (signed.encode(), signed.signature.clone())
+                }
+                TypedTransaction::AccessList(ref signed) => {
+                    let tx: AccessListTransaction = serde_json::from_value(input).unwrap();
+                    assert_eq!(tx, signed.transaction, "{:?}", path);
+                    (signed.encode(), signed.signature.clone())
+                }
+                TypedTransaction::FeeMarket(ref signed) => {
+                    let tx: FeeMarketTransaction = serde_json::from_value(input).unwrap();
+                    assert_eq!(tx, signed.transaction, "{:?}", path);
+                    (signed.encode(), signed.signature.clone())
+                }
+                TypedTransaction::Deposit(_) => panic!("{:?} decoded as a deposit", path),
+            };
+            assert_eq!(bytes, encoded, "{:?}", path);
+            assert_eq!(signature.v, decoded_signature.v, "{:?}", path);
+            assert_eq!(padded(&signature.r), decoded_signature.r, "{:?}", path);
+            assert_eq!(padded(&signature.s), decoded_signature.s, "{:?}", path);
+        }
+    }
+
+    fn padded(scalar: &[u8]) -> Vec<u8> {
+        let mut padded = vec![0u8; 32 - scalar.len()];
+        padded.extend_from_slice(scalar);
+        padded
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let tx = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        let bytes = tx.sign(&tx.ecdsa(&[0x35; 32]).unwrap());
+
+        match LegacyTransaction::decode(&bytes) {
+            Err(DecodeError::UnexpectedType(0x02)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match AccessListTransaction::decode(&bytes) {
+            Err(DecodeError::UnexpectedType(0x02)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match TypedTransaction::decode(&[0x03, 0xc0]) {
+            Err(DecodeError::UnknownType(0x03)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match TypedTransaction::decode(&[]) {
+            Err(DecodeError::Empty) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match TypedTransaction::decode(&bytes[..bytes.len() - 1]) {
+            Err(DecodeError::Rlp(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // yParity of 27 is only valid for legacy transactions
+        let mut bad_parity = bytes.clone();
+        let v_index = bytes.len() - 67;
+        assert!(bytes[v_index] == 0x80 || bytes[v_index] == 0x01);
+        bad_parity[v_index] = 27;
+        match FeeMarketTransaction::decode(&bad_parity) {
+            Err(DecodeError::InvalidSignature) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // v of 27 is a legacy transaction without a chain ID
+        let legacy = LegacyTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        let mut ecdsa = legacy.ecdsa(&[0x35; 32]).unwrap();
+        ecdsa.v -= 10;
+        match LegacyTransaction::decode(&legacy.sign(&ecdsa)) {
+            Err(DecodeError::UnprotectedLegacy) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // the same signature with s replaced by n - s recovers to the same account, but
+        // EIP-2 only lets transactions use the low s
+        let ecdsa = legacy.ecdsa(&[0x35; 32]).unwrap();
+        let mut s = [0u8; 32];
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let d = SECP256K1_ORDER[i] as i16 - ecdsa.s[i] as i16 - borrow;
+            borrow = (d < 0) as i16;
+            s[i] = (d + 256 * borrow) as u8;
+        }
+        // flip the y-parity, which is 0 for a `v` of 35 + 2 * chain
+        let v = if ecdsa.v % 2 == 1 {
+            ecdsa.v + 1
+        } else {
+            ecdsa.v - 1
+        };
+        let high_s = EcdsaSig {
+            v,
+            r: ecdsa.r.clone(),
+            s: s.to_vec(),
+        };
+        assert_eq!(
+            legacy.recover(&ecdsa).unwrap(),
+            legacy.recover(&high_s).unwrap()
+        );
+        match LegacyTransaction::decode(&legacy.sign(&high_s)) {
+            Err(DecodeError::InvalidSignature) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use rlp::Rlp;
+use serde::de::Error as DeError;
+use serde::{Deserializer, Serialize, Serializer};
+
+use decode::{address, decode_list, option_address, DecodeError};
+use rpc;
+use {keccak256_hash, list_header_len, put_list_header, RlpField};
+
+/// Transaction type of OP Stack deposit transactions.
+pub const DEPOSIT_TX_TYPE: u8 = 0x7e;
+
+/// [OP Stack](https://specs.optimism.io/protocol/deposits.html) deposit transaction.
+///
+/// Deposits are derived by the rollup node from events on L1 and carry no signature.
+/// This type deliberately does not implement [`Transaction`](::Transaction), so it cannot
+/// be passed to `ecdsa` or `sign`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositTransaction {
+    /// Hash that uniquely identifies the source of the deposit
+    #[serde(rename = "sourceHash")]
+    #[serde(serialize_with = "::slice_u8_serialize")]
+    #[serde(deserialize_with = "array_u8_32_deserialize")]
+    pub source_hash: [u8; 32],
+    /// Sender on L2
+    #[serde(serialize_with = "::array_u8_20_serialize")]
+    #[serde(deserialize_with = "::array_u8_20_deserialize")]
+    pub from: [u8; 20],
+    /// Recipient (None when contract creation)
+    #[serde(serialize_with = "::option_array_u8_serialize")]
+    #[serde(deserialize_with = "::option_array_u8_deserialize")]
+    #[serde(default)]
+    pub to: Option<[u8; 20]>,
+    /// ETH minted on L2, locked on L1
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    #[serde(default)]
+    pub mint: u128,
+    /// Transfered value
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub value: u128,
+    /// Gas limit
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub gas: u128,
+    /// Whether the transaction is exempt from the L2 gas limit (only before Regolith)
+    #[serde(rename = "isSystemTx")]
+    #[serde(default)]
+    pub is_system_tx: bool,
+    /// Input data
+    #[serde(serialize_with = "::slice_u8_serialize")]
+    #[serde(deserialize_with = "::slice_u8_deserialize")]
+    #[serde(default)]
+    #[serde(alias = "input")]
+    pub data: Vec<u8>,
+}
+
+impl DepositTransaction {
+    /// The [EIP-2718](https://eips.ethereum.org/EIPS/eip-2718) encoding, as it appears in
+    /// blocks.
+    pub fn encode(&self) -> Vec<u8> {
+        let is_system_tx = u64::from(self.is_system_tx);
+        let payload_len = self.source_hash.rlp_len()
+            + self.from.rlp_len()
+            + self.to.rlp_len()
+            + self.mint.rlp_len()
+            + self.value.rlp_len()
+            + self.gas.rlp_len()
+            + is_system_tx.rlp_len()
+            + self.data.rlp_len();
+
+        let mut bytes = Vec::with_capacity(1 + list_header_len(payload_len) + payload_len);
+        bytes.push(DEPOSIT_TX_TYPE);
+        put_list_header(payload_len, &mut bytes);
+        self.source_hash.rlp_encode(&mut bytes);
+        self.from.rlp_encode(&mut bytes);
+        self.to.rlp_encode(&mut bytes);
+        self.mint.rlp_encode(&mut bytes);
+        self.value.rlp_encode(&mut bytes);
+        self.gas.rlp_encode(&mut bytes);
+        is_system_tx.rlp_encode(&mut bytes);
+        self.data.rlp_encode(&mut bytes);
+        bytes
+    }
+
+    /// Hash that identifies the transaction on L2.
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256_hash(&self.encode())
+    }
+
+    /// Decode the EIP-2718 encoding of a deposit transaction.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match bytes.first() {
+            Some(&DEPOSIT_TX_TYPE) => (),
+            Some(&found) => return Err(DecodeError::UnexpectedType(found)),
+            None => return Err(DecodeError::Empty),
+        }
+
+        let rlp: Rlp = decode_list(&bytes[1..], 8)?;
+        let source_hash: Vec<u8> = rlp.val_at(0)?;
+        if source_hash.len() != 32 {
+            return Err(DecodeError::InvalidLength("sourceHash"));
+        }
+        let mut tx = DepositTransaction {
+            from: address(&rlp, 1, "from")?,
+            to: option_address(&rlp, 2, "to")?,
+            mint: rlp.val_at(3)?,
+            value: rlp.val_at(4)?,
+            gas: rlp.val_at(5)?,
+            is_system_tx: rlp.val_at(6)?,
+            data: rlp.val_at(7)?,
+            ..Default::default()
+        };
+        tx.source_hash.copy_from_slice(&source_hash);
+        Ok(tx)
+    }
+}
+
+fn quantity_serialize<S: Serializer>(quantity: &u128, s: S) -> Result<S::Ok, S::Error> {
+    // as `u128_quantity_deserialize` expects
+    if s.is_human_readable() {
+        s.serialize_str(&rpc::quantity(*quantity))
+    } else {
+        quantity.serialize(s)
+    }
+}
+
+fn array_u8_32_deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<[u8; 32], D::Error> {
+    let bytes = ::slice_u8_deserialize(d)?;
+    let mut array = [0u8; 32];
+    if bytes.len() != array.len() {
+        return Err(D::Error::invalid_length(
+            bytes.len(),
+            &"a hex string of length 32",
+        ));
+    }
+    array.copy_from_slice(&bytes);
+    Ok(array)
+}
+
+#[cfg(test)]
+mod test {
+    use deposit::{DepositTransaction, DEPOSIT_TX_TYPE};
+    use {keccak256_hash, DecodeError, TypedTransaction};
+
+    fn deposit() -> DepositTransaction {
+        DepositTransaction {
+            source_hash: [0x5c; 32],
+            from: [0xde; 20],
+            to: Some([0x42; 20]),
+            mint: 10u128.pow(17),
+            value: 10u128.pow(17),
+            gas: 100_000,
+            is_system_tx: false,
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let tx = deposit();
+        let bytes = tx.encode();
+        assert_eq!(DEPOSIT_TX_TYPE, bytes[0]);
+        assert_eq!(keccak256_hash(&bytes), tx.hash());
+        assert_eq!(tx, DepositTransaction::decode(&bytes).unwrap());
+
+        let creation = DepositTransaction {
+            to: None,
+            is_system_tx: true,
+            data: vec![0x60, 0x80],
+            ..tx
+        };
+        let decoded = TypedTransaction::decode(&creation.encode()).unwrap();
+        assert_eq!(TypedTransaction::Deposit(creation.clone()), decoded);
+        assert_eq!(Some(DEPOSIT_TX_TYPE), decoded.transaction_type());
+        assert_eq!(creation.hash(), decoded.hash());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut bytes = deposit().encode();
+        bytes[0] = 0x02;
+        match DepositTransaction::decode(&bytes) {
+            Err(DecodeError::UnexpectedType(0x02)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        let mut bytes = deposit().encode();
+        bytes.push(0x80);
+        match DepositTransaction::decode(&bytes) {
+            Err(DecodeError::TrailingBytes) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_node_json() {
+        let tx: DepositTransaction = serde_json::from_value(serde_json::json!({
+            "type": "0x7e",
+            "sourceHash": "0x5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c5c",
+            "from": "0xdededededededededededededededededededede",
+            "to": "0x4242424242424242424242424242424242424242",
+            "mint": "0x16345785d8a0000",
+            "value": "0x16345785d8a0000",
+            "gas": "0x186a0",
+            "isSystemTx": false,
+            "input": "0x"
+        }))
+        .unwrap();
+        assert_eq!(deposit(), tx);
+
+        let json = serde_json::to_value(&tx).unwrap();
+        assert_eq!("0x16345785d8a0000", json["mint"]);
+        assert_eq!("0x16345785d8a0000", json["value"]);
+        assert_eq!("0x186a0", json["gas"]);
+        assert_eq!(tx, serde_json::from_value(json).unwrap());
+    }
+
+    #[test]
+    fn test_op_mainnet_deposit() {
+        // an L1 to L2 message relayed in OP Mainnet block 121272573, as the node returns it
+        let tx: DepositTransaction = serde_json::from_value(serde_json::json!({
+            "blockHash": "0xef664d656f841b5ad6a2b527b963f1eb48b97d7889d742f6cbff6950388e24cd",
+            "blockNumber": "0x73a78fd",
+            "depositReceiptVersion": "0x1",
+            "from": "0x36bde71c97b33cc4729cf772ae268934f7ab70b2",
+            "gas": "0xc27a8",
+            "gasPrice": "0x521",
+            "hash": "0x0bf1845c5d7a82ec92365d5027f7310793d53004f3c86aa80965c67bf7e7dc80",
+            "input": "0xd764ad0b000100000000000000000000000000000000000000000000000000000001cf5400000000000000000000000099c9fc46f92e8a1c0dec1b1747d010903e884be100000000000000000000000042000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000007a12000000000000000000000000000000000000000000000000000000000000000c000000000000000000000000000000000000000000000000000000000000000e40166a07a0000000000000000000000000994206dfe8de6ec6920ff4d779b0d950605fb53000000000000000000000000d533a949740bb3306d119cc777fa900ba034cd52000000000000000000000000ca74f404e0c7bfa35b13b511097df966d5a65597000000000000000000000000ca74f404e0c7bfa35b13b511097df966d5a65597000000000000000000000000000000000000000000000216614199391dbba2ba00000000000000000000000000000000000000000000000000000000000000c0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+            "mint": "0x0",
+            "nonce": "0x74060",
+            "r": "0x0",
+            "s": "0x0",
+            "sourceHash": "0x074adb22f2e6ed9bdd31c52eefc1f050e5db56eb85056450bccd79a6649520b3",
+            "to": "0x4200000000000000000000000000000000000007",
+            "transactionIndex": "0x1",
+            "type": "0x7e",
+            "v": "0x0",
+            "value": "0x0"
+        }))
+        .unwrap();
+        let raw = "7ef90239a0074adb22f2e6ed9bdd31c52eefc1f050e5db56eb85056450bccd79a6649520b39436bde71c97b33cc4729cf772ae268934f7ab70b29442000000000000000000000000000000000000078080830c27a880b901e4d764ad0b000100000000000000000000000000000000000000000000000000000001cf5400000000000000000000000099c9fc46f92e8a1c0dec1b1747d010903e884be100000000000000000000000042000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000007a12000000000000000000000000000000000000000000000000000000000000000c000000000000000000000000000000000000000000000000000000000000000e40166a07a0000000000000000000000000994206dfe8de6ec6920ff4d779b0d950605fb53000000000000000000000000d533a949740bb3306d119cc777fa900ba034cd52000000000000000000000000ca74f404e0c7bfa35b13b511097df966d5a65597000000000000000000000000ca74f404e0c7bfa35b13b511097df966d5a65597000000000000000000000000000000000000000000000216614199391dbba2ba00000000000000000000000000000000000000000000000000000000000000c0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000";
+
+        assert_eq!(
+            "0bf1845c5d7a82ec92365d5027f7310793d53004f3c86aa80965c67bf7e7dc80",
+            hex::encode(tx.hash())
+        );
+        assert_eq!(raw, hex::encode(tx.encode()));
+        assert_eq!(
+            tx,
+            DepositTransaction::decode(&hex::decode(raw).unwrap()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! RLP encoding written straight into a buffer.
+//!
+//! Every value knows the length of its encoding, so a transaction can size its buffer, or
+//! its list header, before writing a byte. Nothing is cloned or boxed on the way.
+use bytes::buf::UninitSlice;
+use bytes::BufMut;
+use tiny_keccak::{Hasher, Keccak};
+
+use {Access, AccessList};
+
+/// A value with an RLP encoding.
+pub trait RlpField {
+    /// Length of the encoding.
+    fn rlp_len(&self) -> usize;
+
+    /// Write the encoding, which is [`rlp_len`](RlpField::rlp_len) bytes long.
+    fn rlp_encode<B: BufMut>(&self, out: &mut B);
+}
+
+/// Length of the header of a list whose items take `payload_len` bytes.
+pub fn list_header_len(payload_len: usize) -> usize {
+    header_len(payload_len)
+}
+
+/// Write the header of a list whose items take `payload_len` bytes.
+pub fn put_list_header<B: BufMut>(payload_len: usize, out: &mut B) {
+    put_header(0xc0, payload_len, out)
+}
+
+fn header_len(payload_len: usize) -> usize {
+    match payload_len {
+        0..=55 => 1,
+        len => 1 + be_len(len as u128),
+    }
+}
+
+/// Header of a string (`offset` 0x80) or list (0xc0) whose payload takes `len` bytes.
+fn put_header<B: BufMut>(offset: u8, len: usize, out: &mut B) {
+    match len {
+        0..=55 => out.put_u8(offset + len as u8),
+        len => {
+            let len_len = be_len(len as u128);
+            out.put_u8(offset + 55 + len_len as u8);
+            out.put_slice(&(len as u128).to_be_bytes()[16 - len_len..]);
+        }
+    }
+}
+
+/// Bytes needed for `value` in big-endian without leading zeroes.
+fn be_len(value: u128) -> usize {
+    16 - value.leading_zeros() as usize / 8
+}
+
+impl RlpField for u128 {
+    fn rlp_len(&self) -> usize {
+        match *self {
+            0..=0x7f => 1,
+            value => 1 + be_len(value),
+        }
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        let bytes = self.to_be_bytes();
+        bytes[16 - be_len(*self)..].rlp_encode(out)
+    }
+}
+
+impl RlpField for u64 {
+    fn rlp_len(&self) -> usize {
+        (*self as u128).rlp_len()
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        (*self as u128).rlp_encode(out)
+    }
+}
+
+impl RlpField for [u8] {
+    fn rlp_len(&self) -> usize {
+        match *self {
+            [byte] if byte < 0x80 => 1,
+            _ => header_len(self.len()) + self.len(),
+        }
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        match *self {
+            [byte] if byte < 0x80 => out.put_u8(byte),
+            _ => {
+                put_header(0x80, self.len(), out);
+                out.put_slice(self);
+            }
+        }
+    }
+}
+
+impl RlpField for Vec<u8> {
+    fn rlp_len(&self) -> usize {
+        self[..].rlp_len()
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        self[..].rlp_encode(out)
+    }
+}
+
+impl RlpField for [u8; 20] {
+    fn rlp_len(&self) -> usize {
+        21
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        self[..].rlp_encode(out)
+    }
+}
+
+impl RlpField for [u8; 32] {
+    fn rlp_len(&self) -> usize {
+        33
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        self[..].rlp_encode(out)
+    }
+}
+
+/// A recipient, or the empty string for contract creation.
+impl RlpField for Option<[u8; 20]> {
+    fn rlp_len(&self) -> usize {
+        match *self {
+            Some(ref to) => to.rlp_len(),
+            None => 1,
+        }
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        match *self {
+            Some(ref to) => to.rlp_encode(out),
+            None => out.put_u8(0x80),
+        }
+    }
+}
+
+impl Access {
+    fn storage_keys_len(&self) -> usize {
+        self.storage_keys.len() * 33
+    }
+
+    fn payload_len(&self) -> usize {
+        let keys_len = self.storage_keys_len();
+        self.address.rlp_len() + list_header_len(keys_len) + keys_len
+    }
+}
+
+impl RlpField for Access {
+    fn rlp_len(&self) -> usize {
+        let payload_len = self.payload_len();
+        list_header_len(payload_len) + payload_len
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        put_list_header(self.payload_len(), out);
+        self.address.rlp_encode(out);
+        put_list_header(self.storage_keys_len(), out);
+        for storage_key in self.storage_keys.iter() {
+            storage_key.rlp_encode(out);
+        }
+    }
+}
+
+/// Encodes the access list according to [EIP-2930](https://eips.ethereum.org/EIPS/eip-2930).
+impl RlpField for AccessList {
+    fn rlp_len(&self) -> usize {
+        let payload_len = self.0.iter().map(Access::rlp_len).sum();
+        list_header_len(payload_len) + payload_len
+    }
+
+    fn rlp_encode<B: BufMut>(&self, out: &mut B) {
+        put_list_header(self.0.iter().map(Access::rlp_len).sum(), out);
+        for access in self.0.iter() {
+            access.rlp_encode(out);
+        }
+    }
+}
+
+/// A buffer that feeds what is written to it into Keccak-256, so that an encoding can be
+/// hashed without being held in memory.
+pub(crate) struct KeccakWriter {
+    hasher: Keccak,
+    /// Handed out to writers that do not go through `put_slice`
+    scratch: [u8; 32],
+}
+
+impl KeccakWriter {
+    pub(crate) fn new() -> Self {
+        KeccakWriter {
+            hasher: Keccak::v256(),
+            scratch: [0; 32],
+        }
+    }
+
+    pub(crate) fn finalize(self) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        self.hasher.finalize(&mut hash);
+        hash
+    }
+}
+
+unsafe impl BufMut for KeccakWriter {
+    fn remaining_mut(&self) -> usize {
+        usize::MAX
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        self.hasher.update(&self.scratch[..cnt]);
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        UninitSlice::new(&mut self.scratch)
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.hasher.update(src);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{BufMut, BytesMut};
+    use encode::{list_header_len, put_list_header, KeccakWriter, RlpField};
+    use rlp::RlpStream;
+    use {
+        keccak256_hash, Access, AccessList, AccessListTransaction, FeeMarketTransaction,
+        LegacyTransaction, Transaction,
+    };
+
+    fn encoded<T: RlpField + ?Sized>(value: &T) -> Vec<u8> {
+        let mut out = vec![];
+        value.rlp_encode(&mut out);
+        assert_eq!(value.rlp_len(), out.len());
+        out
+    }
+
+    #[test]
+    fn test_matches_rlp_crate() {
+        for &value in [
+            0u128,
+            1,
+            0x7f,
+            0x80,
+            0xff,
+            0x100,
+            u64::MAX as u128,
+            u128::MAX,
+        ]
+        .iter()
+        {
+            assert_eq!(rlp::encode(&value).to_vec(), encoded(&value));
+        }
+        assert_eq!(rlp::encode(&1_000_000u64).to_vec(), encoded(&1_000_000u64));
+        for len in [0usize, 1, 55, 56, 255, 256, 70_000].iter() {
+            let bytes = vec![0xab; *len];
+            assert_eq!(rlp::encode(&bytes).to_vec(), encoded(&bytes));
+        }
+        for &byte in [0x00u8, 0x7f, 0x80].iter() {
+            assert_eq!(rlp::encode(&vec![byte]).to_vec(), encoded(&vec![byte]));
+        }
+        assert_eq!(vec![0x80], encoded(&None::<[u8; 20]>));
+
+        let access_list = AccessList(vec![
+            Access {
+                address: [0x11; 20],
+                storage_keys: vec![[0x22; 32]; 3],
+            },
+            Access {
+                address: [0x33; 20],
+                storage_keys: vec![],
+            },
+        ]);
+        assert_eq!(rlp::encode(&access_list).to_vec(), encoded(&access_list));
+        assert_eq!(
+            rlp::encode(&AccessList(vec![])).to_vec(),
+            encoded(&AccessList(vec![]))
+        );
+    }
+
+    #[test]
+    fn test_list_header() {
+        for len in [0usize, 55, 56, 1024, 1 << 24].iter() {
+            let mut stream = RlpStream::new();
+            stream.begin_unbounded_list();
+            stream.append_raw(&vec![0u8; *len], 1);
+            stream.finalize_unbounded_list();
+            let expected = stream.out();
+
+            let mut header = vec![];
+            put_list_header(*len, &mut header);
+            assert_eq!(list_header_len(*len), header.len());
+            assert_eq!(expected[..header.len()], header[..]);
+        }
+    }
+
+    #[test]
+    fn test_keccak_writer() {
+        let mut writer = KeccakWriter::new();
+        let mut expected = vec![];
+        for i in 0..300u32 {
+            writer.put_u8(i as u8);
+            expected.push(i as u8);
+            if i % 7 == 0 {
+                // through `chunk_mut`, in more than one chunk
+                writer.put_bytes(0xab, 50);
+                expected.extend_from_slice(&[0xab; 50]);
+            }
+        }
+        assert_eq!(keccak256_hash(&expected), writer.finalize());
+        assert_eq!(keccak256_hash(&[]), KeccakWriter::new().finalize());
+    }
+
+    fn check_lengths<T: Transaction>(tx: &T) {
+        let payload = tx.signing_payload();
+        assert_eq!(tx.signing_payload_len(), payload.len());
+        assert_eq!(keccak256_hash(&payload), tx.hash());
+        assert_eq!(payload.len(), payload.capacity());
+
+        let ecdsa = tx.ecdsa(&[0x35; 32]).unwrap();
+        let signed = tx.sign(&ecdsa);
+        assert_eq!(tx.signed_len(&ecdsa), signed.len());
+
+        // into a caller's buffer, after what it already holds
+        let mut out = BytesMut::from(&b"prefix"[..]);
+        tx.encode_signed(&ecdsa, &mut out);
+        assert_eq!(signed[..], out[6..]);
+        let mut out = vec![0u8; payload.len()];
+        tx.encode_signing_payload(&mut &mut out[..]);
+        assert_eq!(payload, out);
+    }
+
+    #[test]
+    fn test_transaction_lengths() {
+        // calldata long enough for multi-byte length prefixes
+        let data = vec![0xab; 70_000];
+        let access_list = AccessList(vec![Access {
+            address: [0x11; 20],
+            storage_keys: vec![[0x22; 32]; 2],
+        }]);
+        check_lengths(&LegacyTransaction {
+            chain: u32::MAX as u64,
+            gas: 21000,
+            data: data.clone(),
+            ..Default::default()
+        });
+        check_lengths(&AccessListTransaction {
+            chain: 1,
+            to: Some([0x45; 20]),
+            access_list: access_list.clone(),
+            ..Default::default()
+        });
+        check_lengths(&FeeMarketTransaction {
+            chain: 1,
+            nonce: u128::MAX,
+            data,
+            access_list,
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+
+use {AccessListTransaction, FeeMarketTransaction, LegacyTransaction};
+
+/// Error computing the cost of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeError {
+    /// The result does not fit in a `u128`.
+    Overflow,
+    /// The most the transaction is willing to pay per gas is below the block base fee,
+    /// so it cannot be included in the block.
+    FeeBelowBaseFee { fee_per_gas: u128, base_fee: u128 },
+    /// A replacement raises a fee by less than the percentage nodes require to replace
+    /// a pending transaction, [`MIN_FEE_BUMP_PERCENT`](::MIN_FEE_BUMP_PERCENT).
+    BumpTooSmall(u128),
+}
+
+impl fmt::Display for FeeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FeeError::Overflow => write!(f, "fee computation overflowed"),
+            FeeError::FeeBelowBaseFee {
+                fee_per_gas,
+                base_fee,
+            } => write!(
+                f,
+                "fee per gas {} is below the base fee {}",
+                fee_per_gas, base_fee
+            ),
+            FeeError::BumpTooSmall(percent) => write!(
+                f,
+                "fee bump of {}% is below the {}% nodes require for a replacement",
+                percent,
+                ::MIN_FEE_BUMP_PERCENT
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FeeError {}
+
+/// How the fee paid by a transaction is split between the burned base fee and the
+/// tip that goes to the block producer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeeBreakdown {
+    /// Base fee portion, burned according to [EIP-1559](https://eips.ethereum.org/EIPS/eip-1559)
+    pub burned: u128,
+    /// Priority fee portion, paid to the block producer
+    pub miner: u128,
+}
+
+impl FeeBreakdown {
+    /// Total fee paid by the sender.
+    pub fn total(&self) -> Result<u128, FeeError> {
+        self.burned
+            .checked_add(self.miner)
+            .ok_or(FeeError::Overflow)
+    }
+}
+
+impl LegacyTransaction {
+    /// The most the sender can be charged: `gas * gas_price + value`.
+    pub fn max_cost(&self) -> Result<u128, FeeError> {
+        max_cost(self.gas, self.gas_price, self.value)
+    }
+
+    /// Price paid per unit of gas in a block with the given base fee.
+    pub fn effective_gas_price(&self, base_fee: u128) -> Result<u128, FeeError> {
+        effective_gas_price(self.gas_price, self.gas_price, base_fee)
+    }
+
+    /// Tip paid per unit of gas to the block producer in a block with the given base fee.
+    pub fn effective_tip(&self, base_fee: u128) -> Result<u128, FeeError> {
+        effective_tip(self.gas_price, self.gas_price, base_fee)
+    }
+
+    /// Split of the fee for `gas_used` units of gas in a block with the given base fee.
+    pub fn fee_breakdown(&self, base_fee: u128, gas_used: u128) -> Result<FeeBreakdown, FeeError> {
+        fee_breakdown(self.gas_price, self.gas_price, base_fee, gas_used)
+    }
+}
+
+impl AccessListTransaction {
+    /// The most the sender can be charged: `gas * gas_price + value`.
+    pub fn max_cost(&self) -> Result<u128, FeeError> {
+        max_cost(self.gas, self.gas_price, self.value)
+    }
+
+    /// Price paid per unit of gas in a block with the given base fee.
+    pub fn effective_gas_price(&self, base_fee: u128) -> Result<u128, FeeError> {
+        effective_gas_price(self.gas_price, self.gas_price, base_fee)
+    }
+
+    /// Tip paid per unit of gas to the block producer in a block with the given base fee.
+    pub fn effective_tip(&self, base_fee: u128) -> Result<u128, FeeError> {
+        effective_tip(self.gas_price, self.gas_price, base_fee)
+    }
+
+    /// Split of the fee for `gas_used` units of gas in a block with the given base fee.
+    pub fn fee_breakdown(&self, base_fee: u128, gas_used: u128) -> Result<FeeBreakdown, FeeError> {
+        fee_breakdown(self.gas_price, self.gas_price, base_fee, gas_used)
+    }
+}
+
+impl FeeMarketTransaction {
+    /// The most the sender can be charged: `gas * max_fee_per_gas + value`.
+    pub fn max_cost(&self) -> Result<u128, FeeError> {
+        max_cost(self.gas, self.max_fee_per_gas, self.value)
+    }
+
+    /// Price paid per unit of gas in a block with the given base fee:
+    /// `min(max_fee_per_gas, base_fee + max_priority_fee_per_gas)`.
+    pub fn effective_gas_price(&self, base_fee: u128) -> Result<u128, FeeError> {
+        effective_gas_price(
+            self.max_fee_per_gas,
+            self.max_priority_fee_per_gas,
+            base_fee,
+        )
+    }
+
+    /// Tip paid per unit of gas to the block producer in a block with the given base fee.
+    pub fn effective_tip(&self, base_fee: u128) -> Result<u128, FeeError> {
+        effective_tip(
+            self.max_fee_per_gas,
+            self.max_priority_fee_per_gas,
+            base_fee,
+        )
+    }
+
+    /// Split of the fee for `gas_used` units of gas in a block with the given base fee.
+    pub fn fee_breakdown(&self, base_fee: u128, gas_used: u128) -> Result<FeeBreakdown, FeeError> {
+        fee_breakdown(
+            self.max_fee_per_gas,
+            self.max_priority_fee_per_gas,
+            base_fee,
+            gas_used,
+        )
+    }
+}
+
+fn max_cost(gas: u128, max_fee_per_gas: u128, value: u128) -> Result<u128, FeeError> {
+    gas.checked_mul(max_fee_per_gas)
+        .and_then(|fee| fee.checked_add(value))
+        .ok_or(FeeError::Overflow)
+}
+
+/// Legacy transactions pass their gas price as both `max_fee_per_gas` and
+/// `max_priority_fee_per_gas`, which is how [EIP-1559](https://eips.ethereum.org/EIPS/eip-1559)
+/// treats them.
+fn effective_gas_price(
+    max_fee_per_gas: u128,
+    max_priority_fee_per_gas: u128,
+    base_fee: u128,
+) -> Result<u128, FeeError> {
+    let tip = effective_tip(max_fee_per_gas, max_priority_fee_per_gas, base_fee)?;
+    base_fee.checked_add(tip).ok_or(FeeError::Overflow)
+}
+
+fn effective_tip(
+    max_fee_per_gas: u128,
+    max_priority_fee_per_gas: u128,
+    base_fee: u128,
+) -> Result<u128, FeeError> {
+    match max_fee_per_gas.checked_sub(base_fee) {
+        Some(headroom) => Ok(headroom.min(max_priority_fee_per_gas)),
+        None => Err(FeeError::FeeBelowBaseFee {
+            fee_per_gas: max_fee_per_gas,
+            base_fee,
+        }),
+    }
+}
+
+fn fee_breakdown(
+    max_fee_per_gas: u128,
+    max_priority_fee_per_gas: u128,
+    base_fee: u128,
+    gas_used: u128,
+) -> Result<FeeBreakdown, FeeError> {
+    let tip = effective_tip(max_fee_per_gas, max_priority_fee_per_gas, base_fee)?;
+    let burned = gas_used.checked_mul(base_fee).ok_or(FeeError::Overflow)?;
+    let miner = gas_used.checked_mul(tip).ok_or(FeeError::Overflow)?;
+    Ok(FeeBreakdown { burned, miner })
+}
+
+#[cfg(test)]
+mod test {
+    use fees::{FeeBreakdown, FeeError};
+    use {AccessList, FeeMarketTransaction, LegacyTransaction};
+
+    const GWEI: u128 = 1_000_000_000;
+
+    fn fee_market() -> FeeMarketTransaction {
+        FeeMarketTransaction {
+            chain: 1,
+            nonce: 0,
+            max_priority_fee_per_gas: 2 * GWEI,
+            max_fee_per_gas: 30 * GWEI,
+            gas: 21_000,
+            to: Some([0x45; 20]),
+            value: 1000,
+            data: vec![],
+            access_list: AccessList(vec![]),
+        }
+    }
+
+    #[test]
+    fn test_fee_market_max_cost() {
+        assert_eq!(Ok(21_000 * 30 * GWEI + 1000), fee_market().max_cost());
+    }
+
+    #[test]
+    fn test_fee_market_tip_capped_by_priority_fee() {
+        let tx = fee_market();
+        assert_eq!(Ok(12 * GWEI), tx.effective_gas_price(10 * GWEI));
+        assert_eq!(Ok(2 * GWEI), tx.effective_tip(10 * GWEI));
+    }
+
+    #[test]
+    fn test_fee_market_tip_capped_by_max_fee() {
+        let tx = fee_market();
+        assert_eq!(Ok(30 * GWEI), tx.effective_gas_price(29 * GWEI));
+        assert_eq!(Ok(GWEI), tx.effective_tip(29 * GWEI));
+        assert_eq!(
+            Ok(FeeBreakdown {
+                burned: 21_000 * 29 * GWEI,
+                miner: 21_000 * GWEI,
+            }),
+            tx.fee_breakdown(29 * GWEI, 21_000)
+        );
+    }
+
+    #[test]
+    fn test_fee_market_below_base_fee() {
+        assert_eq!(
+            Err(FeeError::FeeBelowBaseFee {
+                fee_per_gas: 30 * GWEI,
+                base_fee: 31 * GWEI,
+            }),
+            fee_market().effective_gas_price(31 * GWEI)
+        );
+    }
+
+    #[test]
+    fn test_legacy_fees() {
+        let tx = LegacyTransaction {
+            chain: 1,
+            nonce: 0,
+            to: Some([0x45; 20]),
+            value: 1000,
+            gas_price: 20 * GWEI,
+            gas: 21_000,
+            data: vec![],
+        };
+
+        assert_eq!(Ok(20 * GWEI), tx.effective_gas_price(15 * GWEI));
+        assert_eq!(Ok(5 * GWEI), tx.effective_tip(15 * GWEI));
+        let breakdown = tx.fee_breakdown(15 * GWEI, 21_000).unwrap();
+        assert_eq!(Ok(21_000 * 20 * GWEI), breakdown.total());
+    }
+
+    #[test]
+    fn test_max_cost_overflow() {
+        let tx = FeeMarketTransaction {
+            max_fee_per_gas: u128::MAX,
+            ..fee_market()
+        };
+        assert_eq!(Err(FeeError::Overflow), tx.max_cost());
+    }
+}
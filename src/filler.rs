@@ -0,0 +1,498 @@
+//! Completing partially specified transactions with values from a node.
+//!
+//! ```
+//! use ethereum_tx_sign::filler::{Filler, PartialTransaction};
+//! use ethereum_tx_sign::transport::MockTransport;
+//!
+//! let node = MockTransport::new();
+//! node.respond("eth_chainId", "0x1".into())
+//!     .respond("eth_getTransactionCount", "0x2a".into())
+//!     .respond("eth_feeHistory", serde_json::json!({
+//!         "baseFeePerGas": ["0x3b9aca00", "0x4a817c800"],
+//!         "reward": [["0x77359400"]]
+//!     }))
+//!     .respond("eth_estimateGas", "0x5208".into());
+//!
+//! let filler = Filler::new(&node, [0x11; 20]);
+//! let tx = filler.fill_fee_market(&PartialTransaction {
+//!     to: Some([0x45; 20]),
+//!     value: 1000,
+//!     ..Default::default()
+//! }).unwrap();
+//!
+//! assert_eq!(42, tx.nonce);
+//! assert_eq!(21000, tx.gas);
+//! ```
+use serde_json::Value;
+use std::cell::Cell;
+use std::fmt;
+
+use rpc::{self, parse_quantity, BlockId, JsonRpcRequest, RpcTransaction};
+use transport::{Transport, TransportError};
+use {AccessList, FeeMarketTransaction, LegacyTransaction};
+
+/// Transaction fields as known before talking to the node. `None` fields are filled in.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PartialTransaction {
+    /// Chain ID, from `eth_chainId` when missing
+    pub chain: Option<u64>,
+    /// Nonce, from `eth_getTransactionCount` of the pending block when missing
+    pub nonce: Option<u128>,
+    /// Gas limit, from `eth_estimateGas` when missing
+    pub gas: Option<u128>,
+    /// Fee cap for fee market transactions, from `eth_feeHistory` when missing
+    pub max_fee_per_gas: Option<u128>,
+    /// Tip for fee market transactions, from `eth_feeHistory` when missing
+    pub max_priority_fee_per_gas: Option<u128>,
+    /// Gas price for legacy transactions, from `eth_feeHistory` when missing
+    pub gas_price: Option<u128>,
+    /// Recipient (None when contract creation)
+    pub to: Option<[u8; 20]>,
+    /// Transfered value
+    pub value: u128,
+    /// Input data
+    pub data: Vec<u8>,
+    /// List of addresses and storage keys the transaction plans to access
+    pub access_list: AccessList,
+}
+
+#[derive(Debug)]
+pub enum FillError {
+    Transport(TransportError),
+    /// The node returned a value that could not be understood.
+    InvalidResponse {
+        method: &'static str,
+        result: Value,
+    },
+    /// A filled in value does not fit in its field.
+    Overflow,
+}
+
+impl fmt::Display for FillError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FillError::Transport(ref err) => err.fmt(f),
+            FillError::InvalidResponse { method, ref result } => {
+                write!(f, "unexpected result from {}: {}", method, result)
+            }
+            FillError::Overflow => write!(f, "filled in value overflowed"),
+        }
+    }
+}
+
+impl std::error::Error for FillError {}
+
+impl From<TransportError> for FillError {
+    fn from(error: TransportError) -> Self {
+        FillError::Transport(error)
+    }
+}
+
+/// Estimated fees for the next block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FeeEstimate {
+    next_base_fee: u128,
+    priority_fee: u128,
+}
+
+/// Fills in missing transaction fields by querying a node through a [`Transport`].
+pub struct Filler<T: Transport> {
+    transport: T,
+    from: [u8; 20],
+    fee_history_blocks: u64,
+    reward_percentile: f64,
+    gas_margin_percent: u128,
+    next_id: Cell<u64>,
+}
+
+impl<T: Transport> Filler<T> {
+    /// Filler for transactions sent from `from`.
+    pub fn new(transport: T, from: [u8; 20]) -> Self {
+        Filler {
+            transport,
+            from,
+            fee_history_blocks: 10,
+            reward_percentile: 50.0,
+            gas_margin_percent: 0,
+            next_id: Cell::new(1),
+        }
+    }
+
+    /// Number of recent blocks to base the priority fee on. Defaults to 10.
+    pub fn fee_history_blocks(mut self, blocks: u64) -> Self {
+        self.fee_history_blocks = blocks;
+        self
+    }
+
+    /// Percentile of the priority fees paid in each recent block to aim for.
+    /// Defaults to the median.
+    pub fn reward_percentile(mut self, percentile: f64) -> Self {
+        self.reward_percentile = percentile;
+        self
+    }
+
+    /// Percentage to add on top of the node's gas estimate. Defaults to 0.
+    pub fn gas_margin_percent(mut self, percent: u128) -> Self {
+        self.gas_margin_percent = percent;
+        self
+    }
+
+    /// Complete a [`FeeMarketTransaction`]. A missing `max_fee_per_gas` is set to twice
+    /// the next block's base fee plus the priority fee, which stays includable through
+    /// several blocks of rising base fees. An estimated priority fee is capped at a given
+    /// `max_fee_per_gas`.
+    pub fn fill_fee_market(
+        &self,
+        partial: &PartialTransaction,
+    ) -> Result<FeeMarketTransaction, FillError> {
+        let chain = self.chain(partial)?;
+        let nonce = self.nonce(partial)?;
+
+        let (max_priority_fee_per_gas, max_fee_per_gas) =
+            match (partial.max_priority_fee_per_gas, partial.max_fee_per_gas) {
+                (Some(tip), Some(cap)) => (tip, cap),
+                (tip, Some(cap)) => {
+                    let estimate = self.fee_estimate()?;
+                    (tip.unwrap_or_else(|| estimate.priority_fee.min(cap)), cap)
+                }
+                (tip, None) => {
+                    let estimate = self.fee_estimate()?;
+                    let tip = tip.unwrap_or(estimate.priority_fee);
+                    let cap = estimate
+                        .next_base_fee
+                        .checked_mul(2)
+                        .and_then(|f| f.checked_add(tip))
+                        .ok_or(FillError::Overflow)?;
+                    (tip, cap)
+                }
+            };
+
+        let mut tx = FeeMarketTransaction {
+            chain,
+            nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas: 0,
+            to: partial.to,
+            value: partial.value,
+            data: partial.data.clone(),
+            access_list: partial.access_list.clone(),
+        };
+        tx.gas = self.gas(partial, &tx)?;
+        Ok(tx)
+    }
+
+    /// Complete a [`LegacyTransaction`]. A missing `gas_price` is set to the next
+    /// block's base fee plus the priority fee.
+    pub fn fill_legacy(
+        &self,
+        partial: &PartialTransaction,
+    ) -> Result<LegacyTransaction, FillError> {
+        let chain = self.chain(partial)?;
+        let nonce = self.nonce(partial)?;
+        let gas_price = match partial.gas_price {
+            Some(gas_price) => gas_price,
+            None => {
+                let estimate = self.fee_estimate()?;
+                estimate
+                    .next_base_fee
+                    .checked_add(estimate.priority_fee)
+                    .ok_or(FillError::Overflow)?
+            }
+        };
+
+        let mut tx = LegacyTransaction {
+            chain,
+            nonce,
+            to: partial.to,
+            value: partial.value,
+            gas_price,
+            gas: 0,
+            data: partial.data.clone(),
+        };
+        tx.gas = self.gas(partial, &tx)?;
+        Ok(tx)
+    }
+
+    fn chain(&self, partial: &PartialTransaction) -> Result<u64, FillError> {
+        if let Some(chain) = partial.chain {
+            return Ok(chain);
+        }
+        let method = "eth_chainId";
+        let result = self.send(method, vec![])?;
+        match parse_quantity_result(&result) {
+            Some(chain) if chain <= u64::MAX as u128 => Ok(chain as u64),
+            _ => Err(FillError::InvalidResponse { method, result }),
+        }
+    }
+
+    fn nonce(&self, partial: &PartialTransaction) -> Result<u128, FillError> {
+        if let Some(nonce) = partial.nonce {
+            return Ok(nonce);
+        }
+        let method = "eth_getTransactionCount";
+        let result = self.send(
+            method,
+            vec![rpc::bytes(&self.from).into(), (&BlockId::Pending).into()],
+        )?;
+        parse_quantity_result(&result).ok_or(FillError::InvalidResponse { method, result })
+    }
+
+    fn gas<R: RpcTransaction>(
+        &self,
+        partial: &PartialTransaction,
+        tx: &R,
+    ) -> Result<u128, FillError> {
+        if let Some(gas) = partial.gas {
+            return Ok(gas);
+        }
+        let method = "eth_estimateGas";
+        let mut object = tx.to_rpc_object();
+        object.remove("gas");
+        object.insert("from".into(), rpc::bytes(&self.from).into());
+        let result = self.send(method, vec![Value::Object(object)])?;
+        let estimate =
+            parse_quantity_result(&result).ok_or(FillError::InvalidResponse { method, result })?;
+
+        100u128
+            .checked_add(self.gas_margin_percent)
+            .and_then(|factor| estimate.checked_mul(factor))
+            .map(|gas| gas / 100)
+            .ok_or(FillError::Overflow)
+    }
+
+    fn fee_estimate(&self) -> Result<FeeEstimate, FillError> {
+        let method = "eth_feeHistory";
+        let result = self.send(
+            method,
+            vec![
+                rpc::quantity(self.fee_history_blocks as u128).into(),
+                (&BlockId::Latest).into(),
+                vec![self.reward_percentile].into(),
+            ],
+        )?;
+
+        // the last base fee is the one for the block after the newest one returned
+        let next_base_fee = result
+            .get("baseFeePerGas")
+            .and_then(Value::as_array)
+            .and_then(|fees| fees.last())
+            .and_then(parse_quantity_result);
+        let mut rewards: Option<Vec<u128>> =
+            result
+                .get("reward")
+                .and_then(Value::as_array)
+                .map(|blocks| {
+                    blocks
+                        .iter()
+                        .filter_map(|block| block.get(0).and_then(parse_quantity_result))
+                        .collect()
+                });
+
+        match (next_base_fee, rewards.as_mut()) {
+            (Some(next_base_fee), Some(rewards)) if !rewards.is_empty() => {
+                rewards.sort_unstable();
+                Ok(FeeEstimate {
+                    next_base_fee,
+                    priority_fee: rewards[rewards.len() / 2],
+                })
+            }
+            _ => Err(FillError::InvalidResponse { method, result }),
+        }
+    }
+
+    fn send(&self, method: &'static str, params: Vec<Value>) -> Result<Value, FillError> {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        Ok(self
+            .transport
+            .send(&JsonRpcRequest::new(id, method, params))?)
+    }
+}
+
+fn parse_quantity_result(result: &Value) -> Option<u128> {
+    result.as_str().and_then(parse_quantity)
+}
+
+#[cfg(test)]
+mod test {
+    use filler::{FillError, Filler, PartialTransaction};
+    use serde_json::{json, Value};
+    use transport::{MockTransport, TransportError};
+    use {AccessList, FeeMarketTransaction, LegacyTransaction};
+
+    const FROM: [u8; 20] = [0x11; 20];
+
+    fn node() -> MockTransport {
+        let node = MockTransport::new();
+        node.respond("eth_chainId", "0x89".into())
+            .respond("eth_getTransactionCount", "0x7".into())
+            .respond(
+                "eth_feeHistory",
+                json!({
+                    "oldestBlock": "0x100",
+                    "baseFeePerGas": ["0x1", "0x2", "0x3", "0x64"],
+                    "gasUsedRatio": [0.5, 0.5, 0.5],
+                    "reward": [["0x5"], ["0x1"], ["0x9"]]
+                }),
+            )
+            .respond("eth_estimateGas", "0x7530".into());
+        node
+    }
+
+    fn partial() -> PartialTransaction {
+        PartialTransaction {
+            to: Some([0x45; 20]),
+            value: 1000,
+            data: vec![0xab],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fill_fee_market() {
+        let node = node();
+        let tx = Filler::new(&node, FROM)
+            .gas_margin_percent(20)
+            .fill_fee_market(&partial())
+            .unwrap();
+
+        assert_eq!(
+            FeeMarketTransaction {
+                chain: 0x89,
+                nonce: 7,
+                max_priority_fee_per_gas: 5,
+                max_fee_per_gas: 2 * 0x64 + 5,
+                gas: 36_000,
+                to: Some([0x45; 20]),
+                value: 1000,
+                data: vec![0xab],
+                access_list: AccessList(vec![]),
+            },
+            tx
+        );
+
+        let requests = node.requests();
+        let methods: Vec<&str> = requests.iter().map(|r| r.method).collect();
+        assert_eq!(
+            vec![
+                "eth_chainId",
+                "eth_getTransactionCount",
+                "eth_feeHistory",
+                "eth_estimateGas"
+            ],
+            methods
+        );
+        assert_eq!(
+            json!(["0x1111111111111111111111111111111111111111", "pending"]),
+            Value::from(requests[1].params.clone())
+        );
+        assert_eq!(
+            json!(["0xa", "latest", [50.0]]),
+            Value::from(requests[2].params.clone())
+        );
+        let estimate = &requests[3].params[0];
+        assert_eq!(Value::Null, estimate["gas"]);
+        assert_eq!(
+            json!("0x1111111111111111111111111111111111111111"),
+            estimate["from"]
+        );
+        assert_eq!(json!("0xcd"), estimate["maxFeePerGas"]);
+    }
+
+    #[test]
+    fn test_fill_keeps_given_values() {
+        let node = MockTransport::new();
+        let given = PartialTransaction {
+            chain: Some(1),
+            nonce: Some(3),
+            gas: Some(50_000),
+            max_fee_per_gas: Some(10),
+            max_priority_fee_per_gas: Some(2),
+            ..partial()
+        };
+
+        let tx = Filler::new(&node, FROM).fill_fee_market(&given).unwrap();
+
+        assert_eq!(
+            (1, 3, 50_000, 10, 2),
+            (
+                tx.chain,
+                tx.nonce,
+                tx.gas,
+                tx.max_fee_per_gas,
+                tx.max_priority_fee_per_gas
+            )
+        );
+        assert!(node.requests().is_empty());
+    }
+
+    #[test]
+    fn test_fill_caps_estimated_priority_fee() {
+        let node = node();
+        let given = PartialTransaction {
+            max_fee_per_gas: Some(3),
+            ..partial()
+        };
+        let tx = Filler::new(&node, FROM).fill_fee_market(&given).unwrap();
+
+        assert_eq!((3, 3), (tx.max_fee_per_gas, tx.max_priority_fee_per_gas));
+    }
+
+    #[test]
+    fn test_fill_gas_margin_overflow() {
+        let node = node();
+        match Filler::new(&node, FROM)
+            .gas_margin_percent(u128::MAX)
+            .fill_legacy(&partial())
+        {
+            Err(FillError::Overflow) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_fill_legacy() {
+        let node = node();
+        let tx = Filler::new(&node, FROM).fill_legacy(&partial()).unwrap();
+
+        assert_eq!(
+            LegacyTransaction {
+                chain: 0x89,
+                nonce: 7,
+                to: Some([0x45; 20]),
+                value: 1000,
+                gas_price: 0x64 + 5,
+                gas: 30_000,
+                data: vec![0xab],
+            },
+            tx
+        );
+    }
+
+    #[test]
+    fn test_fill_node_error() {
+        let node = MockTransport::new();
+        node.respond("eth_chainId", "0x1".into())
+            .respond("eth_getTransactionCount", "0x0".into())
+            .respond(
+                "eth_feeHistory",
+                json!({"baseFeePerGas": ["0x1"], "reward": []}),
+            );
+
+        match Filler::new(&node, FROM).fill_fee_market(&partial()) {
+            Err(FillError::InvalidResponse {
+                method: "eth_feeHistory",
+                ..
+            }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        let node = MockTransport::new();
+        node.respond_error("eth_chainId", -32000, "boom");
+        match Filler::new(&node, FROM).fill_legacy(&partial()) {
+            Err(FillError::Transport(TransportError::Rpc { code: -32000, .. })) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
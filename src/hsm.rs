@@ -0,0 +1,601 @@
+//! Signing with keys held in a hardware security module.
+//!
+//! A token returns a bare `r || s` signature of the transaction hash. [`HsmSigner`] turns it
+//! into an [`EcdsaSig`] by finding the recovery ID that recovers to the key's address, which
+//! it derives from the token's public key once.
+//!
+//! With the `pkcs11` feature, `Pkcs11Module` loads a PKCS#11 library, such as SoftHSM's
+//! `libsofthsm2.so` or a vendor's, and finds secp256k1 keys by label.
+use std::fmt;
+
+use {public_key_to_address, EcdsaSig, Error, Transaction};
+
+#[derive(Debug)]
+pub enum HsmError {
+    /// The PKCS#11 library could not be loaded.
+    Library(String),
+    /// A PKCS#11 function failed with the given `CKR_` return value.
+    Pkcs11 { function: &'static str, rv: u64 },
+    /// The token has no key pair with the given label.
+    KeyNotFound(String),
+    /// The key is not a secp256k1 key.
+    UnsupportedKey,
+    /// The token's public key or signature could not be used.
+    Signature(Error),
+}
+
+impl fmt::Display for HsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HsmError::Library(ref message) => {
+                write!(f, "failed to load PKCS#11 library: {}", message)
+            }
+            HsmError::Pkcs11 { function, rv } => write!(f, "{} failed: CKR {:#x}", function, rv),
+            HsmError::KeyNotFound(ref label) => write!(f, "no key pair labelled {:?}", label),
+            HsmError::UnsupportedKey => write!(f, "key is not on the secp256k1 curve"),
+            HsmError::Signature(ref err) => write!(f, "invalid key or signature: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for HsmError {}
+
+impl From<Error> for HsmError {
+    fn from(error: Error) -> Self {
+        HsmError::Signature(error)
+    }
+}
+
+/// A secp256k1 private key that never leaves its token.
+pub trait HsmKey {
+    /// The public key as a SEC1 point, or as the DER `OCTET STRING` holding one that
+    /// PKCS#11 stores in `CKA_EC_POINT`.
+    fn public_key(&self) -> Result<Vec<u8>, HsmError>;
+
+    /// Sign the 32 byte digest without hashing it again (`CKM_ECDSA`), returning `r || s`.
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, HsmError>;
+}
+
+impl<K: HsmKey + ?Sized> HsmKey for &K {
+    fn public_key(&self) -> Result<Vec<u8>, HsmError> {
+        (**self).public_key()
+    }
+
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, HsmError> {
+        (**self).sign_digest(digest)
+    }
+}
+
+/// Signs transactions with an [`HsmKey`].
+#[derive(Debug)]
+pub struct HsmSigner<K: HsmKey> {
+    key: K,
+    address: [u8; 20],
+}
+
+impl<K: HsmKey> HsmSigner<K> {
+    /// Read the key's public key and derive its address.
+    pub fn new(key: K) -> Result<Self, HsmError> {
+        let address = public_key_to_address(ec_point(&key.public_key()?))?;
+        Ok(HsmSigner { key, address })
+    }
+
+    /// Address of the account the key signs for.
+    pub fn address(&self) -> [u8; 20] {
+        self.address
+    }
+
+    /// Compute the signature for the transaction on the token.
+    pub fn ecdsa<T: Transaction>(&self, tx: &T) -> Result<EcdsaSig, HsmError> {
+        let hash = tx.hash();
+        let chain = match T::transaction_type() {
+            Some(_) => None,
+            None => Some(tx.chain()),
+        };
+        let signature = self.key.sign_digest(&hash)?;
+        Ok(EcdsaSig::from_rs(&signature, hash, &self.address, chain)?)
+    }
+
+    /// Sign and encode the transaction.
+    pub fn sign<T: Transaction>(&self, tx: &T) -> Result<Vec<u8>, HsmError> {
+        Ok(tx.sign(&self.ecdsa(tx)?))
+    }
+}
+
+/// Unwrap the DER `OCTET STRING` around a `CKA_EC_POINT`, if there is one.
+fn ec_point(public_key: &[u8]) -> &[u8] {
+    match *public_key {
+        [0x04, len, ref point @ ..] if len as usize == point.len() && (len == 65 || len == 33) => {
+            point
+        }
+        _ => public_key,
+    }
+}
+
+#[cfg(feature = "pkcs11")]
+pub use self::pkcs11::{Pkcs11Key, Pkcs11Module, Pkcs11Session};
+
+#[cfg(feature = "pkcs11")]
+mod pkcs11 {
+    use libloading::Library;
+    use std::os::raw::{c_ulong, c_void};
+    use std::path::Path;
+    use std::ptr;
+
+    use hsm::{HsmError, HsmKey};
+
+    type CkRv = c_ulong;
+    type CkHandle = c_ulong;
+    type CkFn = Option<unsafe extern "C" fn()>;
+
+    const CKR_OK: CkRv = 0;
+    const CKR_USER_ALREADY_LOGGED_IN: CkRv = 0x100;
+    const CKR_CRYPTOKI_ALREADY_INITIALIZED: CkRv = 0x191;
+    const CKF_RW_SESSION: c_ulong = 0x2;
+    const CKF_SERIAL_SESSION: c_ulong = 0x4;
+    const CKU_USER: c_ulong = 1;
+    const CKA_CLASS: c_ulong = 0x0;
+    const CKA_LABEL: c_ulong = 0x3;
+    const CKA_KEY_TYPE: c_ulong = 0x100;
+    const CKA_EC_PARAMS: c_ulong = 0x180;
+    const CKA_EC_POINT: c_ulong = 0x181;
+    const CKO_PUBLIC_KEY: c_ulong = 2;
+    const CKO_PRIVATE_KEY: c_ulong = 3;
+    const CKK_EC: c_ulong = 3;
+    const CKM_ECDSA: c_ulong = 0x1041;
+    /// DER encoding of the secp256k1 OID, as held in `CKA_EC_PARAMS`.
+    const SECP256K1_PARAMS: [u8; 7] = [0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a];
+
+    #[repr(C)]
+    #[cfg_attr(windows, repr(packed(1)))]
+    struct CkAttribute {
+        kind: c_ulong,
+        value: *mut c_void,
+        len: c_ulong,
+    }
+
+    impl CkAttribute {
+        fn new<T>(kind: c_ulong, value: &mut [T]) -> Self {
+            CkAttribute {
+                kind,
+                value: value.as_mut_ptr() as *mut c_void,
+                len: std::mem::size_of_val(value) as c_ulong,
+            }
+        }
+    }
+
+    #[repr(C)]
+    #[cfg_attr(windows, repr(packed(1)))]
+    struct CkMechanism {
+        mechanism: c_ulong,
+        parameter: *mut c_void,
+        parameter_len: c_ulong,
+    }
+
+    /// The start of `CK_FUNCTION_LIST`, up to the last function used.
+    #[repr(C)]
+    #[cfg_attr(windows, repr(packed(1)))]
+    struct CkFunctionList {
+        version: [u8; 2],
+        initialize: unsafe extern "C" fn(*mut c_void) -> CkRv,
+        finalize: unsafe extern "C" fn(*mut c_void) -> CkRv,
+        get_info: CkFn,
+        get_function_list: CkFn,
+        get_slot_list: CkFn,
+        get_slot_info: CkFn,
+        get_token_info: CkFn,
+        get_mechanism_list: CkFn,
+        get_mechanism_info: CkFn,
+        init_token: CkFn,
+        init_pin: CkFn,
+        set_pin: CkFn,
+        open_session:
+            unsafe extern "C" fn(c_ulong, c_ulong, *mut c_void, CkFn, *mut CkHandle) -> CkRv,
+        close_session: unsafe extern "C" fn(CkHandle) -> CkRv,
+        close_all_sessions: CkFn,
+        get_session_info: CkFn,
+        get_operation_state: CkFn,
+        set_operation_state: CkFn,
+        login: unsafe extern "C" fn(CkHandle, c_ulong, *const u8, c_ulong) -> CkRv,
+        logout: CkFn,
+        create_object: CkFn,
+        copy_object: CkFn,
+        destroy_object: CkFn,
+        get_object_size: CkFn,
+        get_attribute_value:
+            unsafe extern "C" fn(CkHandle, CkHandle, *mut CkAttribute, c_ulong) -> CkRv,
+        set_attribute_value: CkFn,
+        find_objects_init: unsafe extern "C" fn(CkHandle, *mut CkAttribute, c_ulong) -> CkRv,
+        find_objects: unsafe extern "C" fn(CkHandle, *mut CkHandle, c_ulong, *mut c_ulong) -> CkRv,
+        find_objects_final: unsafe extern "C" fn(CkHandle) -> CkRv,
+        encrypt_init: CkFn,
+        encrypt: CkFn,
+        encrypt_update: CkFn,
+        encrypt_final: CkFn,
+        decrypt_init: CkFn,
+        decrypt: CkFn,
+        decrypt_update: CkFn,
+        decrypt_final: CkFn,
+        digest_init: CkFn,
+        digest: CkFn,
+        digest_update: CkFn,
+        digest_key: CkFn,
+        digest_final: CkFn,
+        sign_init: unsafe extern "C" fn(CkHandle, *mut CkMechanism, CkHandle) -> CkRv,
+        sign: unsafe extern "C" fn(CkHandle, *const u8, c_ulong, *mut u8, *mut c_ulong) -> CkRv,
+    }
+
+    // `CK_ULONG` is 32 bits on Windows
+    #[allow(clippy::unnecessary_cast)]
+    fn check(function: &'static str, rv: CkRv) -> Result<(), HsmError> {
+        match rv {
+            CKR_OK => Ok(()),
+            rv => Err(HsmError::Pkcs11 {
+                function,
+                rv: rv as u64,
+            }),
+        }
+    }
+
+    /// A loaded PKCS#11 library.
+    pub struct Pkcs11Module {
+        functions: *const CkFunctionList,
+        // whether `load` initialised the library, rather than another user in the process
+        initialized: bool,
+        // keeps `functions` valid
+        _library: Library,
+    }
+
+    impl Pkcs11Module {
+        /// Load the library at `path` and initialise it.
+        pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, HsmError> {
+            let library = unsafe { Library::new(path.as_ref()) }
+                .map_err(|err| HsmError::Library(err.to_string()))?;
+            let mut functions: *const CkFunctionList = ptr::null();
+            let initialized = unsafe {
+                let get_function_list = library
+                    .get::<unsafe extern "C" fn(*mut *const CkFunctionList) -> CkRv>(
+                        b"C_GetFunctionList\0",
+                    )
+                    .map_err(|err| HsmError::Library(err.to_string()))?;
+                check("C_GetFunctionList", get_function_list(&mut functions))?;
+                match ((*functions).initialize)(ptr::null_mut()) {
+                    CKR_CRYPTOKI_ALREADY_INITIALIZED => false,
+                    rv => {
+                        check("C_Initialize", rv)?;
+                        true
+                    }
+                }
+            };
+            Ok(Pkcs11Module {
+                functions,
+                initialized,
+                _library: library,
+            })
+        }
+
+        /// Open a session on the token in `slot` and log in as the user.
+        pub fn open_session(&self, slot: u64, pin: &str) -> Result<Pkcs11Session<'_>, HsmError> {
+            let mut handle = 0;
+            unsafe {
+                check(
+                    "C_OpenSession",
+                    (self.f().open_session)(
+                        slot as c_ulong,
+                        CKF_SERIAL_SESSION | CKF_RW_SESSION,
+                        ptr::null_mut(),
+                        None,
+                        &mut handle,
+                    ),
+                )?;
+            }
+            let session = Pkcs11Session {
+                module: self,
+                handle,
+            };
+            let rv =
+                unsafe { (self.f().login)(handle, CKU_USER, pin.as_ptr(), pin.len() as c_ulong) };
+            match rv {
+                CKR_USER_ALREADY_LOGGED_IN => (),
+                rv => check("C_Login", rv)?,
+            }
+            Ok(session)
+        }
+
+        fn f(&self) -> &CkFunctionList {
+            unsafe { &*self.functions }
+        }
+    }
+
+    impl Drop for Pkcs11Module {
+        fn drop(&mut self) {
+            // finalising would end the sessions of whoever initialised the library
+            if self.initialized {
+                unsafe {
+                    (self.f().finalize)(ptr::null_mut());
+                }
+            }
+        }
+    }
+
+    /// A logged in session on a token.
+    pub struct Pkcs11Session<'a> {
+        module: &'a Pkcs11Module,
+        handle: CkHandle,
+    }
+
+    impl<'a> Pkcs11Session<'a> {
+        /// Find the secp256k1 key pair whose private and public key objects have `label`.
+        pub fn key(&self, label: &str) -> Result<Pkcs11Key<'_>, HsmError> {
+            let private = self
+                .find(CKO_PRIVATE_KEY, label)?
+                .ok_or_else(|| HsmError::KeyNotFound(label.to_string()))?;
+            let public = self
+                .find(CKO_PUBLIC_KEY, label)?
+                .ok_or_else(|| HsmError::KeyNotFound(label.to_string()))?;
+            if self.attribute(public, CKA_EC_PARAMS)? != SECP256K1_PARAMS {
+                return Err(HsmError::UnsupportedKey);
+            }
+            Ok(Pkcs11Key {
+                session: self,
+                private,
+                public,
+            })
+        }
+
+        fn find(&self, class: c_ulong, label: &str) -> Result<Option<CkHandle>, HsmError> {
+            let mut class = [class];
+            let mut key_type = [CKK_EC];
+            let mut label = label.as_bytes().to_vec();
+            let mut template = [
+                CkAttribute::new(CKA_CLASS, &mut class),
+                CkAttribute::new(CKA_KEY_TYPE, &mut key_type),
+                CkAttribute::new(CKA_LABEL, &mut label),
+            ];
+            let f = self.module.f();
+            let (mut object, mut count) = (0, 0);
+            unsafe {
+                check(
+                    "C_FindObjectsInit",
+                    (f.find_objects_init)(
+                        self.handle,
+                        template.as_mut_ptr(),
+                        template.len() as c_ulong,
+                    ),
+                )?;
+                let found = check(
+                    "C_FindObjects",
+                    (f.find_objects)(self.handle, &mut object, 1, &mut count),
+                );
+                check("C_FindObjectsFinal", (f.find_objects_final)(self.handle))?;
+                found?;
+            }
+            Ok(if count == 1 { Some(object) } else { None })
+        }
+
+        fn attribute(&self, object: CkHandle, kind: c_ulong) -> Result<Vec<u8>, HsmError> {
+            let f = self.module.f();
+            // the first call reports the length
+            let mut template = [CkAttribute {
+                kind,
+                value: ptr::null_mut(),
+                len: 0,
+            }];
+            unsafe {
+                check(
+                    "C_GetAttributeValue",
+                    (f.get_attribute_value)(self.handle, object, template.as_mut_ptr(), 1),
+                )?;
+            }
+            let mut value = vec![0u8; template[0].len as usize];
+            let mut template = [CkAttribute::new(kind, &mut value)];
+            unsafe {
+                check(
+                    "C_GetAttributeValue",
+                    (f.get_attribute_value)(self.handle, object, template.as_mut_ptr(), 1),
+                )?;
+            }
+            value.truncate(template[0].len as usize);
+            Ok(value)
+        }
+    }
+
+    impl<'a> Drop for Pkcs11Session<'a> {
+        fn drop(&mut self) {
+            unsafe {
+                (self.module.f().close_session)(self.handle);
+            }
+        }
+    }
+
+    /// A secp256k1 key pair on a token, found with [`Pkcs11Session::key`].
+    pub struct Pkcs11Key<'a> {
+        session: &'a Pkcs11Session<'a>,
+        private: CkHandle,
+        public: CkHandle,
+    }
+
+    impl<'a> HsmKey for Pkcs11Key<'a> {
+        fn public_key(&self) -> Result<Vec<u8>, HsmError> {
+            self.session.attribute(self.public, CKA_EC_POINT)
+        }
+
+        fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, HsmError> {
+            let f = self.session.module.f();
+            let handle = self.session.handle;
+            let mut mechanism = CkMechanism {
+                mechanism: CKM_ECDSA,
+                parameter: ptr::null_mut(),
+                parameter_len: 0,
+            };
+            let mut signature = [0u8; 64];
+            let mut len = signature.len() as c_ulong;
+            unsafe {
+                check(
+                    "C_SignInit",
+                    (f.sign_init)(handle, &mut mechanism, self.private),
+                )?;
+                check(
+                    "C_Sign",
+                    (f.sign)(
+                        handle,
+                        digest.as_ptr(),
+                        digest.len() as c_ulong,
+                        signature.as_mut_ptr(),
+                        &mut len,
+                    ),
+                )?;
+            }
+            Ok(signature[..len as usize].to_vec())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hsm::{HsmError, HsmKey, HsmSigner};
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+    use {Error, FeeMarketTransaction, LegacyTransaction, Transaction};
+
+    /// Stands in for a token: signs with a local key and returns `r || s`.
+    struct SoftKey {
+        key: SecretKey,
+        /// Return `CKA_EC_POINT` wrapped in an `OCTET STRING`, as the specification asks.
+        wrapped: bool,
+    }
+
+    impl SoftKey {
+        fn new(private_key: &[u8], wrapped: bool) -> Self {
+            SoftKey {
+                key: SecretKey::from_slice(private_key).unwrap(),
+                wrapped,
+            }
+        }
+    }
+
+    impl HsmKey for SoftKey {
+        fn public_key(&self) -> Result<Vec<u8>, HsmError> {
+            let point = PublicKey::from_secret_key(&Secp256k1::signing_only(), &self.key)
+                .serialize_uncompressed()
+                .to_vec();
+            Ok(match self.wrapped {
+                true => [vec![0x04, 0x41], point].concat(),
+                false => point,
+            })
+        }
+
+        fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, HsmError> {
+            let msg = Message::from_slice(digest).unwrap();
+            let signature = Secp256k1::signing_only().sign_ecdsa(&msg, &self.key);
+            Ok(signature.serialize_compact().to_vec())
+        }
+    }
+
+    #[test]
+    fn test_hsm_signer() {
+        let key = SoftKey::new(&[0x35; 32], true);
+        let signer = HsmSigner::new(&key).unwrap();
+        assert_eq!(
+            "8b11c43881b3f9148493c2edad543e97efdc517a",
+            hex::encode(signer.address())
+        );
+        assert_eq!(
+            signer.address(),
+            HsmSigner::new(SoftKey::new(&[0x35; 32], false))
+                .unwrap()
+                .address()
+        );
+
+        let legacy = LegacyTransaction {
+            chain: 1,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            ..Default::default()
+        };
+        let fee_market = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        assert_eq!(
+            legacy.sign(&legacy.ecdsa(&[0x35; 32]).unwrap()),
+            signer.sign(&legacy).unwrap()
+        );
+        let ecdsa = signer.ecdsa(&fee_market).unwrap();
+        assert_eq!(fee_market.ecdsa(&[0x35; 32]).unwrap(), ecdsa);
+        assert_eq!(signer.address(), fee_market.recover(&ecdsa).unwrap());
+    }
+
+    #[test]
+    fn test_hsm_errors() {
+        struct BrokenKey;
+        impl HsmKey for BrokenKey {
+            fn public_key(&self) -> Result<Vec<u8>, HsmError> {
+                Ok(vec![0x04; 65])
+            }
+            fn sign_digest(&self, _: &[u8; 32]) -> Result<Vec<u8>, HsmError> {
+                Ok(vec![])
+            }
+        }
+        match HsmSigner::new(BrokenKey) {
+            Err(HsmError::Signature(Error::Secp256k1(_))) => (),
+            r => panic!("Unexpected result: {:?}", r.map(|s| s.address())),
+        }
+
+        // a key whose public key does not match its signatures
+        struct MismatchedKey(SoftKey, SoftKey);
+        impl HsmKey for MismatchedKey {
+            fn public_key(&self) -> Result<Vec<u8>, HsmError> {
+                self.0.public_key()
+            }
+            fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, HsmError> {
+                self.1.sign_digest(digest)
+            }
+        }
+        let key = MismatchedKey(
+            SoftKey::new(&[0x35; 32], true),
+            SoftKey::new(&[0x36; 32], true),
+        );
+        let signer = HsmSigner::new(key).unwrap();
+        match signer.sign(&FeeMarketTransaction::default()) {
+            Err(HsmError::Signature(Error::UnknownSigner(_))) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    /// Signs with a key in SoftHSM, or any other PKCS#11 token. Set `PKCS11_MODULE` to the
+    /// library, `PKCS11_PIN` to the user PIN and `PKCS11_KEY_LABEL` to the label of a
+    /// secp256k1 key pair in slot `PKCS11_SLOT` (default 0), then run
+    /// `cargo test --features pkcs11 -- --ignored test_pkcs11`.
+    #[cfg(feature = "pkcs11")]
+    #[test]
+    #[ignore]
+    fn test_pkcs11() {
+        use hsm::Pkcs11Module;
+        use std::env;
+
+        let module = env::var("PKCS11_MODULE").unwrap();
+        let slot = env::var("PKCS11_SLOT").map_or(0, |slot| slot.parse().unwrap());
+        let pin = env::var("PKCS11_PIN").unwrap();
+        let label = env::var("PKCS11_KEY_LABEL").unwrap();
+
+        let module = Pkcs11Module::load(module).unwrap();
+        let session = module.open_session(slot, &pin).unwrap();
+        let signer = HsmSigner::new(session.key(&label).unwrap()).unwrap();
+
+        let tx = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        for _ in 0..8 {
+            // tokens may return either `s`, so sign several times
+            let ecdsa = signer.ecdsa(&tx).unwrap();
+            assert_eq!(signer.address(), tx.recover(&ecdsa).unwrap());
+        }
+        match session.key("no such key") {
+            Err(HsmError::KeyNotFound(_)) => (),
+            r => panic!("Unexpected result: {:?}", r.is_ok()),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use fees::FeeError;
+
+/// Cost estimates of the Fjord fee function are scaled by 1e6.
+const FJORD_INTERCEPT: u128 = 42_585_600;
+const FJORD_FASTLZ_COEF: u128 = 836_500;
+const FJORD_MIN_TX_SIZE: u128 = 100 * 1_000_000;
+
+/// L1 fee parameters of an OP Stack chain, as stored in its `L1Block` predeploy and
+/// returned by the `GasPriceOracle` (`l1BaseFee`, `blobBaseFee`, `baseFeeScalar` and
+/// `blobBaseFeeScalar`).
+///
+/// The fee functions take the signed transaction bytes returned by
+/// [`sign`](::Transaction::sign), because that is what the sequencer posts to L1.
+/// ```
+/// use ethereum_tx_sign::{FeeMarketTransaction, L1FeeParams, Transaction};
+///
+/// let tx = FeeMarketTransaction { chain: 10, gas: 21000, ..Default::default() };
+/// let tx_bytes = tx.sign(&tx.ecdsa(&vec![0x35; 32]).unwrap());
+///
+/// let params = L1FeeParams {
+///     base_fee: 20_000_000_000,
+///     blob_base_fee: 1,
+///     base_fee_scalar: 1368,
+///     blob_base_fee_scalar: 810949,
+/// };
+/// let l1_fee = params.fjord_fee(&tx_bytes).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct L1FeeParams {
+    /// Base fee of the L1 origin block
+    pub base_fee: u128,
+    /// Blob base fee of the L1 origin block
+    pub blob_base_fee: u128,
+    /// Scalar applied to the base fee
+    pub base_fee_scalar: u32,
+    /// Scalar applied to the blob base fee
+    pub blob_base_fee_scalar: u32,
+}
+
+impl L1FeeParams {
+    /// L1 data fee since the Ecotone upgrade, which charges for calldata gas.
+    pub fn ecotone_fee(&self, tx_bytes: &[u8]) -> Result<u128, FeeError> {
+        calldata_gas(tx_bytes)
+            .checked_mul(self.fee_scaled()?)
+            .map(|fee| fee / (16 * 1_000_000))
+            .ok_or(FeeError::Overflow)
+    }
+
+    /// L1 data fee since the Fjord upgrade, which charges for the size the transaction is
+    /// estimated to have once compressed.
+    pub fn fjord_fee(&self, tx_bytes: &[u8]) -> Result<u128, FeeError> {
+        fjord_estimated_size(tx_bytes)
+            .checked_mul(self.fee_scaled()?)
+            .map(|fee| fee / 1_000_000_000_000)
+            .ok_or(FeeError::Overflow)
+    }
+
+    /// `16 * base_fee_scalar * base_fee + blob_base_fee_scalar * blob_base_fee`
+    fn fee_scaled(&self) -> Result<u128, FeeError> {
+        let base = (self.base_fee_scalar as u128 * 16).checked_mul(self.base_fee);
+        let blob = (self.blob_base_fee_scalar as u128).checked_mul(self.blob_base_fee);
+        base.and_then(|base| base.checked_add(blob?))
+            .ok_or(FeeError::Overflow)
+    }
+}
+
+/// Compressed size of the transaction that the Fjord fee is charged for, scaled by 1e6.
+/// It is a linear regression on the FastLZ compressed size, with a minimum of 100 bytes.
+pub fn fjord_estimated_size(tx_bytes: &[u8]) -> u128 {
+    let size = FJORD_FASTLZ_COEF * fastlz_compressed_len(tx_bytes) as u128;
+    size.saturating_sub(FJORD_INTERCEPT).max(FJORD_MIN_TX_SIZE)
+}
+
+/// Gas that the bytes would cost as L1 calldata: 4 per zero byte and 16 per other byte.
+fn calldata_gas(bytes: &[u8]) -> u128 {
+    bytes.iter().map(|b| if *b == 0 { 4 } else { 16 }).sum()
+}
+
+/// Length of the bytes once compressed with FastLZ (level 1), as computed by the OP Stack
+/// `LibZip.flzCompress` and op-geth. Only the length is computed, nothing is allocated
+/// for the output.
+pub fn fastlz_compressed_len(bytes: &[u8]) -> usize {
+    let mut len = 0;
+    let mut hash_table = vec![0usize; 8192];
+
+    let u24 = |i: usize| bytes[i] as u32 | (bytes[i + 1] as u32) << 8 | (bytes[i + 2] as u32) << 16;
+    let hash = |v: u32| (v.wrapping_mul(2654435769) >> 19) as usize & 0x1fff;
+    let literals = |len: &mut usize, run: usize| {
+        *len += 0x21 * (run / 0x20);
+        if run % 0x20 != 0 {
+            *len += run % 0x20 + 1;
+        }
+    };
+
+    let ip_limit = bytes.len().saturating_sub(13);
+    let mut anchor = 0;
+    let mut ip = 2;
+    while ip < ip_limit {
+        // look for a match of at least three bytes within 8 KiB
+        let mut reference;
+        loop {
+            let seq = u24(ip);
+            let h = hash(seq);
+            reference = hash_table[h];
+            hash_table[h] = ip;
+            let distance = ip - reference;
+            if ip >= ip_limit {
+                break;
+            }
+            ip += 1;
+            if distance <= 0x1fff && seq == u24(reference) {
+                break;
+            }
+        }
+        if ip >= ip_limit {
+            break;
+        }
+
+        ip -= 1;
+        if ip > anchor {
+            literals(&mut len, ip - anchor);
+        }
+
+        // the reference implementation counts the first mismatching byte as matched
+        let end = ip_limit + 9 - (ip + 3);
+        let mut match_len = 0;
+        while match_len < end {
+            if bytes[reference + 3 + match_len] != bytes[ip + 3 + match_len] {
+                match_len += 1;
+                break;
+            }
+            match_len += 1;
+        }
+
+        let l = match_len - 1;
+        len += 3 * (l / 262) + if l % 262 >= 6 { 3 } else { 2 };
+
+        ip += match_len;
+        hash_table[hash(u24(ip))] = ip;
+        ip += 1;
+        hash_table[hash(u24(ip))] = ip;
+        ip += 1;
+        anchor = ip;
+    }
+    literals(&mut len, bytes.len() - anchor);
+    len
+}
+
+#[cfg(test)]
+mod test {
+    use l1_fee::{calldata_gas, fastlz_compressed_len, fjord_estimated_size, L1FeeParams};
+    use FeeError;
+
+    // op-geth's rollup cost tests: the unsigned legacy transaction to
+    // 0x095e7baea6a6c7c4c2dfeb977efac326af552d87 with all other fields zero
+    const EMPTY_TX: &str = "dd80808094095e7baea6a6c7c4c2dfeb977efac326af552d878080808080";
+
+    fn params() -> L1FeeParams {
+        L1FeeParams {
+            base_fee: 1000 * 1_000_000,
+            blob_base_fee: 10 * 1_000_000,
+            base_fee_scalar: 2,
+            blob_base_fee_scalar: 3,
+        }
+    }
+
+    #[test]
+    fn test_ecotone_fee() {
+        let tx = hex::decode(EMPTY_TX).unwrap();
+        assert_eq!(480, calldata_gas(&tx));
+        // (480 / 16) * (2 * 16 * 1000 + 3 * 10)
+        assert_eq!(Ok(960_900), params().ecotone_fee(&tx));
+    }
+
+    #[test]
+    fn test_fjord_fee() {
+        // too small for the linear regression, so the minimum size is used
+        let tx = hex::decode(EMPTY_TX).unwrap();
+        assert_eq!(100_000_000, fjord_estimated_size(&tx));
+        // 100_000_000 * (2 * 1000 * 1e6 * 16 + 3 * 10 * 1e6) / 1e12
+        assert_eq!(Ok(3_203_000), params().fjord_fee(&tx));
+
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7919 % 251) as u8).collect();
+        let size = fastlz_compressed_len(&data) as u128;
+        assert_eq!(size * 836_500 - 42_585_600, fjord_estimated_size(&data));
+    }
+
+    #[test]
+    fn test_fastlz_compressed_len() {
+        assert_eq!(0, fastlz_compressed_len(&[]));
+        // literal runs take one control byte per 32 bytes
+        assert_eq!(6, fastlz_compressed_len(&[1, 2, 3, 4, 5]));
+
+        // repeated data compresses to a fraction of its size
+        let zeroes = vec![0u8; 10_000];
+        assert!(fastlz_compressed_len(&zeroes) < 200);
+        let distinct: Vec<u8> = (0..=255u8).collect();
+        assert_eq!(256 + 8, fastlz_compressed_len(&distinct));
+    }
+
+    #[test]
+    fn test_fee_overflow() {
+        let params = L1FeeParams {
+            base_fee: u128::MAX / 2,
+            ..params()
+        };
+        assert_eq!(Err(FeeError::Overflow), params.ecotone_fee(&[1]));
+    }
+}
@@ -0,0 +1,749 @@
+//! Signing with the Ethereum app on a Ledger device.
+//!
+//! Requests to the app are [APDUs](Apdu) sent over a [`LedgerTransport`], such as USB HID or
+//! a Speculos emulator; [`MockLedger`] answers them with a local key for tests. Transactions
+//! are sent as their [`signing_payload`](::Transaction::signing_payload), in chunks the app
+//! reassembles before asking the user to confirm.
+//!
+//! ```
+//! use ethereum_tx_sign::ledger::{parse_path, Ledger, MockLedger};
+//! use ethereum_tx_sign::{FeeMarketTransaction, PrivateKey, Transaction};
+//!
+//! let ledger = Ledger::new(MockLedger::new(PrivateKey::from_slice(&[0x35; 32]).unwrap()));
+//! let path = parse_path("m/44'/60'/0'/0/0").unwrap();
+//! let account = ledger.address(&path, false).unwrap();
+//!
+//! let tx = FeeMarketTransaction { chain: 1, gas: 21000, ..Default::default() };
+//! let ecdsa = ledger.ecdsa(&path, &tx).unwrap();
+//! let tx_bytes = tx.sign_verified(&ecdsa, &account.address).unwrap();
+//! ```
+use rlp::Rlp;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+use {keccak256_hash, public_key_to_address, EcdsaSig, Error, PrivateKey, Transaction};
+
+/// Instruction class of the Ethereum app.
+pub const CLA: u8 = 0xe0;
+pub const INS_GET_ADDRESS: u8 = 0x02;
+pub const INS_SIGN_TRANSACTION: u8 = 0x04;
+pub const INS_SIGN_PERSONAL_MESSAGE: u8 = 0x08;
+/// `P1` of the first chunk of a payload.
+pub const P1_FIRST_CHUNK: u8 = 0x00;
+/// `P1` of the chunks after the first.
+pub const P1_MORE_CHUNKS: u8 = 0x80;
+/// Status word of a successful request.
+pub const SW_OK: u16 = 0x9000;
+/// Status word when the user declines on the device.
+pub const SW_DENIED: u16 = 0x6985;
+/// Largest data an APDU can carry.
+pub const MAX_APDU_DATA: usize = 255;
+/// Most BIP-32 path components the app accepts.
+pub const MAX_PATH_LEN: usize = 10;
+
+const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Debug)]
+pub enum LedgerError {
+    /// The transport could not exchange the APDU with the device.
+    Transport(String),
+    /// The app answered with a status word other than [`SW_OK`].
+    Status(u16),
+    /// The BIP-32 path is not valid or too long.
+    InvalidPath(String),
+    /// The device's answer could not be understood.
+    InvalidResponse(&'static str),
+    /// The device's signature could not be used.
+    Signature(Error),
+    /// The APDU's data is longer than [`MAX_APDU_DATA`].
+    DataTooLong(usize),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LedgerError::Transport(ref message) => write!(f, "transport error: {}", message),
+            LedgerError::Status(SW_DENIED) => write!(f, "denied by the user"),
+            LedgerError::Status(status) => write!(f, "device returned status {:#06x}", status),
+            LedgerError::InvalidPath(ref path) => write!(f, "invalid BIP-32 path: {}", path),
+            LedgerError::InvalidResponse(message) => {
+                write!(f, "invalid response from device: {}", message)
+            }
+            LedgerError::Signature(ref err) => write!(f, "invalid signature: {:?}", err),
+            LedgerError::DataTooLong(len) => {
+                write!(f, "APDU data is {} bytes, at most {}", len, MAX_APDU_DATA)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+impl From<Error> for LedgerError {
+    fn from(error: Error) -> Self {
+        LedgerError::Signature(error)
+    }
+}
+
+/// A command to the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Apdu {
+    pub cla: u8,
+    pub ins: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub data: Vec<u8>,
+}
+
+impl Apdu {
+    /// `CLA || INS || P1 || P2 || Lc || data`, or an error when the data is too long for the
+    /// one byte `Lc`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, LedgerError> {
+        if self.data.len() > MAX_APDU_DATA {
+            return Err(LedgerError::DataTooLong(self.data.len()));
+        }
+        let mut bytes = vec![self.cla, self.ins, self.p1, self.p2, self.data.len() as u8];
+        bytes.extend_from_slice(&self.data);
+        Ok(bytes)
+    }
+}
+
+/// Something that can exchange APDUs with a device.
+pub trait LedgerTransport {
+    /// Send the APDU and return the response, including the trailing status word.
+    fn exchange(&self, apdu: &Apdu) -> Result<Vec<u8>, LedgerError>;
+}
+
+impl<T: LedgerTransport + ?Sized> LedgerTransport for &T {
+    fn exchange(&self, apdu: &Apdu) -> Result<Vec<u8>, LedgerError> {
+        (**self).exchange(apdu)
+    }
+}
+
+/// Parse a BIP-32 path such as `m/44'/60'/0'/0/0`. Hardened components may be marked with
+/// `'` or `h`.
+pub fn parse_path(path: &str) -> Result<Vec<u32>, LedgerError> {
+    let invalid = || LedgerError::InvalidPath(path.to_string());
+    let components = path.strip_prefix("m/").unwrap_or(path);
+    let path = components
+        .split('/')
+        .map(|component| {
+            let (index, hardened) = match component.strip_suffix(&['\'', 'h'][..]) {
+                Some(index) => (index, HARDENED),
+                None => (component, 0),
+            };
+            match index.parse::<u32>() {
+                Ok(index) if index < HARDENED => Ok(index | hardened),
+                _ => Err(invalid()),
+            }
+        })
+        .collect::<Result<Vec<u32>, LedgerError>>()?;
+    if path.len() > MAX_PATH_LEN {
+        return Err(invalid());
+    }
+    Ok(path)
+}
+
+/// The path as the app expects it: the number of components, then each as 4 bytes.
+fn encode_path(path: &[u32]) -> Result<Vec<u8>, LedgerError> {
+    if path.is_empty() || path.len() > MAX_PATH_LEN {
+        return Err(LedgerError::InvalidPath(format!("{:?}", path)));
+    }
+    let mut bytes = vec![path.len() as u8];
+    for component in path {
+        bytes.extend_from_slice(&component.to_be_bytes());
+    }
+    Ok(bytes)
+}
+
+/// The APDU asking for the public key and address at `path`, optionally showing the
+/// address on the device for the user to check.
+pub fn get_address_apdu(path: &[u32], display: bool) -> Result<Apdu, LedgerError> {
+    Ok(Apdu {
+        cla: CLA,
+        ins: INS_GET_ADDRESS,
+        p1: display as u8,
+        p2: 0x00,
+        data: encode_path(path)?,
+    })
+}
+
+/// Split `header || payload` into APDUs. `chunk_end` may end a chunk earlier than the
+/// offset it is given, the most that fits.
+fn chunked<F>(ins: u8, header: Vec<u8>, payload: &[u8], chunk_end: F) -> Vec<Apdu>
+where
+    F: Fn(usize) -> usize,
+{
+    let mut apdus = vec![];
+    let (mut offset, mut data) = (0, header);
+    loop {
+        let end = chunk_end(offset + MAX_APDU_DATA - data.len());
+        let end = end.min(payload.len());
+        data.extend_from_slice(&payload[offset..end]);
+        let p1 = match apdus.is_empty() {
+            true => P1_FIRST_CHUNK,
+            false => P1_MORE_CHUNKS,
+        };
+        apdus.push(Apdu {
+            cla: CLA,
+            ins,
+            p1,
+            p2: 0x00,
+            data,
+        });
+        offset = end;
+        if offset == payload.len() {
+            return apdus;
+        }
+        data = vec![];
+    }
+}
+
+/// The APDUs that sign the transaction with the key at `path`.
+///
+/// Typed transactions are sent as the type byte followed by the RLP list. Legacy
+/// transactions end with the EIP-155 fields `chain, 0, 0`; the app decides that a legacy
+/// transaction has no such fields if a chunk ends right before them, so they are never
+/// sent on their own.
+pub fn sign_transaction_apdus<T: Transaction>(
+    path: &[u32],
+    tx: &T,
+) -> Result<Vec<Apdu>, LedgerError> {
+    let payload = tx.signing_payload();
+    let eip155_offset = match T::transaction_type() {
+        Some(_) => payload.len(),
+        None => payload.len() - rlp::encode(&tx.chain()).len() - 2,
+    };
+    let len = payload.len();
+    Ok(chunked(
+        INS_SIGN_TRANSACTION,
+        encode_path(path)?,
+        &payload,
+        |end| match end >= eip155_offset && end < len {
+            true => eip155_offset - 1,
+            false => end,
+        },
+    ))
+}
+
+/// The APDUs that sign `message` as `personal_sign` does, with the key at `path`.
+pub fn sign_personal_message_apdus(path: &[u32], message: &[u8]) -> Result<Vec<Apdu>, LedgerError> {
+    let mut header = encode_path(path)?;
+    header.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    Ok(chunked(INS_SIGN_PERSONAL_MESSAGE, header, message, |end| {
+        end
+    }))
+}
+
+/// Parse the app's `v || r || s` answer. For legacy transactions, pass the `chain`: the app
+/// only returns the lowest byte of the EIP-155 `v`, so the y-parity is recovered from it.
+pub fn parse_signature(response: &[u8], chain: Option<u64>) -> Result<EcdsaSig, LedgerError> {
+    if response.len() != 65 {
+        return Err(LedgerError::InvalidResponse("signature is not 65 bytes"));
+    }
+    let v = match chain {
+        Some(c) => {
+            let offset = (c as u128 * 2 + 35) as u8;
+            match response[0].wrapping_sub(offset) {
+                parity @ 0..=1 => parity as u64,
+                _ => return Err(LedgerError::InvalidResponse("v is not for the chain")),
+            }
+        }
+        None => response[0] as u64,
+    };
+    Ok(EcdsaSig::from_parts(
+        &response[1..33],
+        &response[33..],
+        v,
+        chain,
+    )?)
+}
+
+/// An account on the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerAddress {
+    /// Uncompressed public key
+    pub public_key: Vec<u8>,
+    pub address: [u8; 20],
+}
+
+/// Parse the answer to [`get_address_apdu`]: the public key and the address as hex, each
+/// preceded by its length.
+pub fn parse_address(response: &[u8]) -> Result<LedgerAddress, LedgerError> {
+    let invalid = LedgerError::InvalidResponse("malformed address");
+    let (public_key, rest) = match response.split_first() {
+        Some((&len, rest)) if rest.len() > len as usize => rest.split_at(len as usize),
+        _ => return Err(invalid),
+    };
+    let address = match rest.split_first() {
+        Some((&40, rest)) if rest.len() >= 40 => &rest[..40],
+        _ => return Err(invalid),
+    };
+    let mut parsed = [0u8; 20];
+    hex::decode_to_slice(address, &mut parsed).map_err(|_| invalid)?;
+    // the address is derived on the device, so check that it belongs to the key
+    if public_key_to_address(public_key)? != parsed {
+        return Err(LedgerError::InvalidResponse(
+            "address does not match public key",
+        ));
+    }
+    Ok(LedgerAddress {
+        public_key: public_key.to_vec(),
+        address: parsed,
+    })
+}
+
+/// The Ethereum app on a device reached through `T`.
+#[derive(Debug)]
+pub struct Ledger<T: LedgerTransport> {
+    transport: T,
+}
+
+impl<T: LedgerTransport> Ledger<T> {
+    pub fn new(transport: T) -> Self {
+        Ledger { transport }
+    }
+
+    /// The account at `path`. With `display`, the device shows the address and waits for
+    /// the user to confirm it.
+    pub fn address(&self, path: &[u32], display: bool) -> Result<LedgerAddress, LedgerError> {
+        parse_address(&self.send(&get_address_apdu(path, display)?)?)
+    }
+
+    /// Have the user confirm and sign the transaction on the device.
+    pub fn ecdsa<X: Transaction>(&self, path: &[u32], tx: &X) -> Result<EcdsaSig, LedgerError> {
+        let chain = match X::transaction_type() {
+            Some(_) => None,
+            None => Some(tx.chain()),
+        };
+        parse_signature(&self.send_all(sign_transaction_apdus(path, tx)?)?, chain)
+    }
+
+    /// Have the user confirm and sign `message` on the device. `v` of the returned signature
+    /// is the y-parity.
+    pub fn sign_personal_message(
+        &self,
+        path: &[u32],
+        message: &[u8],
+    ) -> Result<EcdsaSig, LedgerError> {
+        let response = self.send_all(sign_personal_message_apdus(path, message)?)?;
+        parse_signature(&response, None)
+    }
+
+    /// Send the APDUs in order and return the data of the last answer.
+    fn send_all(&self, apdus: Vec<Apdu>) -> Result<Vec<u8>, LedgerError> {
+        let mut response = vec![];
+        for apdu in apdus.iter() {
+            response = self.send(apdu)?;
+        }
+        Ok(response)
+    }
+
+    /// Exchange the APDU and strip the status word from the answer.
+    fn send(&self, apdu: &Apdu) -> Result<Vec<u8>, LedgerError> {
+        let mut response = self.transport.exchange(apdu)?;
+        if response.len() < 2 {
+            return Err(LedgerError::InvalidResponse("missing status word"));
+        }
+        let status = response.split_off(response.len() - 2);
+        match u16::from_be_bytes([status[0], status[1]]) {
+            SW_OK => Ok(response),
+            status => Err(LedgerError::Status(status)),
+        }
+    }
+}
+
+/// Hash that `personal_sign` signs for `message`.
+fn personal_message_hash(message: &[u8]) -> [u8; 32] {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+    keccak256_hash(&prefixed)
+}
+
+/// A payload being received in chunks.
+#[derive(Debug)]
+struct Pending {
+    ins: u8,
+    payload: Vec<u8>,
+    /// Length of a personal message
+    message_len: Option<usize>,
+}
+
+/// In-memory device that answers like the Ethereum app, with one key for every path, for
+/// tests.
+///
+/// The user confirms every request unless [`deny`](MockLedger::deny) is called first. Every
+/// APDU that is exchanged is recorded.
+#[derive(Debug)]
+pub struct MockLedger {
+    key: PrivateKey,
+    pending: RefCell<Option<Pending>>,
+    denials: Cell<usize>,
+    apdus: RefCell<Vec<Apdu>>,
+}
+
+impl MockLedger {
+    /// A device holding `key`.
+    pub fn new(key: PrivateKey) -> Self {
+        MockLedger {
+            key,
+            pending: RefCell::new(None),
+            denials: Cell::new(0),
+            apdus: RefCell::new(vec![]),
+        }
+    }
+
+    /// Have the user decline the next request that needs confirming.
+    pub fn deny(&self) -> &Self {
+        self.denials.set(self.denials.get() + 1);
+        self
+    }
+
+    /// APDUs exchanged so far.
+    pub fn apdus(&self) -> Vec<Apdu> {
+        self.apdus.borrow().clone()
+    }
+
+    fn public_key(&self) -> [u8; 65] {
+        self.key.secret_key().public_key()
+    }
+
+    /// `v || r || s` of `hash`, with the y-parity in `v`.
+    fn sign(&self, hash: [u8; 32]) -> Vec<u8> {
+        let (rs, recovery_id) = self.key.secret_key().sign(&hash).unwrap();
+        let mut response = vec![recovery_id];
+        response.extend_from_slice(&rs);
+        response
+    }
+
+    /// Handle one APDU, answering with data or a status word.
+    fn handle(&self, apdu: &Apdu) -> Result<Vec<u8>, u16> {
+        // wrong data length
+        const SW_INVALID_DATA: u16 = 0x6a80;
+        if apdu.cla != CLA {
+            return Err(0x6e00);
+        }
+
+        let len = *apdu.data.first().ok_or(SW_INVALID_DATA)? as usize;
+        let path_len = 1 + len * 4;
+        let path_valid = len > 0 && len <= MAX_PATH_LEN && apdu.data.len() >= path_len;
+        if apdu.ins == INS_GET_ADDRESS {
+            if !path_valid {
+                return Err(SW_INVALID_DATA);
+            }
+            let public_key = self.public_key();
+            let address = hex::encode(public_key_to_address(&public_key).unwrap());
+            let mut response = vec![65];
+            response.extend_from_slice(&public_key);
+            response.push(40);
+            response.extend_from_slice(address.as_bytes());
+            return Ok(response);
+        }
+
+        let mut data = &apdu.data[..];
+        let mut pending = self.pending.borrow_mut();
+        if apdu.p1 == P1_FIRST_CHUNK {
+            if !path_valid {
+                return Err(SW_INVALID_DATA);
+            }
+            data = &data[path_len..];
+            let mut message_len = None;
+            if apdu.ins == INS_SIGN_PERSONAL_MESSAGE {
+                if data.len() < 4 {
+                    return Err(SW_INVALID_DATA);
+                }
+                message_len =
+                    Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize);
+                data = &data[4..];
+            }
+            *pending = Some(Pending {
+                ins: apdu.ins,
+                payload: vec![],
+                message_len,
+            });
+        }
+        let received = match *pending {
+            Some(ref mut received) if received.ins == apdu.ins && apdu.p1 == P1_MORE_CHUNKS => {
+                received
+            }
+            Some(ref mut received) if apdu.p1 == P1_FIRST_CHUNK => received,
+            _ => return Err(SW_INVALID_DATA),
+        };
+        received.payload.extend_from_slice(data);
+
+        let hash = match received.message_len {
+            Some(len) if received.payload.len() < len => return Ok(vec![]),
+            Some(len) if received.payload.len() > len => return Err(SW_INVALID_DATA),
+            Some(_) => personal_message_hash(&received.payload),
+            None => match transaction_complete(&received.payload)? {
+                false => return Ok(vec![]),
+                true => keccak256_hash(&received.payload),
+            },
+        };
+        let received = pending.take().unwrap();
+        if self.denials.get() > 0 {
+            self.denials.set(self.denials.get() - 1);
+            return Err(SW_DENIED);
+        }
+        let mut response = self.sign(hash);
+        match received.message_len {
+            Some(_) => response[0] += 27,
+            None if received.payload[0] >= 0xc0 => {
+                // legacy: the lowest byte of the EIP-155 `v`
+                let chain: u64 = Rlp::new(&received.payload)
+                    .val_at(6)
+                    .map_err(|_| SW_INVALID_DATA)?;
+                response[0] = (chain as u128 * 2 + 35 + response[0] as u128) as u8;
+            }
+            None => (),
+        }
+        Ok(response)
+    }
+}
+
+/// Whether the payload received so far is a whole transaction. Like the app, a legacy
+/// transaction that ends after its sixth field is taken to be complete and lacking the
+/// EIP-155 fields, which the mock refuses to sign.
+fn transaction_complete(payload: &[u8]) -> Result<bool, u16> {
+    let list = match payload.first() {
+        Some(&tx_type) if tx_type < 0x7f => &payload[1..],
+        _ => payload,
+    };
+    let info = match Rlp::new(list).payload_info() {
+        Ok(info) => info,
+        // the list header is split across chunks
+        Err(_) => return Ok(false),
+    };
+    let total = info.header_len + info.value_len;
+    let fields = &list[info.header_len..];
+    if payload[0] >= 0xc0 && !fields.is_empty() {
+        let mut offset = 0;
+        for i in 0..6 {
+            match Rlp::new(&fields[offset..]).payload_info() {
+                Ok(field) if offset + field.header_len + field.value_len <= fields.len() => {
+                    offset += field.header_len + field.value_len
+                }
+                _ => break,
+            }
+            if i == 5 && offset == fields.len() && list.len() < total {
+                return Err(0x6a80);
+            }
+        }
+    }
+    match list.len() {
+        len if len < total => Ok(false),
+        len if len == total => Ok(true),
+        _ => Err(0x6a80),
+    }
+}
+
+impl LedgerTransport for MockLedger {
+    fn exchange(&self, apdu: &Apdu) -> Result<Vec<u8>, LedgerError> {
+        self.apdus.borrow_mut().push(apdu.clone());
+        let (mut response, status) = match self.handle(apdu) {
+            Ok(data) => (data, SW_OK),
+            Err(status) => {
+                *self.pending.borrow_mut() = None;
+                (vec![], status)
+            }
+        };
+        response.extend_from_slice(&status.to_be_bytes());
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ledger::{
+        get_address_apdu, parse_path, parse_signature, personal_message_hash,
+        sign_personal_message_apdus, sign_transaction_apdus, Apdu, Ledger, LedgerError, MockLedger,
+        MAX_APDU_DATA, P1_FIRST_CHUNK, P1_MORE_CHUNKS, SW_DENIED,
+    };
+    use {AccessListTransaction, FeeMarketTransaction, LegacyTransaction, PrivateKey, Transaction};
+
+    const PATH: &str = "m/44'/60'/0'/0/0";
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(
+            vec![0x8000_002c, 0x8000_003c, 0x8000_0000, 0, 0],
+            parse_path(PATH).unwrap()
+        );
+        assert_eq!(
+            parse_path(PATH).unwrap(),
+            parse_path("44h/60h/0h/0/0").unwrap()
+        );
+        for path in ["m/44'/x", "m/2147483648", "m/", "0/0/0/0/0/0/0/0/0/0/0"].iter() {
+            match parse_path(path) {
+                Err(LedgerError::InvalidPath(_)) => (),
+                r => panic!("Unexpected result: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_apdu_encoding() {
+        let path = parse_path(PATH).unwrap();
+        let apdu = get_address_apdu(&path, true).unwrap();
+        assert_eq!(
+            "e002010015058000002c8000003c800000000000000000000000",
+            hex::encode(apdu.to_bytes().unwrap())
+        );
+
+        // chunks carry the path once, then the rest of the payload
+        let tx = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            data: vec![0xab; 600],
+            ..Default::default()
+        };
+        let apdus = sign_transaction_apdus(&path, &tx).unwrap();
+        assert_eq!(3, apdus.len());
+        assert_eq!(P1_FIRST_CHUNK, apdus[0].p1);
+        assert!(apdus[1..].iter().all(|apdu| apdu.p1 == P1_MORE_CHUNKS));
+        assert!(apdus.iter().all(|apdu| apdu.data.len() <= MAX_APDU_DATA));
+        assert_eq!(0x02, apdus[0].data[21]);
+        let payload: Vec<u8> = apdus.iter().flat_map(|apdu| apdu.data.clone()).collect();
+        assert_eq!(tx.signing_payload(), payload[21..].to_vec());
+
+        let apdus = sign_personal_message_apdus(&path, b"hello").unwrap();
+        assert_eq!(
+            "e00800001e058000002c8000003c80000000000000000000000000000005",
+            hex::encode(&apdus[0].to_bytes().unwrap()[..30])
+        );
+        assert_eq!(b"hello", &apdus[0].data[25..]);
+
+        // `Lc` is one byte
+        let apdu = Apdu {
+            data: vec![0; MAX_APDU_DATA + 1],
+            ..apdu
+        };
+        match apdu.to_bytes() {
+            Err(LedgerError::DataTooLong(256)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_legacy_chunk_boundary() {
+        let path = parse_path(PATH).unwrap();
+        // every length of data puts a chunk boundary somewhere new
+        for len in 150..=300 {
+            let tx = LegacyTransaction {
+                chain: 1,
+                gas: 21000,
+                to: Some([0x45; 20]),
+                data: vec![0xab; len],
+                ..Default::default()
+            };
+            let apdus = sign_transaction_apdus(&path, &tx).unwrap();
+            let payload = tx.signing_payload();
+            let mut sent = 0;
+            for apdu in apdus.iter() {
+                sent += apdu.data.len() - if apdu.p1 == P1_FIRST_CHUNK { 21 } else { 0 };
+                assert_ne!(payload.len() - 3, sent);
+                assert!(apdu.data.len() <= MAX_APDU_DATA);
+            }
+            assert_eq!(payload.len(), sent);
+
+            let ecdsa = Ledger::new(MockLedger::new(
+                PrivateKey::from_slice(&[0x35; 32]).unwrap(),
+            ))
+            .ecdsa(&path, &tx)
+            .unwrap();
+            assert_eq!(tx.ecdsa(&[0x35; 32]).unwrap(), ecdsa);
+        }
+    }
+
+    #[test]
+    fn test_mock_ledger() {
+        let device = MockLedger::new(PrivateKey::from_slice(&[0x35; 32]).unwrap());
+        let ledger = Ledger::new(&device);
+        let path = parse_path(PATH).unwrap();
+        let account = ledger.address(&path, false).unwrap();
+        assert_eq!(
+            "8b11c43881b3f9148493c2edad543e97efdc517a",
+            hex::encode(account.address)
+        );
+
+        let legacy = LegacyTransaction {
+            chain: 1,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            ..Default::default()
+        };
+        let ecdsa = ledger.ecdsa(&path, &legacy).unwrap();
+        assert_eq!(legacy.ecdsa(&[0x35; 32]).unwrap(), ecdsa);
+
+        // a chain ID whose EIP-155 `v` does not fit in the byte the app returns
+        let legacy = LegacyTransaction {
+            chain: 11_155_111,
+            ..legacy
+        };
+        let ecdsa = ledger.ecdsa(&path, &legacy).unwrap();
+        assert_eq!(legacy.ecdsa(&[0x35; 32]).unwrap(), ecdsa);
+
+        let access_list = AccessListTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        let ecdsa = ledger.ecdsa(&path, &access_list).unwrap();
+        assert_eq!(access_list.ecdsa(&[0x35; 32]).unwrap(), ecdsa);
+        assert!(access_list.sign_verified(&ecdsa, &account.address).is_ok());
+
+        let ecdsa = ledger.sign_personal_message(&path, &[0x61; 300]).unwrap();
+        assert!(ecdsa.v <= 1);
+        assert_eq!(
+            account.address,
+            ecdsa
+                .recover(personal_message_hash(&[0x61; 300]), None)
+                .unwrap()
+        );
+
+        device.deny();
+        match ledger.ecdsa(&path, &access_list) {
+            Err(LedgerError::Status(SW_DENIED)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert_eq!(7, device.apdus().len());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let ecdsa = LegacyTransaction {
+            chain: 1,
+            ..Default::default()
+        }
+        .ecdsa(&[0x35; 32])
+        .unwrap();
+        let mut response = vec![ecdsa.v as u8];
+        response.extend_from_slice(&ecdsa.r);
+        response.extend_from_slice(&ecdsa.s);
+        assert_eq!(ecdsa, parse_signature(&response, Some(1)).unwrap());
+        match parse_signature(&response, Some(5)) {
+            Err(LedgerError::InvalidResponse(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match parse_signature(&response[1..], None) {
+            Err(LedgerError::InvalidResponse(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // an APDU the app does not understand
+        let ledger = Ledger::new(MockLedger::new(
+            PrivateKey::from_slice(&[0x35; 32]).unwrap(),
+        ));
+        let apdu = Apdu {
+            cla: 0xe0,
+            ins: 0x04,
+            p1: P1_MORE_CHUNKS,
+            p2: 0,
+            data: vec![],
+        };
+        match ledger.send(&apdu) {
+            Err(LedgerError::Status(0x6a80)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
@@ -4,25 +4,81 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate bytes;
+extern crate crc32fast;
+extern crate getrandom;
 extern crate hex;
+#[cfg(feature = "k256")]
+extern crate k256;
+#[cfg(feature = "pkcs11")]
+extern crate libloading;
 extern crate num_traits;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 extern crate rlp;
+#[cfg(any(test, feature = "secp256k1"))]
 extern crate secp256k1;
+extern crate serde_json;
+extern crate sha2;
 extern crate tiny_keccak;
+#[cfg(feature = "http")]
+extern crate ureq;
+extern crate zeroize;
 
 #[cfg(test)]
 extern crate ethereum_types;
-#[cfg(test)]
-extern crate serde_json;
 
+use bytes::BufMut;
 use rlp::{Encodable, RlpStream};
-use secp256k1::{Message, Secp256k1, SecretKey};
 use serde::de::Error as SerdeErr;
 use serde::ser::SerializeSeq;
 use serde::Deserialize;
 use std::convert::TryInto;
 use tiny_keccak::{Hasher, Keccak};
 
+mod abi;
+mod backend;
+mod batch;
+mod chains;
+#[cfg(test)]
+mod conformance;
+mod decode;
+mod deposit;
+mod encode;
+mod fees;
+pub mod filler;
+pub mod hsm;
+mod l1_fee;
+pub mod ledger;
+mod nonce;
+mod private_key;
+mod replacement;
+pub mod rpc;
+pub mod safe;
+mod signature;
+mod signed;
+pub mod transport;
+mod units;
+pub mod ur;
+pub mod user_operation;
+mod validation;
+
+pub use backend::Secp256k1Error;
+pub use batch::{ecdsa_batch, sign_batch};
+pub use chains::{Chain, ChainError, ChainRegistry, ChainWarning};
+pub use decode::{DecodeError, TypedTransaction};
+pub use deposit::{DepositTransaction, DEPOSIT_TX_TYPE};
+use encode::KeccakWriter;
+pub use encode::{list_header_len, put_list_header, RlpField};
+pub use fees::{FeeBreakdown, FeeError};
+pub use l1_fee::{fastlz_compressed_len, fjord_estimated_size, L1FeeParams};
+pub use nonce::{check_nonces, NonceError, NonceIssue, NonceManager, Nonced};
+pub use private_key::{PrivateKey, ToPrivateKey};
+pub use replacement::{FeeBump, MIN_FEE_BUMP_PERCENT};
+pub use signature::public_key_to_address;
+pub use signed::{SignedTransaction, SignedTransactionError};
+pub use units::{Unit, UnitError, Wei};
+pub use validation::{ValidationError, MAX_INITCODE_SIZE, MAX_TX_SIZE};
+
 /// Ethereum transaction
 pub trait Transaction {
     /// [EIP-155](https://github.com/ethereum/EIPs/blob/master/EIPS/eip-155.md) chain ID
@@ -30,32 +86,44 @@ pub trait Transaction {
 
     /// Compute the unique transaction hash
     fn hash(&self) -> [u8; 32] {
-        let rlp = self.rlp_parts();
-        let mut rlp_stream = RlpStream::new();
-        rlp_stream.begin_unbounded_list();
-        for r in rlp.iter() {
-            rlp_stream.append(r);
-        }
+        let mut hasher = KeccakWriter::new();
+        self.encode_signing_payload(&mut hasher);
+        hasher.finalize()
+    }
 
-        // `None` means it is legacy
-        if Self::transaction_type().is_none() {
-            rlp_stream.append(&self.chain());
-            rlp_stream.append_raw(&[0x80], 1);
-            rlp_stream.append_raw(&[0x80], 1);
-        }
+    /// The unsigned [EIP-2718](https://eips.ethereum.org/EIPS/eip-2718) payload that
+    /// [`hash`](Transaction::hash) is computed over. These are the exact bytes an
+    /// offline signer needs; see [`sign_verified`](Transaction::sign_verified).
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.signing_payload_len());
+        self.encode_signing_payload(&mut payload);
+        payload
+    }
 
-        rlp_stream.finalize_unbounded_list();
-        let mut rlp_bytes = rlp_stream.out().to_vec();
+    /// Length of the [`signing_payload`](Transaction::signing_payload).
+    fn signing_payload_len(&self) -> usize {
+        let payload_len = self.fields_len() + eip155_fields_len(self);
+        let type_len = Self::transaction_type().map_or(0, |_| 1);
+        type_len + list_header_len(payload_len) + payload_len
+    }
 
-        if let Some(tt) = Self::transaction_type() {
-            rlp_bytes.insert(0usize, tt);
+    /// Write the [`signing_payload`](Transaction::signing_payload) to `out`.
+    fn encode_signing_payload<B: BufMut>(&self, out: &mut B) {
+        if let Some(tx_type) = Self::transaction_type() {
+            out.put_u8(tx_type);
+        }
+        put_list_header(self.fields_len() + eip155_fields_len(self), out);
+        self.encode_fields(out);
+        // legacy transactions are signed with the chain ID in place of the signature
+        if Self::transaction_type().is_none() {
+            self.chain().rlp_encode(out);
+            out.put_slice(&[0x80, 0x80]);
         }
-
-        keccak256_hash(&rlp_bytes)
     }
 
     /// Compute the [ECDSA](https://en.wikipedia.org/wiki/Elliptic_Curve_Digital_Signature_Algorithm) for the transaction
-    fn ecdsa(&self, private_key: &[u8]) -> Result<EcdsaSig, Error> {
+    /// with a [`PrivateKey`] or its 32 bytes.
+    fn ecdsa<K: ToPrivateKey + ?Sized>(&self, private_key: &K) -> Result<EcdsaSig, Error> {
         let hash = self.hash();
 
         let chain = match Self::transaction_type() {
@@ -66,6 +134,86 @@ pub trait Transaction {
         EcdsaSig::generate(hash, private_key, chain)
     }
 
+    /// Recover the address of the account that produced the signature.
+    fn recover(&self, ecdsa: &EcdsaSig) -> Result<[u8; 20], Error> {
+        let chain = match Self::transaction_type() {
+            Some(_) => None,
+            None => Some(self.chain()),
+        };
+
+        ecdsa.recover(self.hash(), chain)
+    }
+
+    /// Encode this transaction with a signature produced elsewhere, for example by an
+    /// air-gapped device that signed the [`signing_payload`](Transaction::signing_payload).
+    /// The signature must recover to `signer`.
+    /// ```
+    /// use ethereum_tx_sign::{FeeMarketTransaction, Transaction};
+    ///
+    /// let tx = FeeMarketTransaction { chain: 1, gas: 21000, ..Default::default() };
+    /// let payload = tx.signing_payload();
+    /// // `payload` is signed on the offline device, which returns the signature
+    /// // and the address of its key
+    /// # let ecdsa = tx.ecdsa(&vec![0x35; 32]).unwrap();
+    /// # let signer = tx.recover(&ecdsa).unwrap();
+    /// let tx_bytes = tx.sign_verified(&ecdsa, &signer).unwrap();
+    /// ```
+    fn sign_verified(&self, ecdsa: &EcdsaSig, signer: &[u8; 20]) -> Result<Vec<u8>, Error> {
+        let recovered = self.recover(ecdsa)?;
+        if recovered != *signer {
+            return Err(Error::SignerMismatch {
+                expected: *signer,
+                recovered,
+            });
+        }
+
+        Ok(self.sign(ecdsa))
+    }
+
+    /// Like [`sign_verified`](Transaction::sign_verified), but takes the 65 byte `r || s || v`
+    /// signature most signing devices return. `v` may be the recovery ID (0 or 1) or the
+    /// recovery ID plus 27. As `v` is a single byte, an EIP-155 value only fits for chain IDs
+    /// below 110; build the signature with [`EcdsaSig::from_parts`] otherwise.
+    fn sign_verified_rsv(&self, signature: &[u8], signer: &[u8; 20]) -> Result<Vec<u8>, Error> {
+        let chain = match Self::transaction_type() {
+            Some(_) => None,
+            None => Some(self.chain()),
+        };
+        let ecdsa = EcdsaSig::from_rsv(signature, chain)?;
+
+        self.sign_verified(&ecdsa, signer)
+    }
+
+    /// Convert the DER signature of [`hash`](Transaction::hash) that a key management service
+    /// or HSM returns into the [`EcdsaSig`] to [`sign`](Transaction::sign) with. The signature
+    /// must be from `signer`; use [`public_key_to_address`] when only the key's public key
+    /// is known.
+    /// ```
+    /// # extern crate ethereum_tx_sign;
+    /// # extern crate secp256k1;
+    /// use ethereum_tx_sign::{public_key_to_address, FeeMarketTransaction, Transaction};
+    ///
+    /// let tx = FeeMarketTransaction { chain: 1, gas: 21000, ..Default::default() };
+    /// let digest = tx.hash();
+    /// // the KMS signs `digest` and returns a DER signature; its public key is fetched once
+    /// # let secp = secp256k1::Secp256k1::new();
+    /// # let key = secp256k1::SecretKey::from_slice(&[0x35; 32]).unwrap();
+    /// # let msg = secp256k1::Message::from_slice(&digest).unwrap();
+    /// # let der = secp.sign_ecdsa(&msg, &key).serialize_der().to_vec();
+    /// # let public_key = secp256k1::PublicKey::from_secret_key(&secp, &key).serialize_uncompressed();
+    /// let signer = public_key_to_address(&public_key).unwrap();
+    /// let ecdsa = tx.ecdsa_from_der(&der, &signer).unwrap();
+    /// let tx_bytes = tx.sign(&ecdsa);
+    /// ```
+    fn ecdsa_from_der(&self, der: &[u8], signer: &[u8; 20]) -> Result<EcdsaSig, Error> {
+        let chain = match Self::transaction_type() {
+            Some(_) => None,
+            None => Some(self.chain()),
+        };
+
+        EcdsaSig::from_der(der, self.hash(), signer, chain)
+    }
+
     /// Sign and encode this transaction using the given ECDSA signature.
     /// Signing is done in two steps. Example:
     /// ```
@@ -83,12 +231,39 @@ pub trait Transaction {
     /// let ecdsa = tx.ecdsa(&vec![0x35; 32]).unwrap();
     /// let tx_bytes = tx.sign(&ecdsa);
     /// ```
-    fn sign(&self, ecdsa: &EcdsaSig) -> Vec<u8>;
+    fn sign(&self, ecdsa: &EcdsaSig) -> Vec<u8> {
+        let mut tx_bytes = Vec::with_capacity(self.signed_len(ecdsa));
+        self.encode_signed(ecdsa, &mut tx_bytes);
+        tx_bytes
+    }
+
+    /// Length of the transaction [`sign`](Transaction::sign) encodes with `ecdsa`.
+    fn signed_len(&self, ecdsa: &EcdsaSig) -> usize {
+        let payload_len = self.fields_len() + signature_len(ecdsa);
+        let type_len = Self::transaction_type().map_or(0, |_| 1);
+        type_len + list_header_len(payload_len) + payload_len
+    }
 
-    /// Return the fields of the transaction as a list of RLP-encodable
-    /// parts. The parts must follow the order that they will be encoded,
-    /// hashed, or signed.
-    fn rlp_parts(&self) -> Vec<Box<dyn Encodable>>;
+    /// Write the transaction signed with `ecdsa` to `out`, as [`sign`](Transaction::sign)
+    /// returns it.
+    fn encode_signed<B: BufMut>(&self, ecdsa: &EcdsaSig, out: &mut B) {
+        if let Some(tx_type) = Self::transaction_type() {
+            out.put_u8(tx_type);
+        }
+        put_list_header(self.fields_len() + signature_len(ecdsa), out);
+        self.encode_fields(out);
+        ecdsa.v.rlp_encode(out);
+        strip_leading_zeroes(&ecdsa.r).rlp_encode(out);
+        strip_leading_zeroes(&ecdsa.s).rlp_encode(out);
+    }
+
+    /// Length of the encoded fields, as written by
+    /// [`encode_fields`](Transaction::encode_fields).
+    fn fields_len(&self) -> usize;
+
+    /// Write the RLP encoding of each field of the transaction to `out`, in the order that
+    /// they are hashed and signed, without the header of the list that holds them.
+    fn encode_fields<B: BufMut>(&self, out: &mut B);
 
     /// Returns the transaction defined as TransactionType in [EIP-2718](https://eips.ethereum.org/EIPS/eip-2718).
     /// LegacyTransactions do not have a type, so will return None.
@@ -96,55 +271,77 @@ pub trait Transaction {
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    Secp256k1(secp256k1::Error),
+    /// A key or signature was rejected by the secp256k1 implementation.
+    Secp256k1(Secp256k1Error),
+    /// The chain ID is too large to be encoded in the signature's `v` value.
+    ChainIdOverflow(u64),
+    /// The signature's `v` value does not hold a valid recovery ID for the transaction.
+    InvalidV(u64),
+    /// A raw signature does not have the expected length.
+    InvalidSignatureLength(usize),
+    /// The signature was not made by the expected account.
+    SignerMismatch {
+        expected: [u8; 20],
+        recovered: [u8; 20],
+    },
+    /// A signature given as hex could not be decoded.
+    Hex(hex::FromHexError),
+    /// A signature without a recovery ID does not recover to the expected account with
+    /// either ID.
+    UnknownSigner([u8; 20]),
 }
 
-impl From<secp256k1::Error> for Error {
-    fn from(error: secp256k1::Error) -> Self {
+impl From<Secp256k1Error> for Error {
+    fn from(error: Secp256k1Error) -> Self {
         Error::Secp256k1(error)
     }
 }
 
-/// Internal function that avoids duplicating a lot of signing code
-fn sign_bytes<T: Transaction>(tx_type: Option<u8>, ecdsa: &EcdsaSig, t: &T) -> Vec<u8> {
-    let mut rlp_stream = RlpStream::new();
-    let rlp = t.rlp_parts();
-    rlp_stream.begin_unbounded_list();
-    for r in rlp.iter() {
-        rlp_stream.append(r);
+#[cfg(feature = "secp256k1")]
+impl From<secp256k1::Error> for Error {
+    fn from(error: secp256k1::Error) -> Self {
+        Error::Secp256k1(error.into())
     }
-    let EcdsaSig { v, s, r } = ecdsa;
+}
 
-    // removes leading zeroes
-    let mut r_n = r.clone();
-    let mut s_n = s.clone();
-    while r_n[0] == 0 {
-        r_n.remove(0);
-    }
-    while s_n[0] == 0 {
-        s_n.remove(0);
+impl From<hex::FromHexError> for Error {
+    fn from(error: hex::FromHexError) -> Self {
+        Error::Hex(error)
     }
+}
 
-    rlp_stream.append(v);
-    rlp_stream.append(&r_n);
-    rlp_stream.append(&s_n);
+/// Length of the fields that [EIP-155](https://eips.ethereum.org/EIPS/eip-155) appends to the
+/// signing payload of legacy transactions.
+fn eip155_fields_len<T: Transaction + ?Sized>(tx: &T) -> usize {
+    match T::transaction_type() {
+        Some(_) => 0,
+        None => tx.chain().rlp_len() + 2,
+    }
+}
 
-    rlp_stream.finalize_unbounded_list();
+/// Length of the encoded `v`, `r` and `s` of a signed transaction.
+fn signature_len(ecdsa: &EcdsaSig) -> usize {
+    ecdsa.v.rlp_len()
+        + strip_leading_zeroes(&ecdsa.r).rlp_len()
+        + strip_leading_zeroes(&ecdsa.s).rlp_len()
+}
 
-    let mut vec = rlp_stream.out().to_vec();
-    if let Some(b) = tx_type {
-        vec.insert(0usize, b)
-    }
-    vec
+fn strip_leading_zeroes(bytes: &[u8]) -> &[u8] {
+    let zeroes = bytes.iter().take_while(|&&b| b == 0).count();
+    &bytes[zeroes..]
 }
 
 /// Description of a Transaction, pending or in the chain.
 #[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct LegacyTransaction {
     /// Chain ID
+    #[serde(alias = "chainId")]
+    #[serde(deserialize_with = "u64_quantity_deserialize")]
     pub chain: u64,
     /// Nonce
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub nonce: u128,
     /// Recipient (None when contract creation)
     #[serde(serialize_with = "option_array_u8_serialize")]
@@ -152,17 +349,21 @@ pub struct LegacyTransaction {
     #[serde(default)]
     pub to: Option<[u8; 20]>,
     /// Transfered value
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub value: u128,
     /// Gas price
     #[serde(rename = "gasPrice")]
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub gas_price: u128,
     /// Gas limit
     #[serde(alias = "gasLimit")]
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub gas: u128,
     /// Input data
     #[serde(serialize_with = "slice_u8_serialize")]
     #[serde(deserialize_with = "slice_u8_deserialize")]
     #[serde(default)]
+    #[serde(alias = "input")]
     pub data: Vec<u8>,
 }
 
@@ -171,23 +372,22 @@ impl Transaction for LegacyTransaction {
         self.chain
     }
 
-    fn rlp_parts(&self) -> Vec<Box<dyn Encodable>> {
-        let to: Vec<u8> = match self.to {
-            Some(ref to) => to.to_vec(),
-            None => vec![],
-        };
-        vec![
-            Box::new(self.nonce),
-            Box::new(self.gas_price),
-            Box::new(self.gas),
-            Box::new(to),
-            Box::new(self.value),
-            Box::new(self.data.clone()),
-        ]
+    fn fields_len(&self) -> usize {
+        self.nonce.rlp_len()
+            + self.gas_price.rlp_len()
+            + self.gas.rlp_len()
+            + self.to.rlp_len()
+            + self.value.rlp_len()
+            + self.data.rlp_len()
     }
 
-    fn sign(&self, ecdsa: &EcdsaSig) -> Vec<u8> {
-        sign_bytes(None, ecdsa, self)
+    fn encode_fields<B: BufMut>(&self, out: &mut B) {
+        self.nonce.rlp_encode(out);
+        self.gas_price.rlp_encode(out);
+        self.gas.rlp_encode(out);
+        self.to.rlp_encode(out);
+        self.value.rlp_encode(out);
+        self.data.rlp_encode(out);
     }
 
     fn transaction_type() -> Option<u8> {
@@ -243,14 +443,19 @@ impl Encodable for AccessList {
 /// [EIP-2930](https://eips.ethereum.org/EIPS/eip-2930) access list transaction.
 pub struct AccessListTransaction {
     /// Chain ID
+    #[serde(alias = "chainId")]
+    #[serde(deserialize_with = "u64_quantity_deserialize")]
     pub chain: u64,
     /// Nonce
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub nonce: u128,
     /// Gas price
     #[serde(rename = "gasPrice")]
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub gas_price: u128,
     /// Gas limit
     #[serde(alias = "gasLimit")]
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub gas: u128,
     /// Recipient (None when contract creation)
     #[serde(serialize_with = "option_array_u8_serialize")]
@@ -258,11 +463,13 @@ pub struct AccessListTransaction {
     #[serde(default)]
     pub to: Option<[u8; 20]>,
     /// Transfered value
+    #[serde(deserialize_with = "u128_quantity_deserialize")]
     pub value: u128,
     /// Input data
     #[serde(serialize_with = "slice_u8_serialize")]
     #[serde(deserialize_with = "slice_u8_deserialize")]
     #[serde(default)]
+    #[serde(alias = "input")]
     pub data: Vec<u8>,
     /// List of addresses and storage keys the transaction plans to access
     #[serde(rename = "accessList")]
@@ -417,6 +624,77 @@ where
     s.serialize_str(&hex::encode(slice))
 }
 
+/// Accepts the ways nodes and JavaScript libraries write quantities: JSON numbers,
+/// `0x`-prefixed hex strings such as "0x5208", and decimal strings such as "21000".
+struct QuantityVisitor;
+
+impl<'de> serde::de::Visitor<'de> for QuantityVisitor {
+    type Value = u128;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an unsigned integer, a 0x-prefixed hex string or a decimal string")
+    }
+
+    fn visit_u64<E: SerdeErr>(self, v: u64) -> Result<u128, E> {
+        Ok(v as u128)
+    }
+
+    fn visit_u128<E: SerdeErr>(self, v: u128) -> Result<u128, E> {
+        Ok(v)
+    }
+
+    fn visit_i64<E: SerdeErr>(self, v: i64) -> Result<u128, E> {
+        if v < 0 {
+            return Err(E::invalid_value(serde::de::Unexpected::Signed(v), &self));
+        }
+        Ok(v as u128)
+    }
+
+    fn visit_str<E: SerdeErr>(self, v: &str) -> Result<u128, E> {
+        let parsed = match v.strip_prefix(HEX_PREFIX) {
+            Some(digits) if !digits.is_empty() => u128::from_str_radix(digits, 16),
+            Some(_) => return Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+            None if !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()) => v.parse(),
+            None => return Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+        };
+        parsed.map_err(|_| {
+            E::invalid_value(
+                serde::de::Unexpected::Str(v),
+                &"a quantity that fits in 128 bits",
+            )
+        })
+    }
+}
+
+fn u128_quantity_deserialize<'de, D>(deserializer: D) -> Result<u128, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    // binary formats are not self-describing and always hold plain integers
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(QuantityVisitor)
+    } else {
+        u128::deserialize(deserializer)
+    }
+}
+
+fn u64_quantity_deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    if !deserializer.is_human_readable() {
+        return u64::deserialize(deserializer);
+    }
+    let quantity = deserializer.deserialize_any(QuantityVisitor)?;
+    if quantity > u64::MAX as u128 {
+        return Err(D::Error::invalid_value(
+            serde::de::Unexpected::Other("a quantity larger than 64 bits"),
+            &"a quantity that fits in 64 bits",
+        ));
+    }
+    Ok(quantity as u64)
+}
+
 const EIP_2930_TYPE: u8 = 0x01;
 
 impl Transaction for AccessListTransaction {
@@ -424,28 +702,26 @@ impl Transaction for AccessListTransaction {
         self.chain
     }
 
-    #[allow(warnings)]
-    fn rlp_parts(&self) -> Vec<Box<dyn Encodable>> {
-        let to: Vec<u8> = match self.to {
-            Some(ref to) => to.to_vec(),
-            None => vec![],
-        };
-        let mut parts: Vec<Box<dyn Encodable>> = vec![
-            Box::new(self.chain),
-            Box::new(self.nonce),
-            Box::new(self.gas_price),
-            Box::new(self.gas),
-            Box::new(to),
-            Box::new(self.value),
-            Box::new(self.data.clone()),
-            Box::new(self.access_list.clone()),
-        ];
-
-        parts
+    fn fields_len(&self) -> usize {
+        self.chain.rlp_len()
+            + self.nonce.rlp_len()
+            + self.gas_price.rlp_len()
+            + self.gas.rlp_len()
+            + self.to.rlp_len()
+            + self.value.rlp_len()
+            + self.data.rlp_len()
+            + self.access_list.rlp_len()
     }
 
-    fn sign(&self, ecdsa: &EcdsaSig) -> Vec<u8> {
-        sign_bytes(Some(EIP_2930_TYPE), ecdsa, self)
+    fn encode_fields<B: BufMut>(&self, out: &mut B) {
+        self.chain.rlp_encode(out);
+        self.nonce.rlp_encode(out);
+        self.gas_price.rlp_encode(out);
+        self.gas.rlp_encode(out);
+        self.to.rlp_encode(out);
+        self.value.rlp_encode(out);
+        self.data.rlp_encode(out);
+        self.access_list.rlp_encode(out);
     }
 
     fn transaction_type() -> Option<u8> {
@@ -459,16 +735,22 @@ const EIP_1559_TYPE: u8 = 0x02;
 /// [EIP-1559](https://eips.ethereum.org/EIPS/eip-1559) fee market transaction.
 pub struct FeeMarketTransaction {
   /// Chain ID
+  #[serde(alias = "chainId")]
+  #[serde(deserialize_with = "u64_quantity_deserialize")]
   pub chain: u64,
   /// Nonce
+  #[serde(deserialize_with = "u128_quantity_deserialize")]
   pub nonce: u128,
   /// Gas price
   #[serde(rename = "maxPriorityFeePerGas")]
+  #[serde(deserialize_with = "u128_quantity_deserialize")]
   pub max_priority_fee_per_gas: u128,
   #[serde(rename = "maxFeePerGas")]
+  #[serde(deserialize_with = "u128_quantity_deserialize")]
   pub max_fee_per_gas: u128,
   /// Gas limit
   #[serde(alias = "gasLimit")]
+  #[serde(deserialize_with = "u128_quantity_deserialize")]
   pub gas: u128,
   /// Recipient (None when contract creation)
   #[serde(serialize_with = "option_array_u8_serialize")]
@@ -476,11 +758,13 @@ pub struct FeeMarketTransaction {
   #[serde(default)]
   pub to: Option<[u8; 20]>,
   /// Transfered value
+  #[serde(deserialize_with = "u128_quantity_deserialize")]
   pub value: u128,
   /// Input data
   #[serde(serialize_with = "slice_u8_serialize")]
   #[serde(deserialize_with = "slice_u8_deserialize")]
   #[serde(default)]
+  #[serde(alias = "input")]
   pub data: Vec<u8>,
   /// List of addresses and storage keys the transaction plans to access
   #[serde(rename = "accessList")]
@@ -490,26 +774,28 @@ pub struct FeeMarketTransaction {
 impl Transaction for FeeMarketTransaction {
   fn chain(&self) -> u64 { self.chain }
 
-  fn sign(&self, ecdsa: &EcdsaSig) -> Vec<u8> {
-    sign_bytes(Some(EIP_1559_TYPE), ecdsa, self)
+  fn fields_len(&self) -> usize {
+    self.chain.rlp_len()
+      + self.nonce.rlp_len()
+      + self.max_priority_fee_per_gas.rlp_len()
+      + self.max_fee_per_gas.rlp_len()
+      + self.gas.rlp_len()
+      + self.to.rlp_len()
+      + self.value.rlp_len()
+      + self.data.rlp_len()
+      + self.access_list.rlp_len()
   }
 
-  fn rlp_parts(&self) -> Vec<Box<dyn Encodable>> {
-    let to: Vec<u8> = match self.to {
-      Some(ref to) => to.to_vec(),
-      None => vec![],
-    };
-    vec![
-      Box::new(self.chain),
-      Box::new(self.nonce),
-      Box::new(self.max_priority_fee_per_gas),
-      Box::new(self.max_fee_per_gas),
-      Box::new(self.gas),
-      Box::new(to),
-      Box::new(self.value),
-      Box::new(self.data.clone()),
-      Box::new(self.access_list.clone()),
-    ]
+  fn encode_fields<B: BufMut>(&self, out: &mut B) {
+    self.chain.rlp_encode(out);
+    self.nonce.rlp_encode(out);
+    self.max_priority_fee_per_gas.rlp_encode(out);
+    self.max_fee_per_gas.rlp_encode(out);
+    self.gas.rlp_encode(out);
+    self.to.rlp_encode(out);
+    self.value.rlp_encode(out);
+    self.data.rlp_encode(out);
+    self.access_list.rlp_encode(out);
   }
 
   fn transaction_type() -> Option<u8> {
@@ -517,7 +803,7 @@ impl Transaction for FeeMarketTransaction {
   }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 /// Represents an [ECDSA](https://en.wikipedia.org/wiki/Elliptic_Curve_Digital_Signature_Algorithm) signature.
 pub struct EcdsaSig {
     pub v: u64,
@@ -530,21 +816,30 @@ pub struct EcdsaSig {
 }
 
 impl EcdsaSig {
-    fn generate(
+    fn generate<K: ToPrivateKey + ?Sized>(
         hash: [u8; 32],
-        private_key: &[u8],
+        private_key: &K,
         chain_id: Option<u64>,
     ) -> Result<EcdsaSig, Error> {
-        let s = Secp256k1::signing_only();
-        let msg = Message::from_slice(&hash)?;
-        let key = SecretKey::from_slice(private_key)?;
-        let (v, sig_bytes) = s.sign_ecdsa_recoverable(&msg, &key).serialize_compact();
-
-        let v = v.to_i32() as u64
-            + match chain_id {
-                Some(c) => c * 2 + 35,
-                None => 0,
-            };
+        let key = private_key.to_private_key()?.secret_key();
+        Self::sign_hash(hash, &key, chain_id)
+    }
+
+    /// Sign with a key that has already been checked, so that batches check it once.
+    fn sign_hash(
+        hash: [u8; 32],
+        key: &backend::SecretKey,
+        chain_id: Option<u64>,
+    ) -> Result<EcdsaSig, Error> {
+        let (sig_bytes, v) = key.sign(&hash)?;
+
+        let v = match chain_id {
+            Some(c) => c
+                .checked_mul(2)
+                .and_then(|c| c.checked_add(35 + v as u64))
+                .ok_or(Error::ChainIdOverflow(c))?,
+            None => v as u64,
+        };
 
         Ok(EcdsaSig {
             v,
@@ -552,6 +847,69 @@ impl EcdsaSig {
             s: sig_bytes[32..64].to_vec(),
         })
     }
+
+    /// Recover the address of the account that signed `hash`. `chain_id` must be
+    /// given for legacy transactions, whose `v` is encoded according to
+    /// [EIP-155](https://eips.ethereum.org/EIPS/eip-155).
+    pub fn recover(&self, hash: [u8; 32], chain_id: Option<u64>) -> Result<[u8; 20], Error> {
+        let recovery_id = match chain_id {
+            Some(c) => c
+                .checked_mul(2)
+                .and_then(|c| c.checked_add(35))
+                .and_then(|offset| self.v.checked_sub(offset)),
+            None => Some(self.v),
+        };
+        let recovery_id = match recovery_id {
+            Some(id) if id <= 1 => id as u8,
+            _ => return Err(Error::InvalidV(self.v)),
+        };
+        if self.r.len() > 32 || self.s.len() > 32 {
+            return Err(Error::Secp256k1(backend::invalid_signature()));
+        }
+
+        // r and s may have had their leading zeroes stripped
+        let mut compact = [0u8; 64];
+        compact[32 - self.r.len()..32].copy_from_slice(&self.r);
+        compact[64 - self.s.len()..].copy_from_slice(&self.s);
+
+        let public_key = backend::recover(&hash, &compact, recovery_id)?;
+        Ok(public_key_address(&public_key))
+    }
+}
+
+/// Convert the `v` returned by a signing device into the `v` a transaction is encoded with.
+/// Devices may return the recovery ID, the recovery ID plus 27 or, for legacy transactions
+/// (which have a `chain`), an EIP-155 value.
+fn normalize_v(v: u64, chain: Option<u64>) -> Result<u64, Error> {
+    let recovery_id = match v {
+        0..=1 => v,
+        27..=28 => v - 27,
+        // checked during recovery
+        _ if v >= 35 && chain.is_some() => return Ok(v),
+        _ => return Err(Error::InvalidV(v)),
+    };
+    match chain {
+        Some(c) => c
+            .checked_mul(2)
+            .and_then(|c| c.checked_add(35 + recovery_id))
+            .ok_or(Error::ChainIdOverflow(c)),
+        None => Ok(recovery_id),
+    }
+}
+
+/// Address of the account with the given uncompressed (65 byte) public key.
+fn public_key_address(public_key: &[u8; 65]) -> [u8; 20] {
+    let hash = keccak256_hash(&public_key[1..]);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..]);
+    address
+}
+
+/// Hash that `eth_sign` and `personal_sign` sign for a 32 byte message.
+fn eth_signed_message_hash(hash: &[u8; 32]) -> [u8; 32] {
+    let mut message = b"\x19Ethereum Signed Message:\n32".to_vec();
+    message.extend_from_slice(hash);
+    keccak256_hash(&message)
 }
 
 fn keccak256_hash(bytes: &[u8]) -> [u8; 32] {
@@ -565,6 +923,8 @@ fn keccak256_hash(bytes: &[u8]) -> [u8; 32] {
 #[cfg(test)]
 mod test {
     use crate::{AccessListTransaction, EcdsaSig, LegacyTransaction, Transaction, FeeMarketTransaction};
+    use crate::{AccessList, Error, PrivateKey};
+    use crate::rpc::{RpcFormat, RpcTransaction};
 
     use serde_json;
     use std::collections::HashMap;
@@ -996,6 +1356,173 @@ mod test {
       );
     }
 
+    // JSON-RPC QUANTITY ENCODING
+
+    #[test]
+    fn test_deserialize_json_rpc_quantities() {
+        let tx: FeeMarketTransaction = serde_json::from_str(
+            r#"{
+                "chainId": "0x1",
+                "nonce": "0x2a",
+                "maxPriorityFeePerGas": "1500000000",
+                "maxFeePerGas": 30000000000,
+                "gas": "0x5208",
+                "to": "0x4545454545454545454545454545454545454545",
+                "value": "0xde0b6b3a7640000",
+                "input": "0xabcd",
+                "accessList": []
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            FeeMarketTransaction {
+                chain: 1,
+                nonce: 42,
+                max_priority_fee_per_gas: 1_500_000_000,
+                max_fee_per_gas: 30_000_000_000,
+                gas: 21_000,
+                to: Some([0x45; 20]),
+                value: 10u128.pow(18),
+                data: vec![0xab, 0xcd],
+                access_list: AccessList(vec![]),
+            },
+            tx
+        );
+    }
+
+    #[test]
+    fn test_deserialize_invalid_quantities() {
+        for value in ["\"0x\"", "\"-1\"", "\"1e3\"", "\"0xzz\"", "-1", "1.5"].iter() {
+            let json = format!(
+                r#"{{"chain": 1, "nonce": {}, "value": 0, "gasPrice": 0, "gas": 0}}"#,
+                value
+            );
+            let result = serde_json::from_str::<LegacyTransaction>(&json);
+            assert!(result.is_err(), "accepted {}", value);
+        }
+
+        // chain ID must fit in 64 bits
+        let json = r#"{"chain": "0x10000000000000000", "nonce": 0, "value": 0, "gasPrice": 0,
+            "gas": 0}"#;
+        assert!(serde_json::from_str::<LegacyTransaction>(json).is_err());
+    }
+
+    #[test]
+    fn test_serde_rpc_format_fee_market_transaction_001() {
+        run_rpc_format_test::<FeeMarketTransaction>("./test/random_eip_1559_001.json");
+    }
+
+    #[test]
+    fn test_serde_rpc_format_access_list_transaction_001() {
+        run_rpc_format_test::<AccessListTransaction>("./test/random_eip_2930_001.json");
+    }
+
+    #[test]
+    fn test_serde_rpc_format_legacy_001() {
+        run_rpc_format_test::<LegacyTransaction>("./test/random_legacy_001.json");
+    }
+
+    #[test]
+    fn test_sign_verified() {
+        let tx = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            ..Default::default()
+        };
+        let ecdsa = tx.ecdsa(&[0x35; 32]).unwrap();
+        let signer = tx.recover(&ecdsa).unwrap();
+
+        assert_eq!(tx.hash(), super::keccak256_hash(&tx.signing_payload()));
+        assert_eq!(tx.sign(&ecdsa), tx.sign_verified(&ecdsa, &signer).unwrap());
+        match tx.sign_verified(&ecdsa, &[0; 20]) {
+            Err(Error::SignerMismatch { recovered, .. }) => assert_eq!(signer, recovered),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_sign_verified_rsv() {
+        let tx = LegacyTransaction {
+            chain: 1,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            ..Default::default()
+        };
+        let ecdsa = tx.ecdsa(&[0x35; 32]).unwrap();
+        let signer = tx.recover(&ecdsa).unwrap();
+        let expected = tx.sign(&ecdsa);
+
+        let mut rsv = [ecdsa.r.clone(), ecdsa.s.clone(), vec![0]].concat();
+        for v in [ecdsa.v - 37, ecdsa.v - 37 + 27, ecdsa.v].iter() {
+            rsv[64] = *v as u8;
+            assert_eq!(expected, tx.sign_verified_rsv(&rsv, &signer).unwrap());
+        }
+
+        match tx.sign_verified_rsv(&rsv[..64], &signer) {
+            Err(Error::InvalidSignatureLength(64)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        rsv[64] = 39;
+        match tx.sign_verified_rsv(&rsv, &signer) {
+            Err(Error::InvalidV(39)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_recover_fixtures() {
+        for entry in std::fs::read_dir("./test").unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            let path = path.to_str().unwrap();
+            if name.contains("eip_1559") {
+                run_recover_test::<FeeMarketTransaction>(path);
+            } else if name.contains("eip_2930") || name.contains("eip_2718") {
+                run_recover_test::<AccessListTransaction>(path);
+            } else if name.contains("legacy") {
+                run_recover_test::<LegacyTransaction>(path);
+            }
+        }
+    }
+
+    /// The sender recovered from a fixture's signature is the address of its private key.
+    fn run_recover_test<T: Transaction + serde::de::DeserializeOwned>(path: &str) {
+        let values: HashMap<String, serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let transaction: T = serde_json::from_value(values["input"].clone()).unwrap();
+        let private_key: PrivateKey = values["privateKey"].as_str().unwrap().parse().unwrap();
+
+        let ecdsa = transaction.ecdsa(&private_key).unwrap();
+        let recovered = transaction.recover(&ecdsa).unwrap();
+        assert_eq!(private_key.address(), recovered, "{}", path);
+    }
+
+    fn run_rpc_format_test<T: RpcTransaction + serde::de::DeserializeOwned + Debug + Eq>(
+        path: &str,
+    ) {
+        let mut file = File::open(path).unwrap_or_else(|_| panic!("Failed to open: {}", path));
+        let mut f_string = String::new();
+        file.read_to_string(&mut f_string).unwrap();
+
+        let values: HashMap<String, serde_json::Value> = serde_json::from_str(&f_string).unwrap();
+        let transaction: T = serde_json::from_value(values["input"].clone()).unwrap();
+        let rpc_json = serde_json::to_value(RpcFormat(&transaction)).unwrap();
+
+        assert_eq!(
+            values["input"]["nonce"],
+            u128_from_quantity(&rpc_json["nonce"])
+        );
+        assert!(rpc_json["input"].as_str().unwrap().starts_with("0x"));
+        assert_eq!(transaction, serde_json::from_value(rpc_json).unwrap());
+    }
+
+    fn u128_from_quantity(value: &serde_json::Value) -> serde_json::Value {
+        let quantity = u128::from_str_radix(&value.as_str().unwrap()[2..], 16).unwrap();
+        serde_json::Value::from(quantity as u64)
+    }
+
     fn run_serialization_deserialization_test<
         T: Transaction
             + serde::de::DeserializeOwned
@@ -1047,6 +1574,7 @@ mod test {
         assert_eq!(expected_bytes_string, actual_bytes_string);
     }
 
+    #[allow(clippy::multiple_bound_locations)]
     fn run_ecdsa_test<T: Transaction + serde::de::DeserializeOwned>(path: &str)
     where
         T: std::fmt::Debug,
@@ -1069,6 +1597,10 @@ mod test {
         assert_eq!(expected_ecdsa, signed_ecdsa)
     }
 
+    #[allow(
+        clippy::multiple_bound_locations,
+        clippy::needless_borrows_for_generic_args
+    )]
     fn run_hash_test<T: Transaction + serde::de::DeserializeOwned>(path: &str)
     where
         T: std::fmt::Debug,
@@ -0,0 +1,453 @@
+use serde_json;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use {AccessListTransaction, FeeMarketTransaction, LegacyTransaction, Transaction};
+
+/// A transaction whose nonce can be read and assigned.
+pub trait Nonced: Transaction {
+    fn nonce(&self) -> u128;
+
+    fn set_nonce(&mut self, nonce: u128);
+}
+
+impl Nonced for LegacyTransaction {
+    fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    fn set_nonce(&mut self, nonce: u128) {
+        self.nonce = nonce;
+    }
+}
+
+impl Nonced for AccessListTransaction {
+    fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    fn set_nonce(&mut self, nonce: u128) {
+        self.nonce = nonce;
+    }
+}
+
+impl Nonced for FeeMarketTransaction {
+    fn nonce(&self) -> u128 {
+        self.nonce
+    }
+
+    fn set_nonce(&mut self, nonce: u128) {
+        self.nonce = nonce;
+    }
+}
+
+#[derive(Debug)]
+pub enum NonceError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// No starting nonce has been set for this chain and address.
+    UnknownAccount {
+        chain: u64,
+        address: [u8; 20],
+    },
+    /// The nonce was never handed out, so it cannot be rolled back.
+    NotAssigned {
+        chain: u64,
+        nonce: u128,
+    },
+    /// The next nonce does not fit in a `u128`.
+    Overflow,
+}
+
+impl fmt::Display for NonceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NonceError::Io(ref err) => write!(f, "failed to access nonce file: {}", err),
+            NonceError::Json(ref err) => write!(f, "invalid nonce file: {}", err),
+            NonceError::UnknownAccount { chain, ref address } => write!(
+                f,
+                "no starting nonce for 0x{} on chain {}",
+                hex::encode(address),
+                chain
+            ),
+            NonceError::NotAssigned { chain, nonce } => {
+                write!(f, "nonce {} on chain {} was never assigned", nonce, chain)
+            }
+            NonceError::Overflow => write!(f, "nonce overflowed"),
+        }
+    }
+}
+
+impl std::error::Error for NonceError {}
+
+impl From<io::Error> for NonceError {
+    fn from(error: io::Error) -> Self {
+        NonceError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for NonceError {
+    fn from(error: serde_json::Error) -> Self {
+        NonceError::Json(error)
+    }
+}
+
+/// Problem with the nonces of a batch of transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonceIssue {
+    /// More than one transaction uses this nonce.
+    Duplicate { chain: u64, nonce: u128 },
+    /// No transaction uses the nonces from `first` to `last` inclusive, so later
+    /// transactions will be stuck.
+    Gap { chain: u64, first: u128, last: u128 },
+}
+
+/// Find duplicated and missing nonces in a batch of transactions from one sender.
+/// Transactions on different chains are checked separately.
+pub fn check_nonces<T: Nonced>(transactions: &[T]) -> Vec<NonceIssue> {
+    let mut by_chain: BTreeMap<u64, Vec<u128>> = BTreeMap::new();
+    for tx in transactions.iter() {
+        by_chain.entry(tx.chain()).or_default().push(tx.nonce());
+    }
+
+    let mut issues = vec![];
+    for (chain, mut nonces) in by_chain.into_iter() {
+        nonces.sort_unstable();
+        for pair in nonces.windows(2) {
+            let (previous, nonce) = (pair[0], pair[1]);
+            if nonce == previous {
+                if !issues.contains(&NonceIssue::Duplicate { chain, nonce }) {
+                    issues.push(NonceIssue::Duplicate { chain, nonce });
+                }
+            } else if nonce > previous + 1 {
+                issues.push(NonceIssue::Gap {
+                    chain,
+                    first: previous + 1,
+                    last: nonce - 1,
+                });
+            }
+        }
+    }
+    issues
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct AccountNonces {
+    chain: u64,
+    #[serde(serialize_with = "::array_u8_20_serialize")]
+    #[serde(deserialize_with = "::array_u8_20_deserialize")]
+    address: [u8; 20],
+    /// Lowest nonce that has never been handed out
+    next: u128,
+    /// Nonces below `next` that were rolled back and can be handed out again
+    #[serde(default)]
+    released: BTreeSet<u128>,
+}
+
+impl AccountNonces {
+    fn take(&mut self) -> Result<u128, NonceError> {
+        if let Some(nonce) = self.released.iter().next().cloned() {
+            self.released.remove(&nonce);
+            return Ok(nonce);
+        }
+        let nonce = self.next;
+        self.next = nonce.checked_add(1).ok_or(NonceError::Overflow)?;
+        Ok(nonce)
+    }
+
+    fn release(&mut self, nonce: u128) -> Result<(), NonceError> {
+        if nonce >= self.next || self.released.contains(&nonce) {
+            return Err(NonceError::NotAssigned {
+                chain: self.chain,
+                nonce,
+            });
+        }
+        self.released.insert(nonce);
+        // rolled back nonces at the top simply become unassigned again
+        while self.next > 0 && self.released.remove(&(self.next - 1)) {
+            self.next -= 1;
+        }
+        Ok(())
+    }
+}
+
+/// Hands out consecutive nonces for offline signing sessions, per chain and sender.
+///
+/// ```
+/// use ethereum_tx_sign::{LegacyTransaction, NonceManager};
+///
+/// let from = [0x11; 20];
+/// let mut nonces = NonceManager::new();
+/// nonces.set_next_nonce(1, from, 40);
+///
+/// let mut batch = vec![LegacyTransaction { chain: 1, ..Default::default() }; 3];
+/// nonces.assign(from, &mut batch).unwrap();
+///
+/// assert_eq!(vec![40, 41, 42], batch.iter().map(|tx| tx.nonce).collect::<Vec<_>>());
+/// assert_eq!(Some(43), nonces.next_nonce(1, from));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NonceManager {
+    accounts: BTreeMap<(u64, [u8; 20]), AccountNonces>,
+}
+
+impl NonceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load state saved by [`NonceManager::save`]. A missing file gives an empty manager.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, NonceError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let accounts: Vec<AccountNonces> = serde_json::from_str(&contents)?;
+        Ok(NonceManager {
+            accounts: accounts
+                .into_iter()
+                .map(|a| ((a.chain, a.address), a))
+                .collect(),
+        })
+    }
+
+    /// Save state to a JSON file. The file is replaced atomically so an interrupted
+    /// save never leaves a truncated file behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), NonceError> {
+        let path = path.as_ref();
+        let accounts: Vec<&AccountNonces> = self.accounts.values().collect();
+        let json = serde_json::to_string_pretty(&accounts)?;
+
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Set the next nonce for a sender, usually from `eth_getTransactionCount`.
+    /// Any rolled back nonces are forgotten.
+    pub fn set_next_nonce(&mut self, chain: u64, address: [u8; 20], nonce: u128) {
+        self.accounts.insert(
+            (chain, address),
+            AccountNonces {
+                chain,
+                address,
+                next: nonce,
+                released: BTreeSet::new(),
+            },
+        );
+    }
+
+    /// The nonce that the next assigned transaction will get, if the sender is known.
+    pub fn next_nonce(&self, chain: u64, address: [u8; 20]) -> Option<u128> {
+        self.accounts
+            .get(&(chain, address))
+            .map(|a| a.released.iter().next().cloned().unwrap_or(a.next))
+    }
+
+    /// Assign nonces to a batch of transactions from `address` in order. Rolled back
+    /// nonces are reused first so that no gaps are left behind. If any transaction cannot
+    /// be assigned a nonce, none are.
+    pub fn assign<T: Nonced>(
+        &mut self,
+        address: [u8; 20],
+        transactions: &mut [T],
+    ) -> Result<(), NonceError> {
+        let mut assigned = BTreeMap::new();
+        let mut nonces = Vec::with_capacity(transactions.len());
+        for tx in transactions.iter() {
+            let chain = tx.chain();
+            let account = match assigned.entry(chain) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.account(chain, address)?.clone()),
+            };
+            nonces.push(account.take()?);
+        }
+
+        for (chain, account) in assigned {
+            self.accounts.insert((chain, address), account);
+        }
+        for (tx, nonce) in transactions.iter_mut().zip(nonces) {
+            tx.set_nonce(nonce);
+        }
+        Ok(())
+    }
+
+    /// Give back nonces of transactions that were signed but never broadcast. If any of
+    /// them cannot be rolled back, none are.
+    pub fn rollback(
+        &mut self,
+        chain: u64,
+        address: [u8; 20],
+        nonces: &[u128],
+    ) -> Result<(), NonceError> {
+        let account = self.account(chain, address)?;
+        let mut rolled_back = account.clone();
+        for nonce in nonces.iter() {
+            rolled_back.release(*nonce)?;
+        }
+        *account = rolled_back;
+        Ok(())
+    }
+
+    fn account(&mut self, chain: u64, address: [u8; 20]) -> Result<&mut AccountNonces, NonceError> {
+        self.accounts
+            .get_mut(&(chain, address))
+            .ok_or(NonceError::UnknownAccount { chain, address })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nonce::{check_nonces, NonceError, NonceIssue, NonceManager};
+    use std::env;
+    use std::fs;
+    use {FeeMarketTransaction, LegacyTransaction};
+
+    const FROM: [u8; 20] = [0x11; 20];
+
+    fn batch(chain: u64, size: usize) -> Vec<FeeMarketTransaction> {
+        vec![
+            FeeMarketTransaction {
+                chain,
+                ..Default::default()
+            };
+            size
+        ]
+    }
+
+    fn nonces(txs: &[FeeMarketTransaction]) -> Vec<u128> {
+        txs.iter().map(|tx| tx.nonce).collect()
+    }
+
+    #[test]
+    fn test_assign_per_chain() {
+        let mut manager = NonceManager::new();
+        manager.set_next_nonce(1, FROM, 5);
+        manager.set_next_nonce(10, FROM, 0);
+
+        let mut txs = batch(1, 2);
+        txs.extend(batch(10, 1));
+        txs.extend(batch(1, 1));
+        manager.assign(FROM, &mut txs).unwrap();
+
+        assert_eq!(vec![5, 6, 0, 7], nonces(&txs));
+    }
+
+    #[test]
+    fn test_assign_unknown_account() {
+        let mut manager = NonceManager::new();
+        manager.set_next_nonce(1, FROM, 5);
+
+        let mut txs = batch(1, 1);
+        txs.extend(batch(5, 1));
+
+        match manager.assign(FROM, &mut txs) {
+            Err(NonceError::UnknownAccount { chain: 5, .. }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        // nothing is assigned if any transaction cannot be
+        assert_eq!(Some(5), manager.next_nonce(1, FROM));
+    }
+
+    #[test]
+    fn test_assign_overflow() {
+        let mut manager = NonceManager::new();
+        manager.set_next_nonce(1, FROM, u128::MAX - 1);
+
+        let mut txs = batch(1, 2);
+        let before = manager.clone();
+        match manager.assign(FROM, &mut txs) {
+            Err(NonceError::Overflow) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        // the first transaction's nonce is not consumed either
+        assert_eq!(before, manager);
+        assert_eq!(vec![0, 0], nonces(&txs));
+    }
+
+    #[test]
+    fn test_rollback_reuses_nonces() {
+        let mut manager = NonceManager::new();
+        manager.set_next_nonce(1, FROM, 0);
+        let mut txs = batch(1, 5);
+        manager.assign(FROM, &mut txs).unwrap();
+
+        manager.rollback(1, FROM, &[1, 4]).unwrap();
+        assert_eq!(Some(1), manager.next_nonce(1, FROM));
+
+        let mut retry = batch(1, 2);
+        manager.assign(FROM, &mut retry).unwrap();
+        assert_eq!(vec![1, 4], nonces(&retry));
+
+        match manager.rollback(1, FROM, &[5]) {
+            Err(NonceError::NotAssigned { nonce: 5, .. }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        // nothing is rolled back when one of the nonces is invalid
+        let before = manager.clone();
+        match manager.rollback(1, FROM, &[2, 3, 3]) {
+            Err(NonceError::NotAssigned { nonce: 3, .. }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert_eq!(before, manager);
+        assert_eq!(Some(5), manager.next_nonce(1, FROM));
+    }
+
+    #[test]
+    fn test_check_nonces() {
+        let txs: Vec<LegacyTransaction> = [(1, 3), (1, 4), (1, 4), (1, 8), (2, 0), (2, 1)]
+            .iter()
+            .map(|&(chain, nonce)| LegacyTransaction {
+                chain,
+                nonce,
+                ..Default::default()
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                NonceIssue::Duplicate { chain: 1, nonce: 4 },
+                NonceIssue::Gap {
+                    chain: 1,
+                    first: 5,
+                    last: 7,
+                },
+            ],
+            check_nonces(&txs)
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = env::temp_dir().join(format!("nonces_{}.json", std::process::id()));
+
+        let mut manager = NonceManager::new();
+        manager.set_next_nonce(1, FROM, 10);
+        manager.set_next_nonce(137, [0x22; 20], 3);
+        let mut txs = batch(1, 3);
+        manager.assign(FROM, &mut txs).unwrap();
+        manager.rollback(1, FROM, &[11]).unwrap();
+        manager.save(&path).unwrap();
+
+        let loaded = NonceManager::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(manager, loaded);
+        assert_eq!(Some(11), loaded.next_nonce(1, FROM));
+        assert_eq!(Some(3), loaded.next_nonce(137, [0x22; 20]));
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let path = env::temp_dir().join("nonces_that_do_not_exist.json");
+        assert_eq!(NonceManager::new(), NonceManager::load(path).unwrap());
+    }
+}
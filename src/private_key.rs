@@ -0,0 +1,208 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroize;
+
+use backend::SecretKey;
+use {public_key_address, Error};
+
+/// A secp256k1 private key.
+///
+/// The key is checked to be a valid scalar when it is created, and its own copy of the bytes
+/// is wiped when it is dropped; the buffer it was created from is left to the caller. It never
+/// shows up in `{:?}` or `{}` output and does not implement serde's traits unless the
+/// `serde-private-key` feature is enabled, so it cannot end up in logs or serialized
+/// structures by accident.
+/// ```
+/// use ethereum_tx_sign::{LegacyTransaction, PrivateKey, Transaction};
+///
+/// let key: PrivateKey = "0x3535353535353535353535353535353535353535353535353535353535353535"
+///     .parse()
+///     .unwrap();
+/// assert_eq!("PrivateKey(..)", format!("{:?}", key));
+///
+/// let tx = LegacyTransaction { chain: 1, gas: 21000, ..Default::default() };
+/// let ecdsa = tx.ecdsa(&key).unwrap();
+/// assert_eq!(key.address(), tx.recover(&ecdsa).unwrap());
+/// ```
+#[derive(Clone)]
+pub struct PrivateKey([u8; 32]);
+
+impl PrivateKey {
+    /// The key with the given 32 bytes, which must be a valid secp256k1 scalar.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        SecretKey::from_slice(bytes)?;
+        let mut key = [0u8; 32];
+        key.copy_from_slice(bytes);
+        Ok(PrivateKey(key))
+    }
+
+    /// The key's bytes. Take care not to copy them anywhere they will not be wiped.
+    pub fn secret_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Address of the key's account.
+    pub fn address(&self) -> [u8; 20] {
+        public_key_address(&self.secret_key().public_key())
+    }
+
+    /// The key in the form the secp256k1 backend signs with, which erases it when dropped.
+    pub(crate) fn secret_key(&self) -> SecretKey {
+        SecretKey::from_slice(&self.0).expect("checked on construction")
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PrivateKey(..)")
+    }
+}
+
+impl fmt::Display for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[redacted private key]")
+    }
+}
+
+/// Parses 64 hex digits, with or without `0x`.
+impl FromStr for PrivateKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut bytes = [0u8; 32];
+        let decoded = hex::decode_to_slice(s.strip_prefix("0x").unwrap_or(s), &mut bytes);
+        let private_key = decoded
+            .map_err(Error::from)
+            .and_then(|_| Self::from_slice(&bytes));
+        bytes.zeroize();
+        private_key
+    }
+}
+
+#[cfg(feature = "serde-private-key")]
+impl serde::Serialize for PrivateKey {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut hex = hex::encode(self.0);
+        let result = s.serialize_str(&hex);
+        hex.zeroize();
+        result
+    }
+}
+
+#[cfg(feature = "serde-private-key")]
+impl<'de> serde::Deserialize<'de> for PrivateKey {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let mut hex = String::deserialize(d)?;
+        let private_key = hex
+            .parse()
+            .map_err(|err| serde::de::Error::custom(format!("invalid private key: {:?}", err)));
+        hex.zeroize();
+        private_key
+    }
+}
+
+/// Anything the signing functions accept as a private key: a [`PrivateKey`], or its 32
+/// bytes. Bytes are checked and copied into a temporary [`PrivateKey`], which is wiped after
+/// use; the caller's bytes are not, so prefer holding a [`PrivateKey`].
+pub trait ToPrivateKey {
+    fn to_private_key(&self) -> Result<Cow<'_, PrivateKey>, Error>;
+}
+
+impl ToPrivateKey for PrivateKey {
+    fn to_private_key(&self) -> Result<Cow<'_, PrivateKey>, Error> {
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+impl ToPrivateKey for [u8] {
+    fn to_private_key(&self) -> Result<Cow<'_, PrivateKey>, Error> {
+        PrivateKey::from_slice(self).map(Cow::Owned)
+    }
+}
+
+impl ToPrivateKey for [u8; 32] {
+    fn to_private_key(&self) -> Result<Cow<'_, PrivateKey>, Error> {
+        self[..].to_private_key()
+    }
+}
+
+impl ToPrivateKey for Vec<u8> {
+    fn to_private_key(&self) -> Result<Cow<'_, PrivateKey>, Error> {
+        self[..].to_private_key()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use private_key::{PrivateKey, ToPrivateKey};
+    use {Error, FeeMarketTransaction, Transaction};
+
+    #[test]
+    fn test_private_key() {
+        let key = PrivateKey::from_slice(&[0x35; 32]).unwrap();
+        assert_eq!(
+            "8b11c43881b3f9148493c2edad543e97efdc517a",
+            hex::encode(key.address())
+        );
+        assert_eq!(&[0x35; 32], key.secret_bytes());
+        assert_eq!("PrivateKey(..)", format!("{:?}", key));
+        assert!(!key.to_string().contains("35"));
+
+        let tx = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        assert_eq!(tx.ecdsa(&[0x35; 32]).unwrap(), tx.ecdsa(&key).unwrap());
+        assert_eq!(
+            tx.ecdsa(&vec![0x35; 32]).unwrap(),
+            tx.ecdsa(&key.clone()).unwrap()
+        );
+        assert_eq!(
+            key.address(),
+            [0x35u8; 32].to_private_key().unwrap().address()
+        );
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        // zero and the curve order are not valid scalars
+        let order = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+        for key in [&[0u8; 32][..], &[0x35; 31], &hex::decode(order).unwrap()].iter() {
+            match PrivateKey::from_slice(key) {
+                Err(Error::Secp256k1(_)) => (),
+                r => panic!("Unexpected result: {:?}", r),
+            }
+        }
+        match "0x35".parse::<PrivateKey>() {
+            Err(Error::Hex(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match format!("0x0x{}", "35".repeat(32)).parse::<PrivateKey>() {
+            Err(Error::Hex(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match FeeMarketTransaction::default().ecdsa(&[0u8; 32]) {
+            Err(Error::Secp256k1(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "serde-private-key")]
+    #[test]
+    fn test_serde() {
+        let key = PrivateKey::from_slice(&[0x35; 32]).unwrap();
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(format!("\"{}\"", "35".repeat(32)), json);
+        let parsed: PrivateKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(key.secret_bytes(), parsed.secret_bytes());
+        assert!(serde_json::from_str::<PrivateKey>("\"0x00\"").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use fees::FeeError;
+use {AccessList, AccessListTransaction, FeeMarketTransaction, LegacyTransaction};
+
+/// Gas used by a plain transfer, which is all a cancellation needs.
+const CANCELLATION_GAS: u128 = 21_000;
+
+/// Minimum percentage by which geth requires fees to increase before it will replace
+/// a pending transaction with one of the same nonce.
+pub const MIN_FEE_BUMP_PERCENT: u128 = 10;
+
+/// How much to raise fees by when building a replacement transaction.
+/// The default is the minimum that geth accepts; smaller bumps are refused with
+/// [`FeeError::BumpTooSmall`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeBump {
+    /// Percentage increase of `max_fee_per_gas`, or of `gas_price` for legacy and
+    /// access list transactions
+    pub max_fee_percent: u128,
+    /// Percentage increase of `max_priority_fee_per_gas`
+    pub priority_fee_percent: u128,
+}
+
+impl Default for FeeBump {
+    fn default() -> Self {
+        FeeBump {
+            max_fee_percent: MIN_FEE_BUMP_PERCENT,
+            priority_fee_percent: MIN_FEE_BUMP_PERCENT,
+        }
+    }
+}
+
+impl FeeBump {
+    /// Bump both fees by the same percentage.
+    pub fn percent(percent: u128) -> Self {
+        FeeBump {
+            max_fee_percent: percent,
+            priority_fee_percent: percent,
+        }
+    }
+}
+
+impl LegacyTransaction {
+    /// Copy of this transaction with `gas_price` raised so that it replaces the
+    /// original in the transaction pool.
+    pub fn replacement(&self, bump: &FeeBump) -> Result<Self, FeeError> {
+        Ok(LegacyTransaction {
+            gas_price: bump_fee(self.gas_price, bump.max_fee_percent)?,
+            ..self.clone()
+        })
+    }
+
+    /// Transaction that cancels this one by sending nothing to `from`, the sender,
+    /// at the same nonce with a bumped `gas_price`.
+    pub fn cancellation(&self, from: [u8; 20], bump: &FeeBump) -> Result<Self, FeeError> {
+        Ok(LegacyTransaction {
+            chain: self.chain,
+            nonce: self.nonce,
+            to: Some(from),
+            value: 0,
+            gas_price: bump_fee(self.gas_price, bump.max_fee_percent)?,
+            gas: CANCELLATION_GAS,
+            data: vec![],
+        })
+    }
+}
+
+impl AccessListTransaction {
+    /// Copy of this transaction with `gas_price` raised so that it replaces the
+    /// original in the transaction pool.
+    pub fn replacement(&self, bump: &FeeBump) -> Result<Self, FeeError> {
+        Ok(AccessListTransaction {
+            gas_price: bump_fee(self.gas_price, bump.max_fee_percent)?,
+            ..self.clone()
+        })
+    }
+
+    /// Transaction that cancels this one by sending nothing to `from`, the sender,
+    /// at the same nonce with a bumped `gas_price`.
+    pub fn cancellation(&self, from: [u8; 20], bump: &FeeBump) -> Result<Self, FeeError> {
+        Ok(AccessListTransaction {
+            chain: self.chain,
+            nonce: self.nonce,
+            gas_price: bump_fee(self.gas_price, bump.max_fee_percent)?,
+            gas: CANCELLATION_GAS,
+            to: Some(from),
+            value: 0,
+            data: vec![],
+            access_list: AccessList(vec![]),
+        })
+    }
+}
+
+impl FeeMarketTransaction {
+    /// Copy of this transaction with `max_fee_per_gas` and `max_priority_fee_per_gas`
+    /// raised so that it replaces the original in the transaction pool. The priority fee
+    /// is capped at the new max fee, which is still enough of a bump when the original
+    /// priority fee did not exceed the original max fee.
+    pub fn replacement(&self, bump: &FeeBump) -> Result<Self, FeeError> {
+        let max_fee_per_gas = bump_fee(self.max_fee_per_gas, bump.max_fee_percent)?;
+        let max_priority_fee_per_gas =
+            bump_fee(self.max_priority_fee_per_gas, bump.priority_fee_percent)?;
+        Ok(FeeMarketTransaction {
+            max_priority_fee_per_gas: max_priority_fee_per_gas.min(max_fee_per_gas),
+            max_fee_per_gas,
+            ..self.clone()
+        })
+    }
+
+    /// Transaction that cancels this one by sending nothing to `from`, the sender,
+    /// at the same nonce with bumped fees.
+    pub fn cancellation(&self, from: [u8; 20], bump: &FeeBump) -> Result<Self, FeeError> {
+        let replacement = self.replacement(bump)?;
+        Ok(FeeMarketTransaction {
+            chain: self.chain,
+            nonce: self.nonce,
+            max_priority_fee_per_gas: replacement.max_priority_fee_per_gas,
+            max_fee_per_gas: replacement.max_fee_per_gas,
+            gas: CANCELLATION_GAS,
+            to: Some(from),
+            value: 0,
+            data: vec![],
+            access_list: AccessList(vec![]),
+        })
+    }
+}
+
+/// Raise `fee` by `percent`, rounding up. geth compares against the rounded-down
+/// threshold and also requires the new fee to be strictly greater, so a fee of zero
+/// or one still goes up by at least one wei.
+fn bump_fee(fee: u128, percent: u128) -> Result<u128, FeeError> {
+    if percent < MIN_FEE_BUMP_PERCENT {
+        return Err(FeeError::BumpTooSmall(percent));
+    }
+    let factor = percent.checked_add(100).ok_or(FeeError::Overflow)?;
+    let bumped = fee
+        .checked_mul(factor)
+        .and_then(|f| f.checked_add(99))
+        .ok_or(FeeError::Overflow)?
+        / 100;
+    if bumped > fee {
+        Ok(bumped)
+    } else {
+        fee.checked_add(1).ok_or(FeeError::Overflow)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fees::FeeError;
+    use replacement::FeeBump;
+    use {AccessList, FeeMarketTransaction, LegacyTransaction};
+
+    const GWEI: u128 = 1_000_000_000;
+
+    fn fee_market() -> FeeMarketTransaction {
+        FeeMarketTransaction {
+            chain: 1,
+            nonce: 7,
+            max_priority_fee_per_gas: 2 * GWEI,
+            max_fee_per_gas: 30 * GWEI,
+            gas: 100_000,
+            to: Some([0x45; 20]),
+            value: 1000,
+            data: vec![0xde, 0xad],
+            access_list: AccessList(vec![]),
+        }
+    }
+
+    #[test]
+    fn test_fee_market_replacement() {
+        let replacement = fee_market().replacement(&FeeBump::default()).unwrap();
+
+        assert_eq!(
+            FeeMarketTransaction {
+                max_priority_fee_per_gas: 2_200_000_000,
+                max_fee_per_gas: 33_000_000_000,
+                ..fee_market()
+            },
+            replacement
+        );
+    }
+
+    #[test]
+    fn test_fee_market_cancellation() {
+        let bump = FeeBump {
+            max_fee_percent: 25,
+            priority_fee_percent: 50,
+        };
+        let cancellation = fee_market().cancellation([0x11; 20], &bump).unwrap();
+
+        assert_eq!(
+            FeeMarketTransaction {
+                chain: 1,
+                nonce: 7,
+                max_priority_fee_per_gas: 3 * GWEI,
+                max_fee_per_gas: 37_500_000_000,
+                gas: 21_000,
+                to: Some([0x11; 20]),
+                value: 0,
+                data: vec![],
+                access_list: AccessList(vec![]),
+            },
+            cancellation
+        );
+    }
+
+    #[test]
+    fn test_legacy_replacement_rounds_up() {
+        let tx = LegacyTransaction {
+            chain: 1,
+            nonce: 0,
+            to: Some([0x45; 20]),
+            value: 1000,
+            gas_price: 15,
+            gas: 21_000,
+            data: vec![],
+        };
+
+        assert_eq!(17, tx.replacement(&FeeBump::default()).unwrap().gas_price);
+        assert_eq!(
+            1,
+            LegacyTransaction { gas_price: 0, ..tx }
+                .replacement(&FeeBump::default())
+                .unwrap()
+                .gas_price
+        );
+    }
+
+    #[test]
+    fn test_priority_fee_capped() {
+        let bump = FeeBump {
+            max_fee_percent: 10,
+            priority_fee_percent: 100,
+        };
+        let tx = FeeMarketTransaction {
+            max_priority_fee_per_gas: 20 * GWEI,
+            max_fee_per_gas: 30 * GWEI,
+            ..fee_market()
+        };
+        let replacement = tx.replacement(&bump).unwrap();
+
+        assert_eq!(33 * GWEI, replacement.max_fee_per_gas);
+        assert_eq!(33 * GWEI, replacement.max_priority_fee_per_gas);
+    }
+
+    #[test]
+    fn test_bump_too_small() {
+        assert_eq!(
+            Err(FeeError::BumpTooSmall(5)),
+            fee_market().replacement(&FeeBump::percent(5))
+        );
+        let bump = FeeBump {
+            max_fee_percent: 10,
+            priority_fee_percent: 0,
+        };
+        assert_eq!(
+            Err(FeeError::BumpTooSmall(0)),
+            fee_market().cancellation([0x11; 20], &bump)
+        );
+        let tx = LegacyTransaction::default();
+        assert_eq!(
+            Err(FeeError::BumpTooSmall(9)),
+            tx.replacement(&FeeBump::percent(9))
+        );
+    }
+
+    #[test]
+    fn test_replacement_overflow() {
+        let tx = FeeMarketTransaction {
+            max_fee_per_gas: u128::MAX / 2,
+            ..fee_market()
+        };
+
+        assert_eq!(
+            Err(FeeError::Overflow),
+            tx.replacement(&FeeBump::percent(10))
+        );
+    }
+}
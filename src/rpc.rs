@@ -0,0 +1,472 @@
+//! Builders for the JSON-RPC request bodies used to broadcast, sign and simulate
+//! transactions. The output can be posted with any HTTP client or saved and sent
+//! later from another machine.
+//!
+//! ```
+//! use ethereum_tx_sign::{LegacyTransaction, Transaction};
+//! use ethereum_tx_sign::rpc;
+//!
+//! let tx = LegacyTransaction {
+//!     chain: 1,
+//!     nonce: 0,
+//!     to: Some([0x45; 20]),
+//!     value: 1000,
+//!     gas_price: 20 * 10u128.pow(9),
+//!     gas: 21000,
+//!     data: vec![]
+//! };
+//! let ecdsa = tx.ecdsa(&vec![0x35; 32]).unwrap();
+//! let body = rpc::send_raw_transaction(1, &tx.sign(&ecdsa)).to_json();
+//! assert!(body.starts_with(r#"{"jsonrpc":"2.0","id":1,"method":"eth_sendRawTransaction","params":["0xf86"#));
+//! ```
+use serde::{Serialize, Serializer};
+use serde_json::{self, Map, Value};
+use std::collections::BTreeMap;
+
+use {
+    AccessList, AccessListTransaction, FeeMarketTransaction, LegacyTransaction, Transaction,
+    HEX_PREFIX,
+};
+
+/// A JSON-RPC 2.0 request.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: &'static str,
+    pub id: u64,
+    pub method: &'static str,
+    pub params: Vec<Value>,
+}
+
+impl JsonRpcRequest {
+    pub fn new(id: u64, method: &'static str, params: Vec<Value>) -> Self {
+        JsonRpcRequest {
+            jsonrpc: "2.0",
+            id,
+            method,
+            params,
+        }
+    }
+
+    /// The request body as compact JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("JSON values always serialize")
+    }
+}
+
+/// Block to execute a call against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockId {
+    Number(u64),
+    Hash([u8; 32]),
+    Earliest,
+    Latest,
+    Safe,
+    Finalized,
+    Pending,
+}
+
+impl<'a> From<&'a BlockId> for Value {
+    fn from(block: &'a BlockId) -> Self {
+        match *block {
+            BlockId::Number(n) => quantity(n as u128).into(),
+            BlockId::Hash(ref h) => bytes(h).into(),
+            BlockId::Earliest => "earliest".into(),
+            BlockId::Latest => "latest".into(),
+            BlockId::Safe => "safe".into(),
+            BlockId::Finalized => "finalized".into(),
+            BlockId::Pending => "pending".into(),
+        }
+    }
+}
+
+/// Expected state of an account for `eth_sendRawTransactionConditional`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KnownAccount {
+    /// The account's storage root must match
+    StorageRoot([u8; 32]),
+    /// Each storage slot must hold the given value
+    Slots(BTreeMap<[u8; 32], [u8; 32]>),
+}
+
+/// Conditions under which a block builder may include a transaction sent with
+/// `eth_sendRawTransactionConditional`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionConditions {
+    pub known_accounts: BTreeMap<[u8; 20], KnownAccount>,
+    pub block_number_min: Option<u64>,
+    pub block_number_max: Option<u64>,
+    pub timestamp_min: Option<u64>,
+    pub timestamp_max: Option<u64>,
+}
+
+impl<'a> From<&'a TransactionConditions> for Value {
+    fn from(conditions: &'a TransactionConditions) -> Self {
+        let mut known_accounts = Map::new();
+        for (address, account) in conditions.known_accounts.iter() {
+            let value = match *account {
+                KnownAccount::StorageRoot(ref root) => bytes(root).into(),
+                KnownAccount::Slots(ref slots) => Value::Object(
+                    slots
+                        .iter()
+                        .map(|(slot, value)| (bytes(slot), bytes(value).into()))
+                        .collect(),
+                ),
+            };
+            known_accounts.insert(bytes(address), value);
+        }
+
+        let mut object = Map::new();
+        object.insert("knownAccounts".into(), Value::Object(known_accounts));
+        let bounds = [
+            ("blockNumberMin", conditions.block_number_min),
+            ("blockNumberMax", conditions.block_number_max),
+            ("timestampMin", conditions.timestamp_min),
+            ("timestampMax", conditions.timestamp_max),
+        ];
+        for &(name, bound) in bounds.iter() {
+            if let Some(bound) = bound {
+                object.insert(name.into(), quantity(bound as u128).into());
+            }
+        }
+        Value::Object(object)
+    }
+}
+
+/// A transaction that can be described as a JSON-RPC transaction object.
+pub trait RpcTransaction: Transaction {
+    /// Fields of the transaction object, with quantities as minimal `0x`-prefixed hex.
+    fn to_rpc_object(&self) -> Map<String, Value>;
+}
+
+impl RpcTransaction for LegacyTransaction {
+    fn to_rpc_object(&self) -> Map<String, Value> {
+        let mut object = Map::new();
+        object.insert("type".into(), quantity(0).into());
+        object.insert("chainId".into(), quantity(self.chain as u128).into());
+        object.insert("nonce".into(), quantity(self.nonce).into());
+        object.insert("gasPrice".into(), quantity(self.gas_price).into());
+        object.insert("gas".into(), quantity(self.gas).into());
+        insert_call_fields(&mut object, self.to, self.value, &self.data);
+        object
+    }
+}
+
+impl RpcTransaction for AccessListTransaction {
+    fn to_rpc_object(&self) -> Map<String, Value> {
+        let mut object = Map::new();
+        object.insert(
+            "type".into(),
+            quantity(Self::transaction_type().unwrap() as u128).into(),
+        );
+        object.insert("chainId".into(), quantity(self.chain as u128).into());
+        object.insert("nonce".into(), quantity(self.nonce).into());
+        object.insert("gasPrice".into(), quantity(self.gas_price).into());
+        object.insert("gas".into(), quantity(self.gas).into());
+        insert_call_fields(&mut object, self.to, self.value, &self.data);
+        object.insert("accessList".into(), access_list(&self.access_list));
+        object
+    }
+}
+
+impl RpcTransaction for FeeMarketTransaction {
+    fn to_rpc_object(&self) -> Map<String, Value> {
+        let mut object = Map::new();
+        object.insert(
+            "type".into(),
+            quantity(Self::transaction_type().unwrap() as u128).into(),
+        );
+        object.insert("chainId".into(), quantity(self.chain as u128).into());
+        object.insert("nonce".into(), quantity(self.nonce).into());
+        object.insert(
+            "maxPriorityFeePerGas".into(),
+            quantity(self.max_priority_fee_per_gas).into(),
+        );
+        object.insert("maxFeePerGas".into(), quantity(self.max_fee_per_gas).into());
+        object.insert("gas".into(), quantity(self.gas).into());
+        insert_call_fields(&mut object, self.to, self.value, &self.data);
+        object.insert("accessList".into(), access_list(&self.access_list));
+        object
+    }
+}
+
+/// Serializes a transaction the way JSON-RPC does, with minimal `0x`-prefixed hex
+/// quantities, `0x`-prefixed byte strings, `chainId` and `input`. The crate's
+/// transaction types deserialize from this format as well.
+///
+/// ```
+/// use ethereum_tx_sign::LegacyTransaction;
+/// use ethereum_tx_sign::rpc::RpcFormat;
+///
+/// let tx = LegacyTransaction { chain: 1, gas: 21000, ..Default::default() };
+/// let json = serde_json::to_value(RpcFormat(&tx)).unwrap();
+///
+/// assert_eq!("0x5208", json["gas"]);
+/// assert_eq!(tx, serde_json::from_value(json).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcFormat<'a, T: 'a>(pub &'a T);
+
+impl<'a, T: RpcTransaction> Serialize for RpcFormat<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.to_rpc_object().serialize(serializer)
+    }
+}
+
+/// `eth_sendRawTransaction` with the bytes returned by [`Transaction::sign`].
+pub fn send_raw_transaction(id: u64, signed: &[u8]) -> JsonRpcRequest {
+    JsonRpcRequest::new(id, "eth_sendRawTransaction", vec![bytes(signed).into()])
+}
+
+/// `eth_sendRawTransactionConditional`, which only includes the transaction while
+/// the given conditions hold.
+pub fn send_raw_transaction_conditional(
+    id: u64,
+    signed: &[u8],
+    conditions: &TransactionConditions,
+) -> JsonRpcRequest {
+    JsonRpcRequest::new(
+        id,
+        "eth_sendRawTransactionConditional",
+        vec![bytes(signed).into(), conditions.into()],
+    )
+}
+
+/// `eth_signTransaction`, asking the node to sign with the key of `from`.
+pub fn sign_transaction<T: RpcTransaction>(id: u64, from: [u8; 20], tx: &T) -> JsonRpcRequest {
+    JsonRpcRequest::new(id, "eth_signTransaction", vec![call_object(Some(from), tx)])
+}
+
+/// `eth_estimateGas`. The node uses its default block when `block` is `None`.
+pub fn estimate_gas<T: RpcTransaction>(
+    id: u64,
+    from: Option<[u8; 20]>,
+    tx: &T,
+    block: Option<BlockId>,
+) -> JsonRpcRequest {
+    let mut params = vec![call_object(from, tx)];
+    if let Some(ref block) = block {
+        params.push(block.into());
+    }
+    JsonRpcRequest::new(id, "eth_estimateGas", params)
+}
+
+/// `eth_call` against the given block.
+pub fn call<T: RpcTransaction>(
+    id: u64,
+    from: Option<[u8; 20]>,
+    tx: &T,
+    block: BlockId,
+) -> JsonRpcRequest {
+    JsonRpcRequest::new(id, "eth_call", vec![call_object(from, tx), (&block).into()])
+}
+
+fn call_object<T: RpcTransaction>(from: Option<[u8; 20]>, tx: &T) -> Value {
+    let mut object = tx.to_rpc_object();
+    if let Some(ref from) = from {
+        object.insert("from".into(), bytes(from).into());
+    }
+    Value::Object(object)
+}
+
+fn insert_call_fields(
+    object: &mut Map<String, Value>,
+    to: Option<[u8; 20]>,
+    value: u128,
+    data: &[u8],
+) {
+    if let Some(ref to) = to {
+        object.insert("to".into(), bytes(to).into());
+    }
+    object.insert("value".into(), quantity(value).into());
+    object.insert("input".into(), bytes(data).into());
+}
+
+fn access_list(access_list: &AccessList) -> Value {
+    Value::Array(
+        access_list
+            .0
+            .iter()
+            .map(|access| {
+                let mut object = Map::new();
+                object.insert("address".into(), bytes(&access.address).into());
+                object.insert(
+                    "storageKeys".into(),
+                    Value::Array(
+                        access
+                            .storage_keys
+                            .iter()
+                            .map(|k| bytes(k).into())
+                            .collect(),
+                    ),
+                );
+                Value::Object(object)
+            })
+            .collect(),
+    )
+}
+
+/// Encode a quantity as minimal `0x`-prefixed hex, e.g. `0x0` or `0x5208`.
+pub(crate) fn quantity(n: u128) -> String {
+    format!("0x{:x}", n)
+}
+
+/// Encode big-endian bytes as a minimal quantity, as nodes write `r` and `s`.
+pub(crate) fn quantity_bytes(bytes: &[u8]) -> String {
+    let digits = hex::encode(bytes);
+    let digits = digits.trim_start_matches('0');
+    format!("0x{}", if digits.is_empty() { "0" } else { digits })
+}
+
+/// Encode bytes as `0x`-prefixed hex.
+pub(crate) fn bytes(b: &[u8]) -> String {
+    format!("0x{}", hex::encode(b))
+}
+
+/// Decode a `0x`-prefixed hex quantity. Leading zeroes are tolerated.
+pub(crate) fn parse_quantity(s: &str) -> Option<u128> {
+    let digits = s.strip_prefix(HEX_PREFIX)?;
+    if digits.is_empty() {
+        return None;
+    }
+    u128::from_str_radix(digits, 16).ok()
+}
+
+/// Decode a `0x`-prefixed hex quantity of up to 256 bits into a big-endian word.
+pub(crate) fn parse_word(s: &str) -> Option<[u8; 32]> {
+    let digits = s.strip_prefix(HEX_PREFIX)?;
+    if digits.is_empty() || digits.len() > 64 {
+        return None;
+    }
+    let mut word = [0u8; 32];
+    word.copy_from_slice(&hex::decode(format!("{:0>64}", digits)).ok()?);
+    Some(word)
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::{self, BlockId, KnownAccount, TransactionConditions};
+    use serde_json::{self, Value};
+    use std::collections::BTreeMap;
+    use {Access, AccessList, FeeMarketTransaction};
+
+    fn fee_market() -> FeeMarketTransaction {
+        FeeMarketTransaction {
+            chain: 1,
+            nonce: 0,
+            max_priority_fee_per_gas: 1_000_000_000,
+            max_fee_per_gas: 30_000_000_000,
+            gas: 21_000,
+            to: Some([0x45; 20]),
+            value: 0,
+            data: vec![0xab, 0xcd],
+            access_list: AccessList(vec![Access {
+                address: [0x01; 20],
+                storage_keys: vec![[0x00; 32]],
+            }]),
+        }
+    }
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_send_raw_transaction() {
+        assert_eq!(
+            r#"{"jsonrpc":"2.0","id":7,"method":"eth_sendRawTransaction","params":["0x02c0ff"]}"#,
+            rpc::send_raw_transaction(7, &[0x02, 0xc0, 0xff]).to_json()
+        );
+    }
+
+    #[test]
+    fn test_send_raw_transaction_conditional() {
+        let mut slots = BTreeMap::new();
+        slots.insert([0x00; 32], [0x01; 32]);
+        let mut conditions = TransactionConditions {
+            block_number_max: Some(1000),
+            timestamp_min: Some(0),
+            ..Default::default()
+        };
+        conditions
+            .known_accounts
+            .insert([0xaa; 20], KnownAccount::Slots(slots));
+        conditions
+            .known_accounts
+            .insert([0xbb; 20], KnownAccount::StorageRoot([0x02; 32]));
+
+        let request = rpc::send_raw_transaction_conditional(1, &[0x02], &conditions);
+
+        assert_eq!(
+            json(&format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"eth_sendRawTransactionConditional","params":["0x02",{{
+                    "knownAccounts":{{
+                        "0x{aa}":{{"0x{zero}":"0x{one}"}},
+                        "0x{bb}":"0x{two}"
+                    }},
+                    "blockNumberMax":"0x3e8",
+                    "timestampMin":"0x0"
+                }}]}}"#,
+                aa = "aa".repeat(20),
+                bb = "bb".repeat(20),
+                zero = "00".repeat(32),
+                one = "01".repeat(32),
+                two = "02".repeat(32),
+            )),
+            json(&request.to_json())
+        );
+    }
+
+    #[test]
+    fn test_estimate_gas() {
+        let request = rpc::estimate_gas(3, Some([0x11; 20]), &fee_market(), Some(BlockId::Pending));
+
+        assert_eq!(
+            json(&format!(
+                r#"{{"jsonrpc":"2.0","id":3,"method":"eth_estimateGas","params":[{{
+                    "type":"0x2",
+                    "chainId":"0x1",
+                    "nonce":"0x0",
+                    "maxPriorityFeePerGas":"0x3b9aca00",
+                    "maxFeePerGas":"0x6fc23ac00",
+                    "gas":"0x5208",
+                    "from":"0x{from}",
+                    "to":"0x{to}",
+                    "value":"0x0",
+                    "input":"0xabcd",
+                    "accessList":[{{"address":"0x{address}","storageKeys":["0x{key}"]}}]
+                }},"pending"]}}"#,
+                from = "11".repeat(20),
+                to = "45".repeat(20),
+                address = "01".repeat(20),
+                key = "00".repeat(32),
+            )),
+            json(&request.to_json())
+        );
+    }
+
+    #[test]
+    fn test_call_contract_creation() {
+        let tx = FeeMarketTransaction {
+            to: None,
+            ..fee_market()
+        };
+        let request = rpc::call(4, None, &tx, BlockId::Number(0x10));
+        let params = json(&request.to_json())["params"].clone();
+
+        assert_eq!(Value::Null, params[0]["to"]);
+        assert_eq!(Value::Null, params[0]["from"]);
+        assert_eq!(json(r#""0x10""#), params[1]);
+    }
+
+    #[test]
+    fn test_sign_transaction() {
+        let request = rpc::sign_transaction(5, [0x11; 20], &fee_market());
+        let params = json(&request.to_json())["params"].clone();
+
+        assert_eq!("eth_signTransaction", request.method);
+        assert_eq!(
+            json(&format!(r#""0x{}""#, "11".repeat(20))),
+            params[0]["from"]
+        );
+    }
+}
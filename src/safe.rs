@@ -0,0 +1,429 @@
+//! Transactions of [Safe](https://safe.global) multisig wallets, version 1.3.0 and later.
+//!
+//! Owners sign the EIP-712 hash of a [`SafeTransaction`]. Once enough owners have signed,
+//! anyone can submit the `execTransaction` call to the Safe.
+//!
+//! ```
+//! use ethereum_tx_sign::safe::SafeTransaction;
+//! use ethereum_tx_sign::{FeeMarketTransaction, Transaction};
+//!
+//! let safe = [0x5a; 20];
+//! let safe_tx = SafeTransaction {
+//!     to: [0x45; 20],
+//!     value: 10u128.pow(18),
+//!     nonce: 3,
+//!     ..Default::default()
+//! };
+//! let signatures = vec![
+//!     safe_tx.sign(&safe, 1, &[0x35; 32]).unwrap(),
+//!     safe_tx.sign(&safe, 1, &[0x36; 32]).unwrap(),
+//! ];
+//!
+//! let tx = FeeMarketTransaction {
+//!     chain: 1,
+//!     to: Some(safe),
+//!     data: safe_tx.exec_transaction_data(&signatures).unwrap(),
+//!     ..Default::default()
+//! };
+//! ```
+use std::fmt;
+
+use abi::{self, Token};
+use {eth_signed_message_hash, keccak256_hash, normalize_v, EcdsaSig, Error, ToPrivateKey};
+
+/// `keccak256("EIP712Domain(uint256 chainId,address verifyingContract)")`
+const DOMAIN_SEPARATOR_TYPEHASH: [u8; 32] = [
+    0x47, 0xe7, 0x95, 0x34, 0xa2, 0x45, 0x95, 0x2e, 0x8b, 0x16, 0x89, 0x3a, 0x33, 0x6b, 0x85, 0xa3,
+    0xd9, 0xea, 0x9f, 0xa8, 0xc5, 0x73, 0xf3, 0xd8, 0x03, 0xaf, 0xb9, 0x2a, 0x79, 0x46, 0x92, 0x18,
+];
+/// `keccak256("SafeTx(address to,uint256 value,bytes data,uint8 operation,uint256 safeTxGas,uint256 baseGas,uint256 gasPrice,address gasToken,address refundReceiver,uint256 nonce)")`
+const SAFE_TX_TYPEHASH: [u8; 32] = [
+    0xbb, 0x83, 0x10, 0xd4, 0x86, 0x36, 0x8d, 0xb6, 0xbd, 0x6f, 0x84, 0x94, 0x02, 0xfd, 0xd7, 0x3a,
+    0xd5, 0x3d, 0x31, 0x6b, 0x5a, 0x4b, 0x26, 0x44, 0xad, 0x6e, 0xfe, 0x0f, 0x94, 0x12, 0x86, 0xd8,
+];
+/// Selector of `execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)`
+const EXEC_TRANSACTION_SELECTOR: [u8; 4] = [0x6a, 0x76, 0x12, 0x02];
+
+#[derive(Debug)]
+pub enum SafeError {
+    /// The owner has more than one signature; the Safe requires each owner at most once.
+    DuplicateOwner([u8; 20]),
+    /// The signature is invalid or its signer cannot be recovered.
+    Signature(Error),
+}
+
+impl fmt::Display for SafeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SafeError::DuplicateOwner(ref owner) => {
+                write!(f, "more than one signature by 0x{}", hex::encode(owner))
+            }
+            SafeError::Signature(ref err) => write!(f, "invalid signature: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for SafeError {}
+
+impl From<Error> for SafeError {
+    fn from(error: Error) -> Self {
+        SafeError::Signature(error)
+    }
+}
+
+/// How the Safe executes the transaction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SafeOperation {
+    #[default]
+    Call = 0,
+    DelegateCall = 1,
+}
+
+/// A transaction to be executed by a Safe. The gas and refund fields are zero for
+/// transactions paid for by the account that submits them.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SafeTransaction {
+    /// Address the Safe calls
+    pub to: [u8; 20],
+    /// Value sent by the Safe
+    pub value: u128,
+    /// Input data of the call
+    pub data: Vec<u8>,
+    pub operation: SafeOperation,
+    /// Gas for the call, when the refund is paid by the Safe
+    pub safe_tx_gas: u128,
+    /// Gas paid for by the Safe on top of the call, for the refund
+    pub base_gas: u128,
+    /// Gas price used for the refund
+    pub gas_price: u128,
+    /// Token the refund is paid in, zero for ether
+    pub gas_token: [u8; 20],
+    /// Receiver of the refund, zero for the submitting account
+    pub refund_receiver: [u8; 20],
+    /// Nonce of the Safe
+    pub nonce: u128,
+}
+
+impl SafeTransaction {
+    /// The EIP-712 `SafeTx` hash that owners sign, for the Safe at `safe` on chain `chain`.
+    pub fn hash(&self, safe: &[u8; 20], chain: u64) -> [u8; 32] {
+        let domain = abi::encode(&[
+            Token::Word(DOMAIN_SEPARATOR_TYPEHASH),
+            Token::uint(chain as u128),
+            Token::address(safe),
+        ]);
+        let message = abi::encode(&[
+            Token::Word(SAFE_TX_TYPEHASH),
+            Token::address(&self.to),
+            Token::uint(self.value),
+            Token::Word(keccak256_hash(&self.data)),
+            Token::uint(self.operation as u128),
+            Token::uint(self.safe_tx_gas),
+            Token::uint(self.base_gas),
+            Token::uint(self.gas_price),
+            Token::address(&self.gas_token),
+            Token::address(&self.refund_receiver),
+            Token::uint(self.nonce),
+        ]);
+
+        let mut encoded = vec![0x19, 0x01];
+        encoded.extend_from_slice(&keccak256_hash(&domain));
+        encoded.extend_from_slice(&keccak256_hash(&message));
+        keccak256_hash(&encoded)
+    }
+
+    /// Sign the transaction hash with an owner's private key.
+    pub fn sign<K: ToPrivateKey + ?Sized>(
+        &self,
+        safe: &[u8; 20],
+        chain: u64,
+        private_key: &K,
+    ) -> Result<SafeSignature, Error> {
+        let hash = self.hash(safe, chain);
+        let signature = EcdsaSig::generate(hash, private_key, None)?;
+        SafeSignature::from_ecdsa(hash, signature)
+    }
+
+    /// Input data of the Safe's `execTransaction` function. The signatures are sorted by
+    /// owner, as the Safe requires.
+    pub fn exec_transaction_data(
+        &self,
+        signatures: &[SafeSignature],
+    ) -> Result<Vec<u8>, SafeError> {
+        let signatures = encode_signatures(signatures)?;
+        Ok(abi::encode_call(
+            EXEC_TRANSACTION_SELECTOR,
+            &[
+                Token::address(&self.to),
+                Token::uint(self.value),
+                Token::Bytes(&self.data),
+                Token::uint(self.operation as u128),
+                Token::uint(self.safe_tx_gas),
+                Token::uint(self.base_gas),
+                Token::uint(self.gas_price),
+                Token::address(&self.gas_token),
+                Token::address(&self.refund_receiver),
+                Token::Bytes(&signatures),
+            ],
+        ))
+    }
+}
+
+/// An owner's approval of a Safe transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SafeSignature {
+    /// Signature of the transaction hash
+    Ecdsa {
+        owner: [u8; 20],
+        signature: EcdsaSig,
+    },
+    /// `eth_sign` (`personal_sign`) signature of the transaction hash, as made by wallets
+    /// that cannot sign a bare hash
+    EthSign {
+        owner: [u8; 20],
+        signature: EcdsaSig,
+    },
+    /// The owner approved the hash with `approveHash`, or is the account submitting
+    /// the transaction
+    PreApproved { owner: [u8; 20] },
+}
+
+impl SafeSignature {
+    /// Signature of the transaction hash. `v` may be the recovery ID or the recovery ID
+    /// plus 27.
+    pub fn from_ecdsa(hash: [u8; 32], signature: EcdsaSig) -> Result<Self, Error> {
+        let signature = EcdsaSig {
+            v: normalize_v(signature.v, None)?,
+            ..signature
+        };
+        Ok(SafeSignature::Ecdsa {
+            owner: signature.recover(hash, None)?,
+            signature,
+        })
+    }
+
+    /// `eth_sign` signature of the transaction hash. `v` may be the recovery ID, the
+    /// recovery ID plus 27 or the Safe's encoding, the recovery ID plus 31.
+    pub fn from_eth_sign(hash: [u8; 32], signature: EcdsaSig) -> Result<Self, Error> {
+        let v = match signature.v {
+            31..=32 => signature.v - 31,
+            v => normalize_v(v, None)?,
+        };
+        let signature = EcdsaSig { v, ..signature };
+
+        Ok(SafeSignature::EthSign {
+            owner: signature.recover(eth_signed_message_hash(&hash), None)?,
+            signature,
+        })
+    }
+
+    pub fn owner(&self) -> [u8; 20] {
+        match *self {
+            SafeSignature::Ecdsa { owner, .. }
+            | SafeSignature::EthSign { owner, .. }
+            | SafeSignature::PreApproved { owner } => owner,
+        }
+    }
+
+    /// The 65 byte `r || s || v` form the Safe contract decodes. Fails if `r` or `s` is
+    /// longer than 32 bytes or `v` is invalid.
+    pub fn encode(&self) -> Result<[u8; 65], Error> {
+        let mut out = [0u8; 65];
+        match *self {
+            SafeSignature::Ecdsa { ref signature, .. } => {
+                out[..64].copy_from_slice(&signature.rs()?);
+                out[64] = signature.y_parity()? + 27;
+            }
+            SafeSignature::EthSign { ref signature, .. } => {
+                out[..64].copy_from_slice(&signature.rs()?);
+                out[64] = signature.y_parity()? + 31;
+            }
+            SafeSignature::PreApproved { ref owner } => {
+                out[12..32].copy_from_slice(owner);
+                out[64] = 1;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Concatenate signatures sorted by owner, the format of `execTransaction`'s `signatures`.
+pub fn encode_signatures(signatures: &[SafeSignature]) -> Result<Vec<u8>, SafeError> {
+    let mut sorted: Vec<&SafeSignature> = signatures.iter().collect();
+    sorted.sort_by_key(|s| s.owner());
+    if let Some(pair) = sorted.windows(2).find(|w| w[0].owner() == w[1].owner()) {
+        return Err(SafeError::DuplicateOwner(pair[0].owner()));
+    }
+
+    let mut encoded = Vec::with_capacity(sorted.len() * 65);
+    for signature in sorted {
+        encoded.extend_from_slice(&signature.encode()?);
+    }
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod test {
+    use safe::{
+        encode_signatures, SafeError, SafeOperation, SafeSignature, SafeTransaction,
+        DOMAIN_SEPARATOR_TYPEHASH, EXEC_TRANSACTION_SELECTOR, SAFE_TX_TYPEHASH,
+    };
+    use {eth_signed_message_hash, keccak256_hash, EcdsaSig, Error};
+
+    const SAFE: [u8; 20] = [0x5a; 20];
+
+    fn safe_tx() -> SafeTransaction {
+        SafeTransaction {
+            to: [0x45; 20],
+            value: 1000,
+            data: vec![0xa9, 0x05, 0x9c, 0xbb, 0x01],
+            operation: SafeOperation::Call,
+            nonce: 7,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_typehashes() {
+        assert_eq!(
+            DOMAIN_SEPARATOR_TYPEHASH,
+            keccak256_hash(b"EIP712Domain(uint256 chainId,address verifyingContract)")
+        );
+        assert_eq!(
+            SAFE_TX_TYPEHASH,
+            keccak256_hash(
+                b"SafeTx(address to,uint256 value,bytes data,uint8 operation,uint256 safeTxGas,\
+                  uint256 baseGas,uint256 gasPrice,address gasToken,address refundReceiver,\
+                  uint256 nonce)"
+            )
+        );
+    }
+
+    #[test]
+    fn test_exec_transaction_selector() {
+        let hash = keccak256_hash(
+            b"execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)",
+        );
+        assert_eq!(EXEC_TRANSACTION_SELECTOR, hash[..4]);
+    }
+
+    #[test]
+    fn test_hash_depends_on_domain() {
+        let tx = safe_tx();
+        let hash = tx.hash(&SAFE, 1);
+        assert_ne!(hash, tx.hash(&SAFE, 5));
+        assert_ne!(hash, tx.hash(&[0x5b; 20], 1));
+        assert_ne!(
+            hash,
+            SafeTransaction {
+                operation: SafeOperation::DelegateCall,
+                ..tx
+            }
+            .hash(&SAFE, 1)
+        );
+    }
+
+    /// Safe transaction 234 of a Safe on Arbitrum One, an `addOwnerWithThreshold` call, with
+    /// the hash the Safe Transaction Service and `safe_hashes` report for it.
+    #[test]
+    fn test_hash_arbitrum_transaction() {
+        let mut safe = [0u8; 20];
+        safe.copy_from_slice(&hex::decode("111ceeee040739fd91d29c34c33e6b3e112f2177").unwrap());
+        let tx = SafeTransaction {
+            to: safe,
+            data: hex::decode(
+                "0d582f130000000000000000000000000c75fa5a5f1c0997e3eea425cfa13184ed0ec9e5\
+                 0000000000000000000000000000000000000000000000000000000000000003",
+            )
+            .unwrap(),
+            nonce: 234,
+            ..Default::default()
+        };
+        assert_eq!(
+            "0cb7250b8becd7069223c54e2839feaed4cee156363fbfe5dd0a48e75c4e25b3",
+            hex::encode(tx.hash(&safe, 42161))
+        );
+    }
+
+    #[test]
+    fn test_signatures() {
+        let tx = safe_tx();
+        let hash = tx.hash(&SAFE, 1);
+        let first = tx.sign(&SAFE, 1, &[0x35; 32]).unwrap();
+        let second = tx.sign(&SAFE, 1, &[0x36; 32]).unwrap();
+
+        let eth_sign =
+            EcdsaSig::generate(eth_signed_message_hash(&hash), &[0x37; 32], None).unwrap();
+        let third = SafeSignature::from_eth_sign(hash, eth_sign).unwrap();
+        let approved = SafeSignature::PreApproved { owner: [0x01; 20] };
+
+        let signatures = vec![first.clone(), second, third, approved.clone()];
+        let encoded = encode_signatures(&signatures).unwrap();
+
+        let mut sorted = signatures.clone();
+        sorted.sort_by_key(SafeSignature::owner);
+        assert_eq!(approved, sorted[0]);
+        let expected: Vec<u8> = sorted
+            .iter()
+            .flat_map(|s| s.encode().unwrap().to_vec())
+            .collect();
+        assert_eq!(expected, encoded);
+
+        let v: Vec<u8> = signatures.iter().map(|s| s.encode().unwrap()[64]).collect();
+        assert!(v[0] == 27 || v[0] == 28);
+        assert!(v[2] == 31 || v[2] == 32);
+        assert_eq!(1, v[3]);
+        assert_eq!(&[0x01; 20][..], &approved.encode().unwrap()[12..32]);
+
+        match encode_signatures(&[first.clone(), first]) {
+            Err(SafeError::DuplicateOwner(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_encode_long_signature() {
+        let signature = SafeSignature::Ecdsa {
+            owner: [0x01; 20],
+            signature: EcdsaSig {
+                v: 0,
+                r: vec![0x01; 33],
+                s: vec![0x01; 32],
+            },
+        };
+        match signature.encode() {
+            Err(Error::InvalidSignatureLength(33)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match encode_signatures(&[signature]) {
+            Err(SafeError::Signature(Error::InvalidSignatureLength(33))) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_exec_transaction_data() {
+        let tx = safe_tx();
+        let signature = tx.sign(&SAFE, 1, &[0x35; 32]).unwrap();
+        let data = tx
+            .exec_transaction_data(std::slice::from_ref(&signature))
+            .unwrap();
+
+        assert_eq!("6a761202", hex::encode(&data[..4]));
+        let words: Vec<&[u8]> = data[4..].chunks(32).collect();
+        assert_eq!(&[0x45; 20][..], &words[0][12..]);
+        // offsets of `data` and `signatures`
+        assert_eq!(320, u16::from_be_bytes([words[2][30], words[2][31]]));
+        assert_eq!(384, u16::from_be_bytes([words[9][30], words[9][31]]));
+        // `data` is padded to a whole word
+        assert_eq!(5, words[10][31]);
+        assert_eq!(&tx.data[..], &words[11][..5]);
+        // the signature is padded to three words
+        assert_eq!(65, words[12][31]);
+        assert_eq!(
+            &signature.encode().unwrap()[..],
+            &data[4 + 13 * 32..4 + 13 * 32 + 65]
+        );
+        assert_eq!(4 + 16 * 32, data.len());
+    }
+}
@@ -0,0 +1,442 @@
+use std::fmt;
+use std::str::FromStr;
+
+use backend;
+use {normalize_v, public_key_address, EcdsaSig, Error};
+
+/// DER prefix of a secp256k1 `SubjectPublicKeyInfo` holding an uncompressed key, the form
+/// in which key management services export public keys.
+const SPKI_UNCOMPRESSED_PREFIX: [u8; 23] = [
+    0x30, 0x56, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b,
+    0x81, 0x04, 0x00, 0x0a, 0x03, 0x42, 0x00,
+];
+/// As [`SPKI_UNCOMPRESSED_PREFIX`], for a compressed key.
+const SPKI_COMPRESSED_PREFIX: [u8; 23] = [
+    0x30, 0x36, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b,
+    0x81, 0x04, 0x00, 0x0a, 0x03, 0x22, 0x00,
+];
+
+/// Conversions between [`EcdsaSig`] and the other forms signatures are exchanged in.
+///
+/// Where a `chain` is taken it follows [`EcdsaSig::recover`]: pass the chain ID for legacy
+/// transactions, whose `v` is encoded according to
+/// [EIP-155](https://eips.ethereum.org/EIPS/eip-155), and `None` for typed transactions,
+/// whose `v` is the y-parity.
+/// ```
+/// use ethereum_tx_sign::{EcdsaSig, LegacyTransaction, Transaction};
+///
+/// let tx = LegacyTransaction { chain: 1, gas: 21000, ..Default::default() };
+/// let ecdsa = tx.ecdsa(&vec![0x35; 32]).unwrap();
+///
+/// let compact = ecdsa.to_compact().unwrap();
+/// assert_eq!(ecdsa, EcdsaSig::from_compact(&compact, Some(1)).unwrap());
+///
+/// let hex = ecdsa.to_string();
+/// let parsed: EcdsaSig = hex.parse().unwrap();
+/// assert_eq!(ecdsa, parsed.for_chain(Some(1)).unwrap());
+/// ```
+impl EcdsaSig {
+    /// Build a signature from `r`, `s` and a `v` that may be the y-parity, the y-parity
+    /// plus 27 or, given a `chain`, an EIP-155 value. `r` and `s` may have had their
+    /// leading zeroes stripped.
+    pub fn from_parts(r: &[u8], s: &[u8], v: u64, chain: Option<u64>) -> Result<Self, Error> {
+        if r.len() > 32 || s.len() > 32 {
+            return Err(Error::InvalidSignatureLength(r.len().max(s.len())));
+        }
+        let ecdsa = EcdsaSig {
+            v: normalize_v(v, chain)?,
+            r: left_pad(r).to_vec(),
+            s: left_pad(s).to_vec(),
+        };
+        // an EIP-155 value must be for this chain
+        ecdsa.for_chain(chain)?;
+        Ok(ecdsa)
+    }
+
+    /// Parse the 65 byte `r || s || v` form.
+    pub fn from_rsv(signature: &[u8], chain: Option<u64>) -> Result<Self, Error> {
+        if signature.len() != 65 {
+            return Err(Error::InvalidSignatureLength(signature.len()));
+        }
+        Self::from_parts(
+            &signature[..32],
+            &signature[32..64],
+            signature[64] as u64,
+            chain,
+        )
+    }
+
+    /// The 65 byte `r || s || v` form, with `v` as 27 or 28.
+    pub fn to_rsv(&self) -> Result<[u8; 65], Error> {
+        let mut rsv = [0u8; 65];
+        rsv[..64].copy_from_slice(&self.rs()?);
+        rsv[64] = 27 + self.y_parity()?;
+        Ok(rsv)
+    }
+
+    /// Parse the 64 byte [EIP-2098](https://eips.ethereum.org/EIPS/eip-2098) form, where the
+    /// y-parity is the top bit of `s`.
+    pub fn from_compact(signature: &[u8], chain: Option<u64>) -> Result<Self, Error> {
+        if signature.len() != 64 {
+            return Err(Error::InvalidSignatureLength(signature.len()));
+        }
+        let mut s = [0u8; 32];
+        s.copy_from_slice(&signature[32..]);
+        let y_parity = s[0] >> 7;
+        s[0] &= 0x7f;
+        Self::from_parts(&signature[..32], &s, y_parity as u64, chain)
+    }
+
+    /// The 64 byte [EIP-2098](https://eips.ethereum.org/EIPS/eip-2098) form. Only signatures
+    /// with a low `s`, as produced by [`ecdsa`](::Transaction::ecdsa), can be represented.
+    pub fn to_compact(&self) -> Result<[u8; 64], Error> {
+        let mut compact = self.rs()?;
+        if compact[32] & 0x80 != 0 {
+            return Err(Error::Secp256k1(backend::invalid_signature()));
+        }
+        compact[32] |= self.y_parity()? << 7;
+        Ok(compact)
+    }
+
+    /// The y-parity (0 or 1) encoded in `v`, whichever encoding `v` uses.
+    pub fn y_parity(&self) -> Result<u8, Error> {
+        match self.v {
+            0 | 1 => Ok(self.v as u8),
+            27 | 28 => Ok((self.v - 27) as u8),
+            v if v >= 35 => Ok(((v - 35) % 2) as u8),
+            v => Err(Error::InvalidV(v)),
+        }
+    }
+
+    /// The same signature with `v` encoded for a transaction on `chain`: EIP-155 for legacy
+    /// transactions and the y-parity for typed transactions (`None`).
+    pub fn for_chain(&self, chain: Option<u64>) -> Result<Self, Error> {
+        if let (Some(c), true) = (chain, self.v >= 35) {
+            if (self.v - 35) / 2 != c {
+                return Err(Error::InvalidV(self.v));
+            }
+        }
+        Ok(EcdsaSig {
+            v: normalize_v(self.y_parity()? as u64, chain)?,
+            r: self.r.clone(),
+            s: self.s.clone(),
+        })
+    }
+
+    /// Convert an ASN.1 DER signature of `hash`, as returned by key management services and
+    /// HSMs, which carries no recovery ID. `s` is normalised to the lower half of the curve
+    /// order and the recovery ID is found by recovering the public key for both candidates and
+    /// comparing it with `signer`.
+    pub fn from_der(
+        der: &[u8],
+        hash: [u8; 32],
+        signer: &[u8; 20],
+        chain: Option<u64>,
+    ) -> Result<Self, Error> {
+        Self::recoverable(backend::der_to_rs(der)?, hash, signer, chain)
+    }
+
+    /// Like [`from_der`](EcdsaSig::from_der), for the 64 byte `r || s` signature without a
+    /// recovery ID that PKCS#11 tokens return.
+    pub fn from_rs(
+        signature: &[u8],
+        hash: [u8; 32],
+        signer: &[u8; 20],
+        chain: Option<u64>,
+    ) -> Result<Self, Error> {
+        if signature.len() != 64 {
+            return Err(Error::InvalidSignatureLength(signature.len()));
+        }
+        let mut rs = [0u8; 64];
+        rs.copy_from_slice(signature);
+        Self::recoverable(backend::normalize_rs(&rs)?, hash, signer, chain)
+    }
+
+    /// Find the recovery ID of `compact`, whose `s` has been normalised.
+    fn recoverable(
+        compact: [u8; 64],
+        hash: [u8; 32],
+        signer: &[u8; 20],
+        chain: Option<u64>,
+    ) -> Result<Self, Error> {
+        for id in 0..2 {
+            let recovered = match backend::recover(&hash, &compact, id) {
+                Ok(public_key) => public_key_address(&public_key),
+                Err(_) => continue,
+            };
+            if recovered == *signer {
+                return Self::from_parts(&compact[..32], &compact[32..], id as u64, chain);
+            }
+        }
+        Err(Error::UnknownSigner(*signer))
+    }
+
+    pub(crate) fn rs(&self) -> Result<[u8; 64], Error> {
+        if self.r.len() > 32 || self.s.len() > 32 {
+            return Err(Error::InvalidSignatureLength(
+                self.r.len().max(self.s.len()),
+            ));
+        }
+        let mut rs = [0u8; 64];
+        rs[..32].copy_from_slice(&left_pad(&self.r));
+        rs[32..].copy_from_slice(&left_pad(&self.s));
+        Ok(rs)
+    }
+}
+
+/// Address of the account with the given secp256k1 public key, either SEC1 encoded (33 or 65
+/// bytes) or as the DER `SubjectPublicKeyInfo` that key management services export.
+pub fn public_key_to_address(public_key: &[u8]) -> Result<[u8; 20], Error> {
+    let sec1 = if public_key.starts_with(&SPKI_UNCOMPRESSED_PREFIX)
+        || public_key.starts_with(&SPKI_COMPRESSED_PREFIX)
+    {
+        &public_key[SPKI_UNCOMPRESSED_PREFIX.len()..]
+    } else {
+        public_key
+    };
+    Ok(public_key_address(&backend::parse_public_key(sec1)?))
+}
+
+/// Parses the hex of the 65 byte `r || s || v` or 64 byte EIP-2098 form, with or without
+/// `0x`. `v` is the y-parity; use [`for_chain`](EcdsaSig::for_chain) to sign a legacy
+/// transaction.
+impl FromStr for EcdsaSig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let bytes = hex::decode(s.strip_prefix("0x").unwrap_or(s))?;
+        match bytes.len() {
+            64 => Self::from_compact(&bytes, None),
+            _ => Self::from_rsv(&bytes, None),
+        }
+    }
+}
+
+/// The hex of the 65 byte `r || s || v` form, with `v` as 27 or 28. Signatures that have
+/// no such form are shown as their fields.
+impl fmt::Display for EcdsaSig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_rsv() {
+            Ok(rsv) => write!(f, "0x{}", hex::encode(&rsv[..])),
+            Err(_) => write!(
+                f,
+                "{{ v: {}, r: 0x{}, s: 0x{} }}",
+                self.v,
+                hex::encode(&self.r),
+                hex::encode(&self.s)
+            ),
+        }
+    }
+}
+
+fn left_pad(bytes: &[u8]) -> [u8; 32] {
+    let mut padded = [0u8; 32];
+    padded[32 - bytes.len()..].copy_from_slice(bytes);
+    padded
+}
+
+#[cfg(test)]
+mod test {
+    use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+    use signature::public_key_to_address;
+    use {keccak256_hash, EcdsaSig, Error, FeeMarketTransaction, LegacyTransaction, Transaction};
+
+    const SECP256K1_ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36,
+        0x41, 0x41,
+    ];
+
+    /// Stands in for a key management service: signs `hash` without a recovery ID and
+    /// returns the DER signature, optionally with the high `s` some services produce.
+    fn kms_sign(hash: [u8; 32], private_key: &[u8], high_s: bool) -> Vec<u8> {
+        let secp = Secp256k1::signing_only();
+        let msg = Message::from_slice(&hash).unwrap();
+        let key = SecretKey::from_slice(private_key).unwrap();
+        let signature = secp.sign_ecdsa(&msg, &key);
+        if !high_s {
+            return signature.serialize_der().to_vec();
+        }
+        // s' = n - s
+        let mut compact = signature.serialize_compact();
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let d = SECP256K1_ORDER[i] as i16 - compact[32 + i] as i16 - borrow;
+            borrow = (d < 0) as i16;
+            compact[32 + i] = (d + 256 * borrow) as u8;
+        }
+        secp256k1::ecdsa::Signature::from_compact(&compact)
+            .unwrap()
+            .serialize_der()
+            .to_vec()
+    }
+
+    fn kms_public_key(private_key: &[u8]) -> PublicKey {
+        let key = SecretKey::from_slice(private_key).unwrap();
+        PublicKey::from_secret_key(&Secp256k1::signing_only(), &key)
+    }
+
+    fn eth_signed_message_hash(message: &[u8]) -> [u8; 32] {
+        let prefix = format!("\x19Ethereum Signed Message:\n{}", message.len());
+        keccak256_hash(&[prefix.as_bytes(), message].concat())
+    }
+
+    fn legacy() -> LegacyTransaction {
+        LegacyTransaction {
+            chain: 5,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rsv() {
+        let tx = legacy();
+        let ecdsa = tx.ecdsa(&[0x35; 32]).unwrap();
+        let rsv = ecdsa.to_rsv().unwrap();
+        assert_eq!(ecdsa.y_parity().unwrap() + 27, rsv[64]);
+        assert_eq!(ecdsa, EcdsaSig::from_rsv(&rsv, Some(5)).unwrap());
+
+        // the same signature on a typed transaction
+        let typed = EcdsaSig::from_rsv(&rsv, None).unwrap();
+        assert_eq!(ecdsa.v - 45, typed.v);
+        assert_eq!(ecdsa, typed.for_chain(Some(5)).unwrap());
+        assert_eq!(typed, ecdsa.for_chain(None).unwrap());
+
+        match EcdsaSig::from_rsv(&rsv[1..], None) {
+            Err(Error::InvalidSignatureLength(64)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        // EIP-155 value of another chain
+        match ecdsa.for_chain(Some(1)) {
+            Err(Error::InvalidV(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        // EIP-2098 test vectors, personal signatures by key 0x1234567890...1234
+        let signer = hex::decode("2e988a386a799f506693793c6a5af6b54dfaabfb").unwrap();
+        let vectors = [
+            (
+                "Hello World",
+                "68a020a209d3d56c46f38cc50a33f704f4a9a10a59377f8dd762ac66910e9b90",
+                "7e865ad05c4035ab5792787d4a0297a43617ae897930a6fe4d822b8faea52064",
+                "7e865ad05c4035ab5792787d4a0297a43617ae897930a6fe4d822b8faea52064",
+                0,
+            ),
+            (
+                "It's a small(er) world",
+                "9328da16089fcba9bececa81663203989f2df5fe1faa6291a45381c81bd17f76",
+                "139c6d6b623b42da56557e5e734a43dc83345ddfadec52cbe24d0cc64f550793",
+                "939c6d6b623b42da56557e5e734a43dc83345ddfadec52cbe24d0cc64f550793",
+                1,
+            ),
+        ];
+        for &(message, r, s, y_parity_and_s, y_parity) in vectors.iter() {
+            let hash = eth_signed_message_hash(message.as_bytes());
+            let compact = hex::decode(format!("{}{}", r, y_parity_and_s)).unwrap();
+            let ecdsa = EcdsaSig::from_compact(&compact, None).unwrap();
+            assert_eq!(y_parity, ecdsa.v);
+            assert_eq!(hex::decode(s).unwrap(), ecdsa.s);
+            assert_eq!(signer, ecdsa.recover(hash, None).unwrap().to_vec());
+            assert_eq!(compact, ecdsa.to_compact().unwrap().to_vec());
+        }
+
+        let high_s = EcdsaSig {
+            v: 0,
+            r: vec![1; 32],
+            s: vec![0xff; 32],
+        };
+        assert!(high_s.to_compact().is_err());
+    }
+
+    #[test]
+    fn test_der() {
+        let key = [0x35; 32];
+        let signer = public_key_to_address(&kms_public_key(&key).serialize()).unwrap();
+
+        let legacy = legacy();
+        let typed = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        for &high_s in [false, true].iter() {
+            let der = kms_sign(legacy.hash(), &key, high_s);
+            let ecdsa = legacy.ecdsa_from_der(&der, &signer).unwrap();
+            assert_eq!(legacy.ecdsa(&key).unwrap(), ecdsa);
+            assert_eq!(
+                legacy.sign(&ecdsa),
+                legacy.sign_verified(&ecdsa, &signer).unwrap()
+            );
+
+            let der = kms_sign(typed.hash(), &key, high_s);
+            assert_eq!(
+                typed.ecdsa(&key).unwrap(),
+                typed.ecdsa_from_der(&der, &signer).unwrap()
+            );
+        }
+
+        let other = public_key_to_address(&kms_public_key(&[0x36; 32]).serialize()).unwrap();
+        let der = kms_sign(typed.hash(), &key, false);
+        match typed.ecdsa_from_der(&der, &other) {
+            Err(Error::UnknownSigner(address)) => assert_eq!(other, address),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match typed.ecdsa_from_der(&der[1..], &signer) {
+            Err(Error::Secp256k1(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_public_key_to_address() {
+        let public_key = kms_public_key(&[0x35; 32]);
+        let address = hex::decode("8b11c43881b3f9148493c2edad543e97efdc517a").unwrap();
+        let uncompressed = public_key.serialize_uncompressed();
+        assert_eq!(address, public_key_to_address(&uncompressed).unwrap());
+        assert_eq!(
+            address,
+            public_key_to_address(&public_key.serialize()).unwrap()
+        );
+
+        // SubjectPublicKeyInfo, as exported by key management services
+        let spki = [
+            hex::decode("3056301006072a8648ce3d020106052b8104000a034200").unwrap(),
+            uncompressed.to_vec(),
+        ]
+        .concat();
+        assert_eq!(address, public_key_to_address(&spki).unwrap());
+        assert!(public_key_to_address(&uncompressed[1..]).is_err());
+    }
+
+    #[test]
+    fn test_hex() {
+        let tx = FeeMarketTransaction {
+            chain: 1,
+            gas: 21000,
+            ..Default::default()
+        };
+        let ecdsa = tx.ecdsa(&[0x35; 32]).unwrap();
+        let hex = ecdsa.to_string();
+        assert_eq!(132, hex.len());
+        assert_eq!(ecdsa, hex.parse().unwrap());
+        assert_eq!(ecdsa, hex[2..].parse().unwrap());
+        assert!(format!("0x{}", hex).parse::<EcdsaSig>().is_err());
+        let compact = hex::encode(ecdsa.to_compact().unwrap());
+        assert_eq!(ecdsa, compact.parse().unwrap());
+
+        // stripped leading zeroes are restored
+        let stripped = EcdsaSig::from_parts(&ecdsa.r[1..], &ecdsa.s, 27, None);
+        assert_eq!(32, stripped.unwrap().r.len());
+
+        assert!("0xzz".parse::<EcdsaSig>().is_err());
+        match "0x00".parse::<EcdsaSig>() {
+            Err(Error::InvalidSignatureLength(1)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
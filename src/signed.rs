@@ -0,0 +1,482 @@
+use serde::de::{Deserialize, DeserializeOwned, Deserializer, Error as DeError};
+use serde::ser::{Error as SerError, Serialize, Serializer};
+use serde_json::{self, Map, Value};
+use std::fmt;
+
+use rpc::{self, parse_quantity, RpcTransaction};
+use {keccak256_hash, EcdsaSig, Error, HEX_PREFIX};
+
+/// A transaction together with its signature.
+///
+/// It serializes to and deserializes from the JSON that nodes return from
+/// `eth_getTransactionByHash`, so it can be stored in the same shape as node data.
+/// When deserializing, the `hash` and `from` fields must match what the crate computes.
+///
+/// ```
+/// use ethereum_tx_sign::{LegacyTransaction, SignedTransaction, Transaction};
+///
+/// let tx = LegacyTransaction { chain: 1, gas: 21000, ..Default::default() };
+/// let ecdsa = tx.ecdsa(&vec![0x35; 32]).unwrap();
+/// let signed = SignedTransaction::new(tx, ecdsa);
+///
+/// let json = serde_json::to_string(&signed).unwrap();
+/// let decoded: SignedTransaction<LegacyTransaction> = serde_json::from_str(&json).unwrap();
+/// assert_eq!(signed, decoded);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTransaction<T> {
+    pub transaction: T,
+    pub signature: EcdsaSig,
+}
+
+#[derive(Debug)]
+pub enum SignedTransactionError {
+    Json(serde_json::Error),
+    /// A field needed to rebuild the signature is missing.
+    MissingField(&'static str),
+    /// A field could not be parsed.
+    InvalidField(&'static str),
+    /// The `type` field does not match the requested transaction type.
+    WrongType {
+        expected: u8,
+        found: u8,
+    },
+    /// `yParity` and `v` disagree.
+    ParityMismatch,
+    /// The `hash` field differs from the hash of the signed transaction.
+    HashMismatch {
+        included: [u8; 32],
+        computed: [u8; 32],
+    },
+    /// The `from` field differs from the address recovered from the signature.
+    SenderMismatch {
+        included: [u8; 20],
+        recovered: [u8; 20],
+    },
+    /// The signature could not be used to recover the sender.
+    Signature(Error),
+}
+
+impl fmt::Display for SignedTransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SignedTransactionError::Json(ref err) => err.fmt(f),
+            SignedTransactionError::MissingField(field) => write!(f, "missing field `{}`", field),
+            SignedTransactionError::InvalidField(field) => write!(f, "invalid field `{}`", field),
+            SignedTransactionError::WrongType { expected, found } => {
+                write!(f, "expected transaction type {}, found {}", expected, found)
+            }
+            SignedTransactionError::ParityMismatch => write!(f, "`yParity` does not match `v`"),
+            SignedTransactionError::HashMismatch {
+                ref included,
+                ref computed,
+            } => write!(
+                f,
+                "included hash 0x{} does not match computed hash 0x{}",
+                hex::encode(included),
+                hex::encode(computed)
+            ),
+            SignedTransactionError::SenderMismatch {
+                ref included,
+                ref recovered,
+            } => write!(
+                f,
+                "included sender 0x{} does not match recovered sender 0x{}",
+                hex::encode(included),
+                hex::encode(recovered)
+            ),
+            SignedTransactionError::Signature(ref err) => write!(f, "invalid signature: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for SignedTransactionError {}
+
+impl From<serde_json::Error> for SignedTransactionError {
+    fn from(error: serde_json::Error) -> Self {
+        SignedTransactionError::Json(error)
+    }
+}
+
+impl From<Error> for SignedTransactionError {
+    fn from(error: Error) -> Self {
+        SignedTransactionError::Signature(error)
+    }
+}
+
+impl<T: RpcTransaction> SignedTransaction<T> {
+    pub fn new(transaction: T, signature: EcdsaSig) -> Self {
+        SignedTransaction {
+            transaction,
+            signature,
+        }
+    }
+
+    /// The signed transaction bytes, as returned by [`Transaction::sign`](::Transaction::sign).
+    pub fn encode(&self) -> Vec<u8> {
+        self.transaction.sign(&self.signature)
+    }
+
+    /// Hash that identifies the transaction on chain, the keccak256 hash of the signed bytes.
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256_hash(&self.encode())
+    }
+
+    /// Address of the account that signed the transaction.
+    pub fn sender(&self) -> Result<[u8; 20], Error> {
+        self.transaction.recover(&self.signature)
+    }
+
+    /// The transaction in the format of `eth_getTransactionByHash`. Fields that depend on
+    /// the block the transaction is included in are `null`, as for a pending transaction.
+    pub fn to_rpc_json(&self) -> Result<Value, Error> {
+        let mut object = self.transaction.to_rpc_object();
+        let EcdsaSig { v, ref r, ref s } = self.signature;
+
+        object.entry("to").or_insert(Value::Null);
+        // pending fee market transactions report their fee cap as the gas price
+        if let Some(max_fee_per_gas) = object.get("maxFeePerGas").cloned() {
+            object.insert("gasPrice".into(), max_fee_per_gas);
+        }
+        object.insert("v".into(), rpc::quantity(v as u128).into());
+        object.insert("r".into(), rpc::quantity_bytes(r).into());
+        object.insert("s".into(), rpc::quantity_bytes(s).into());
+        if T::transaction_type().is_some() {
+            object.insert("yParity".into(), rpc::quantity(v as u128).into());
+        }
+        object.insert("hash".into(), rpc::bytes(&self.hash()).into());
+        object.insert("from".into(), rpc::bytes(&self.sender()?).into());
+        for field in ["blockHash", "blockNumber", "transactionIndex"].iter() {
+            object.insert(field.to_string(), Value::Null);
+        }
+        Ok(Value::Object(object))
+    }
+}
+
+impl<T: RpcTransaction + DeserializeOwned> SignedTransaction<T> {
+    /// Read a transaction returned by a node, checking that its `hash` and `from`
+    /// fields, when present, match the hash and sender computed from the other fields.
+    pub fn from_rpc_json(json: &Value) -> Result<Self, SignedTransactionError> {
+        let object = match *json {
+            Value::Object(ref object) => object,
+            _ => return Err(SignedTransactionError::InvalidField("transaction")),
+        };
+
+        let expected_type = T::transaction_type().unwrap_or(0);
+        let found_type = match object.get("type") {
+            Some(_) => quantity_field(object, "type")?,
+            None => 0,
+        };
+        if found_type != expected_type as u128 {
+            return Err(SignedTransactionError::WrongType {
+                expected: expected_type,
+                found: found_type.min(u8::MAX as u128) as u8,
+            });
+        }
+
+        let v = quantity_field(object, "v")?;
+        if v > u64::MAX as u128 {
+            return Err(SignedTransactionError::InvalidField("v"));
+        }
+        let v = v as u64;
+        if object.contains_key("yParity") && quantity_field(object, "yParity")? != v as u128 {
+            return Err(SignedTransactionError::ParityMismatch);
+        }
+        let signature = EcdsaSig {
+            v,
+            r: word_field(object, "r")?,
+            s: word_field(object, "s")?,
+        };
+
+        let mut fields = object.clone();
+        // older nodes leave out the chain ID of legacy transactions, but EIP-155 puts it in `v`
+        if T::transaction_type().is_none() && !fields.contains_key("chainId") {
+            if v < 35 {
+                return Err(SignedTransactionError::InvalidField("v"));
+            }
+            fields.insert("chainId".into(), ((v - 35) / 2).into());
+        }
+        // `chain` is an older spelling that would conflict with `chainId`
+        fields.remove("chain");
+        let transaction: T = serde_json::from_value(Value::Object(fields))?;
+        let signed = SignedTransaction::new(transaction, signature);
+
+        if object.contains_key("hash") {
+            let included = word_field(object, "hash")?;
+            let computed = signed.hash();
+            if included[..] != computed[..] {
+                let mut hash = [0u8; 32];
+                hash.copy_from_slice(&included);
+                return Err(SignedTransactionError::HashMismatch {
+                    included: hash,
+                    computed,
+                });
+            }
+        }
+
+        let recovered = signed.sender()?;
+        if let Some(from) = object.get("from") {
+            let included = from
+                .as_str()
+                .and_then(|s| s.strip_prefix(HEX_PREFIX))
+                .and_then(|s| hex::decode(s).ok())
+                .filter(|b| b.len() == 20)
+                .ok_or(SignedTransactionError::InvalidField("from"))?;
+            if included[..] != recovered[..] {
+                let mut address = [0u8; 20];
+                address.copy_from_slice(&included);
+                return Err(SignedTransactionError::SenderMismatch {
+                    included: address,
+                    recovered,
+                });
+            }
+        }
+
+        Ok(signed)
+    }
+}
+
+impl<T: RpcTransaction> Serialize for SignedTransaction<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_rpc_json()
+            .map_err(|err| S::Error::custom(format!("cannot recover sender: {:?}", err)))?
+            .serialize(serializer)
+    }
+}
+
+impl<'de, T: RpcTransaction + DeserializeOwned> Deserialize<'de> for SignedTransaction<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = Value::deserialize(deserializer)?;
+        SignedTransaction::from_rpc_json(&json).map_err(D::Error::custom)
+    }
+}
+
+fn quantity_field(
+    object: &Map<String, Value>,
+    field: &'static str,
+) -> Result<u128, SignedTransactionError> {
+    match object.get(field) {
+        Some(value) => value
+            .as_str()
+            .and_then(parse_quantity)
+            .ok_or(SignedTransactionError::InvalidField(field)),
+        None => Err(SignedTransactionError::MissingField(field)),
+    }
+}
+
+/// Read a quantity or data field holding up to 32 bytes, left-padded to 32 bytes.
+fn word_field(
+    object: &Map<String, Value>,
+    field: &'static str,
+) -> Result<Vec<u8>, SignedTransactionError> {
+    let digits = match object.get(field) {
+        Some(value) => value
+            .as_str()
+            .and_then(|s| s.strip_prefix(HEX_PREFIX))
+            .filter(|s| s.len() <= 64)
+            .ok_or(SignedTransactionError::InvalidField(field))?,
+        None => return Err(SignedTransactionError::MissingField(field)),
+    };
+    hex::decode(format!("{:0>64}", digits)).map_err(|_| SignedTransactionError::InvalidField(field))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{self, json, Value};
+    use signed::{SignedTransaction, SignedTransactionError};
+    use {AccessList, FeeMarketTransaction, LegacyTransaction, Transaction};
+
+    const PRIVATE_KEY: [u8; 32] = [0x35; 32];
+    const FROM: &str = "0xa4fb4cf8d7b1bf7b0b0b8d9b3a5c9bd1b9ca8bc7";
+
+    fn fee_market() -> FeeMarketTransaction {
+        FeeMarketTransaction {
+            chain: 1,
+            nonce: 3,
+            max_priority_fee_per_gas: 1_000_000_000,
+            max_fee_per_gas: 30_000_000_000,
+            gas: 21_000,
+            to: Some([0x45; 20]),
+            value: 1000,
+            data: vec![],
+            access_list: AccessList(vec![]),
+        }
+    }
+
+    fn signed<T: Transaction + ::rpc::RpcTransaction>(tx: T) -> SignedTransaction<T> {
+        let ecdsa = tx.ecdsa(&PRIVATE_KEY).unwrap();
+        SignedTransaction::new(tx, ecdsa)
+    }
+
+    #[test]
+    fn test_fee_market_to_rpc_json() {
+        let signed = signed(fee_market());
+        let json = signed.to_rpc_json().unwrap();
+
+        assert_eq!(json!("0x2"), json["type"]);
+        assert_eq!(json!("0x1"), json["chainId"]);
+        assert_eq!(json!("0x6fc23ac00"), json["gasPrice"]);
+        assert_eq!(json["v"], json["yParity"]);
+        assert_eq!(
+            json!(format!("0x{}", hex::encode(signed.hash()))),
+            json["hash"]
+        );
+        assert_eq!(
+            json!(format!("0x{}", hex::encode(signed.sender().unwrap()))),
+            json["from"]
+        );
+        assert_eq!(Value::Null, json["blockHash"]);
+        assert_eq!(signed, SignedTransaction::from_rpc_json(&json).unwrap());
+    }
+
+    #[test]
+    fn test_legacy_round_trip() {
+        let signed = signed(LegacyTransaction {
+            chain: 5,
+            nonce: 1,
+            to: None,
+            value: 0,
+            gas_price: 1_000_000_000,
+            gas: 100_000,
+            data: vec![0x60, 0x00],
+        });
+        let json = serde_json::to_value(&signed).unwrap();
+
+        assert_eq!(Value::Null, json["to"]);
+        assert_eq!(Value::Null, json["yParity"]);
+        let decoded: SignedTransaction<LegacyTransaction> = serde_json::from_value(json).unwrap();
+        assert_eq!(signed, decoded);
+    }
+
+    #[test]
+    fn test_node_response() {
+        // a mainnet transaction as returned by geth
+        let json = json!({
+            "blockHash": "0x8e38b4dbf6b11fcc3b9dee84fb7986e29ca0a02cecd8977c161ff7333329681e",
+            "blockNumber": "0xf4240",
+            "from": "0x39fa8c5f2793459d6622857e7d9fbb4bd91766d3",
+            "gas": "0x1f8dc",
+            "gasPrice": "0x12bfb19e60",
+            "hash": "0xea1093d492a1dcb1bef708f771a99a96ff05dcab81ca76c31940300177fcf49f",
+            "input": "0x",
+            "nonce": "0x15",
+            "to": "0xc083e9947cf02b8ffc7d3090ae9aea72df98fd47",
+            "transactionIndex": "0x1",
+            "value": "0x56bc75e2d63100000",
+            "type": "0x0",
+            "v": "0x1c",
+            "r": "0xa254fe085f721c2abe00a2cd244110bfc0df5f4f25461c85d8ab75ebac11eb10",
+            "s": "0x30b7835ba481955b20193a703ebc5fdffeab081d63117199040cdf5a91c68765"
+        });
+
+        // pre-EIP-155 signatures are not supported
+        match SignedTransaction::<LegacyTransaction>::from_rpc_json(&json) {
+            Err(SignedTransactionError::InvalidField("v")) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_node_response_eip155() {
+        // mainnet transaction 0x280cde7c…6fc4, a Uniswap V2 swap
+        let json = json!({
+            "from": "0xa12e1462d0ced572f396f58b6e2d03894cd7c8a4",
+            "gas": "0x22ef1",
+            "gasPrice": "0x5d21dba00",
+            "hash": "0x280cde7cdefe4b188750e76c888f13bd05ce9a4d7767730feefe8a0e50ca6fc4",
+            "input": "0x7ff36ab50000000000000000000000000000000000000000000066ab5a608bd00a23f2fe000000000000000000000000000000000000000000000000000000000000008000000000000000000000000048c04ed5691981c42154c6167398f95e8f38a7ff00000000000000000000000000000000000000000000000000000000632ceac70000000000000000000000000000000000000000000000000000000000000002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc20000000000000000000000006c6ee5e31d828de241282b9606c8e98ea48526e2",
+            "nonce": "0x78b",
+            "to": "0x7a250d5630b4cf539739df2c5dacb4c659f2488d",
+            "value": "0xc46549a521b13d8",
+            "type": "0x0",
+            "chainId": "0x1",
+            "v": "0x25",
+            "r": "0xc9077369501641a92ef7399ff81c21639ed4fd8fc69cb793cfa1dbfab342e10a",
+            "s": "0x615facb2f1bcf3274a354cfe384a38d0cc008a11c2dd23a69111bc6930ba27a8"
+        });
+        let raw = "f9015482078b8505d21dba0083022ef1947a250d5630b4cf539739df2c5dacb4c659f2488d880c46549a521b13d8b8e47ff36ab50000000000000000000000000000000000000000000066ab5a608bd00a23f2fe000000000000000000000000000000000000000000000000000000000000008000000000000000000000000048c04ed5691981c42154c6167398f95e8f38a7ff00000000000000000000000000000000000000000000000000000000632ceac70000000000000000000000000000000000000000000000000000000000000002000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc20000000000000000000000006c6ee5e31d828de241282b9606c8e98ea48526e225a0c9077369501641a92ef7399ff81c21639ed4fd8fc69cb793cfa1dbfab342e10aa0615facb2f1bcf3274a354cfe384a38d0cc008a11c2dd23a69111bc6930ba27a8";
+
+        let signed = SignedTransaction::<LegacyTransaction>::from_rpc_json(&json).unwrap();
+        assert_eq!(
+            "280cde7cdefe4b188750e76c888f13bd05ce9a4d7767730feefe8a0e50ca6fc4",
+            hex::encode(signed.hash())
+        );
+        assert_eq!(
+            "a12e1462d0ced572f396f58b6e2d03894cd7c8a4",
+            hex::encode(signed.sender().unwrap())
+        );
+        assert_eq!(raw, hex::encode(signed.encode()));
+
+        // older nodes leave out the chain ID, which is then taken from `v`
+        let mut without_chain = json.clone();
+        without_chain.as_object_mut().unwrap().remove("chainId");
+        assert_eq!(
+            signed,
+            SignedTransaction::<LegacyTransaction>::from_rpc_json(&without_chain).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_node_response_fee_market() {
+        // mainnet transaction 0xce4dc6d7…ab31
+        let json = json!({
+            "from": "0x001e2b7de757ba469a57bf6b23d982458a07efce",
+            "gas": "0x98f0",
+            "gasPrice": "0x29e7822d6",
+            "maxFeePerGas": "0x29e7822d6",
+            "maxPriorityFeePerGas": "0x3b9aca00",
+            "hash": "0xce4dc6d7a7549a98ee3b071b67e970879ff51b5b95d1c340bacd80fa1e1aab31",
+            "input": "0x1b55ba3a",
+            "nonce": "0x2",
+            "to": "0xd9e1459a7a482635700cbc20bbaf52d495ab9c96",
+            "value": "0x0",
+            "type": "0x2",
+            "accessList": [],
+            "chainId": "0x1",
+            "v": "0x0",
+            "yParity": "0x0",
+            "r": "0xc199674fcb29f353693dd779c017823b954b3c69dffa3cd6b2a6ff7888798039",
+            "s": "0x28ca912de909e7e6cdef9cdcaf24c54dd8c1032946dfa1d85c206b32a9064fe8"
+        });
+        let raw = "02f86f0102843b9aca0085029e7822d68298f094d9e1459a7a482635700cbc20bbaf52d495ab9c9680841b55ba3ac080a0c199674fcb29f353693dd779c017823b954b3c69dffa3cd6b2a6ff7888798039a028ca912de909e7e6cdef9cdcaf24c54dd8c1032946dfa1d85c206b32a9064fe8";
+
+        let signed = SignedTransaction::<FeeMarketTransaction>::from_rpc_json(&json).unwrap();
+        assert_eq!(
+            "ce4dc6d7a7549a98ee3b071b67e970879ff51b5b95d1c340bacd80fa1e1aab31",
+            hex::encode(signed.hash())
+        );
+        assert_eq!(
+            "001e2b7de757ba469a57bf6b23d982458a07efce",
+            hex::encode(signed.sender().unwrap())
+        );
+        assert_eq!(raw, hex::encode(signed.encode()));
+    }
+
+    #[test]
+    fn test_rejects_mismatches() {
+        let signed = signed(fee_market());
+        let json = signed.to_rpc_json().unwrap();
+
+        let mut wrong_hash = json.clone();
+        wrong_hash["hash"] = json!(format!("0x{}", "00".repeat(32)));
+        match SignedTransaction::<FeeMarketTransaction>::from_rpc_json(&wrong_hash) {
+            Err(SignedTransactionError::HashMismatch { .. }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        let mut wrong_sender = json.clone();
+        wrong_sender["from"] = json!(FROM);
+        match SignedTransaction::<FeeMarketTransaction>::from_rpc_json(&wrong_sender) {
+            Err(SignedTransactionError::SenderMismatch { .. }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+
+        match SignedTransaction::<LegacyTransaction>::from_rpc_json(&json) {
+            Err(SignedTransactionError::WrongType {
+                expected: 0,
+                found: 2,
+            }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Sending JSON-RPC requests to a node.
+use serde_json::{self, Value};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use rpc::JsonRpcRequest;
+
+#[derive(Debug)]
+pub enum TransportError {
+    /// The request could not be delivered or the node answered with an HTTP error.
+    Http(String),
+    /// The node's response is not valid JSON.
+    Json(serde_json::Error),
+    /// The node answered with a JSON-RPC error object.
+    Rpc { code: i64, message: String },
+    /// The response has neither a `result` nor an `error`, or no response is available.
+    InvalidResponse(String),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransportError::Http(ref message) => write!(f, "HTTP error: {}", message),
+            TransportError::Json(ref err) => write!(f, "invalid JSON response: {}", err),
+            TransportError::Rpc { code, ref message } => {
+                write!(f, "JSON-RPC error {}: {}", code, message)
+            }
+            TransportError::InvalidResponse(ref message) => {
+                write!(f, "invalid JSON-RPC response: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<serde_json::Error> for TransportError {
+    fn from(error: serde_json::Error) -> Self {
+        TransportError::Json(error)
+    }
+}
+
+/// Something that can deliver a JSON-RPC request to a node.
+pub trait Transport {
+    /// Send the request and return the `result` member of the response.
+    fn send(&self, request: &JsonRpcRequest) -> Result<Value, TransportError>;
+}
+
+impl<T: Transport + ?Sized> Transport for &T {
+    fn send(&self, request: &JsonRpcRequest) -> Result<Value, TransportError> {
+        (**self).send(request)
+    }
+}
+
+/// Extract the `result` from a JSON-RPC response, turning an `error` member into
+/// [`TransportError::Rpc`].
+pub fn response_result(mut response: Value) -> Result<Value, TransportError> {
+    if let Some(error) = response.get("error") {
+        return Err(TransportError::Rpc {
+            code: error.get("code").and_then(Value::as_i64).unwrap_or(0),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string(),
+        });
+    }
+    match response.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(TransportError::InvalidResponse(
+            "missing `result`".to_string(),
+        )),
+    }
+}
+
+/// Transport that posts requests to a node over HTTP or HTTPS.
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "http")]
+impl HttpTransport {
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        HttpTransport {
+            url: url.into(),
+            agent: ureq::Agent::new(),
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+impl Transport for HttpTransport {
+    fn send(&self, request: &JsonRpcRequest) -> Result<Value, TransportError> {
+        let response = self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&request.to_json());
+        let body = match response {
+            Ok(response) => response
+                .into_string()
+                .map_err(|err| TransportError::Http(err.to_string()))?,
+            // nodes often describe the problem in a JSON-RPC error body
+            Err(ureq::Error::Status(status, response)) => match response.into_string() {
+                Ok(ref body) if serde_json::from_str::<Value>(body).is_ok() => body.clone(),
+                _ => return Err(TransportError::Http(format!("status {}", status))),
+            },
+            Err(err) => return Err(TransportError::Http(err.to_string())),
+        };
+        response_result(serde_json::from_str(&body)?)
+    }
+}
+
+/// Canned response: a `result`, or the code and message of a JSON-RPC error.
+type MockResponse = Result<Value, (i64, String)>;
+
+/// In-memory transport that answers from canned responses, for tests.
+///
+/// Responses are queued per method and handed out in order. Every request that is
+/// sent is recorded.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: RefCell<HashMap<String, VecDeque<MockResponse>>>,
+    requests: RefCell<Vec<JsonRpcRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a successful `result` for the next call to `method`.
+    pub fn respond(&self, method: &str, result: Value) -> &Self {
+        self.queue(method, Ok(result))
+    }
+
+    /// Queue a JSON-RPC error for the next call to `method`.
+    pub fn respond_error(&self, method: &str, code: i64, message: &str) -> &Self {
+        self.queue(method, Err((code, message.to_string())))
+    }
+
+    /// Requests sent so far.
+    pub fn requests(&self) -> Vec<JsonRpcRequest> {
+        self.requests.borrow().clone()
+    }
+
+    fn queue(&self, method: &str, response: MockResponse) -> &Self {
+        self.responses
+            .borrow_mut()
+            .entry(method.to_string())
+            .or_default()
+            .push_back(response);
+        self
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, request: &JsonRpcRequest) -> Result<Value, TransportError> {
+        self.requests.borrow_mut().push(request.clone());
+        let response = self
+            .responses
+            .borrow_mut()
+            .get_mut(request.method)
+            .and_then(VecDeque::pop_front);
+        match response {
+            Some(Ok(result)) => Ok(result),
+            Some(Err((code, message))) => Err(TransportError::Rpc { code, message }),
+            None => Err(TransportError::InvalidResponse(format!(
+                "no response queued for {}",
+                request.method
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::JsonRpcRequest;
+    use serde_json::Value;
+    use transport::{response_result, MockTransport, Transport, TransportError};
+
+    #[test]
+    fn test_response_result() {
+        let response = serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#).unwrap();
+        assert_eq!(Value::from("0x1"), response_result(response).unwrap());
+
+        let response = serde_json::from_str(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"nonce too low"}}"#,
+        )
+        .unwrap();
+        match response_result(response) {
+            Err(TransportError::Rpc {
+                code: -32000,
+                ref message,
+            }) if message == "nonce too low" => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_mock_transport() {
+        let mock = MockTransport::new();
+        mock.respond("eth_chainId", "0x1".into()).respond_error(
+            "eth_chainId",
+            -32601,
+            "method not found",
+        );
+
+        let request = JsonRpcRequest::new(1, "eth_chainId", vec![]);
+        assert_eq!(Value::from("0x1"), mock.send(&request).unwrap());
+        match mock.send(&request) {
+            Err(TransportError::Rpc { code: -32601, .. }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match mock.send(&request) {
+            Err(TransportError::InvalidResponse(_)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert_eq!(3, mock.requests().len());
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_http_transport() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        use std::thread;
+        use transport::HttpTransport;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buffer = [0u8; 1024];
+            while !request.ends_with(b"]}") {
+                let len = stream.read(&mut buffer).unwrap();
+                if len == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..len]);
+            }
+            let body = r#"{"jsonrpc":"2.0","id":1,"result":"0x2105"}"#;
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let transport = HttpTransport::new(url);
+        let result = transport
+            .send(&JsonRpcRequest::new(1, "eth_chainId", vec![]))
+            .unwrap();
+        let request = server.join().unwrap();
+
+        assert_eq!(Value::from("0x2105"), result);
+        assert!(request.starts_with("POST / HTTP/1.1"));
+        assert!(request.ends_with(r#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId","params":[]}"#));
+    }
+}
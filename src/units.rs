@@ -0,0 +1,364 @@
+use serde::de::{Deserialize, Deserializer, Error as DeError, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A denomination of ether.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Unit {
+    Wei,
+    Kwei,
+    Mwei,
+    Gwei,
+    Szabo,
+    Finney,
+    Ether,
+}
+
+impl Unit {
+    /// Number of decimals, so that one of this unit is `10^decimals` wei.
+    pub fn decimals(self) -> u32 {
+        match self {
+            Unit::Wei => 0,
+            Unit::Kwei => 3,
+            Unit::Mwei => 6,
+            Unit::Gwei => 9,
+            Unit::Szabo => 12,
+            Unit::Finney => 15,
+            Unit::Ether => 18,
+        }
+    }
+
+    /// Wei in one of this unit.
+    pub fn wei(self) -> u128 {
+        10u128.pow(self.decimals())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Unit::Wei => "wei",
+            Unit::Kwei => "kwei",
+            Unit::Mwei => "mwei",
+            Unit::Gwei => "gwei",
+            Unit::Szabo => "szabo",
+            Unit::Finney => "finney",
+            Unit::Ether => "ether",
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Unit {
+    type Err = UnitError;
+
+    /// Unit names are case-insensitive; `eth` is accepted for ether.
+    fn from_str(s: &str) -> Result<Self, UnitError> {
+        let unit = match s.to_ascii_lowercase().as_str() {
+            "wei" => Unit::Wei,
+            "kwei" => Unit::Kwei,
+            "mwei" => Unit::Mwei,
+            "gwei" => Unit::Gwei,
+            "szabo" => Unit::Szabo,
+            "finney" => Unit::Finney,
+            "ether" | "eth" => Unit::Ether,
+            _ => return Err(UnitError::UnknownUnit(s.to_string())),
+        };
+        Ok(unit)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnitError {
+    /// The amount is not a non-negative decimal number.
+    InvalidNumber(String),
+    UnknownUnit(String),
+    /// The amount has more decimals than the unit, so it is not a whole number of wei.
+    TooPrecise {
+        amount: String,
+        unit: Unit,
+    },
+    /// The amount in wei does not fit in a `u128`.
+    Overflow,
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnitError::InvalidNumber(ref amount) => write!(f, "invalid amount `{}`", amount),
+            UnitError::UnknownUnit(ref unit) => write!(f, "unknown unit `{}`", unit),
+            UnitError::TooPrecise { ref amount, unit } => {
+                write!(f, "{} {} is not a whole number of wei", amount, unit)
+            }
+            UnitError::Overflow => write!(f, "amount does not fit in 128 bits of wei"),
+        }
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+/// An amount of ether, held exactly as wei.
+///
+/// Amounts are parsed from a decimal number followed by an optional unit, which defaults
+/// to wei. Converting into `u128` gives the wei value that transaction fields expect.
+/// ```
+/// use ethereum_tx_sign::{FeeMarketTransaction, Unit, Wei};
+///
+/// let max_fee: Wei = "30 gwei".parse().unwrap();
+/// let value: Wei = "1.25ether".parse().unwrap();
+///
+/// let tx = FeeMarketTransaction {
+///     chain: 1,
+///     max_fee_per_gas: max_fee.into(),
+///     value: value.into(),
+///     ..Default::default()
+/// };
+/// assert_eq!(30_000_000_000, tx.max_fee_per_gas);
+/// assert_eq!("1.3 ether", value.format(Unit::Ether, 1));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Wei(pub u128);
+
+impl Wei {
+    /// `amount` of `unit`, for example `Wei::new(30, Unit::Gwei)`.
+    pub fn new(amount: u128, unit: Unit) -> Result<Self, UnitError> {
+        amount
+            .checked_mul(unit.wei())
+            .map(Wei)
+            .ok_or(UnitError::Overflow)
+    }
+
+    /// Parse a decimal amount such as `1.5` in the given unit.
+    pub fn parse_in(amount: &str, unit: Unit) -> Result<Self, UnitError> {
+        let invalid = || UnitError::InvalidNumber(amount.to_string());
+        let (whole, fraction) = match amount.find('.') {
+            Some(i) => (&amount[..i], &amount[i + 1..]),
+            None => (amount, ""),
+        };
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if whole.len() + fraction.len() == 0 || !is_digits(whole) || !is_digits(fraction) {
+            return Err(invalid());
+        }
+
+        // digits below one wei may only be zeroes
+        let decimals = unit.decimals() as usize;
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > decimals {
+            return Err(UnitError::TooPrecise {
+                amount: amount.to_string(),
+                unit,
+            });
+        }
+
+        let mut wei = 0u128;
+        let digits = whole
+            .bytes()
+            .chain(fraction.bytes())
+            .chain((fraction.len()..decimals).map(|_| b'0'));
+        for digit in digits {
+            wei = wei
+                .checked_mul(10)
+                .and_then(|wei| wei.checked_add((digit - b'0') as u128))
+                .ok_or(UnitError::Overflow)?;
+        }
+        Ok(Wei(wei))
+    }
+
+    /// The amount in `unit`, rounded half up to `precision` decimals and followed by the
+    /// unit name, for example `"1.25 ether"`.
+    pub fn format(&self, unit: Unit, precision: usize) -> String {
+        let decimals = unit.decimals() as usize;
+        let (mut whole, mut fraction) = (self.0 / unit.wei(), self.0 % unit.wei());
+        if precision < decimals {
+            let scale = 10u128.pow((decimals - precision) as u32);
+            fraction = fraction / scale + u128::from(fraction % scale >= scale / 2);
+            if fraction == 10u128.pow(precision as u32) {
+                whole += 1;
+                fraction = 0;
+            }
+        }
+
+        if precision == 0 {
+            return format!("{} {}", whole, unit);
+        }
+        let mut fraction = match decimals {
+            0 => String::new(),
+            _ => format!("{:0width$}", fraction, width = decimals.min(precision)),
+        };
+        fraction.extend((decimals..precision).map(|_| '0'));
+        format!("{}.{} {}", whole, fraction, unit)
+    }
+
+    /// The exact amount in `unit`, without trailing zeroes.
+    pub fn format_exact(&self, unit: Unit) -> String {
+        let formatted = self.format(unit, unit.decimals() as usize);
+        let (amount, name) = formatted.split_at(formatted.find(' ').unwrap());
+        if !amount.contains('.') {
+            return formatted;
+        }
+        format!(
+            "{}{}",
+            amount.trim_end_matches('0').trim_end_matches('.'),
+            name
+        )
+    }
+}
+
+impl From<Wei> for u128 {
+    fn from(wei: Wei) -> Self {
+        wei.0
+    }
+}
+
+impl From<u128> for Wei {
+    fn from(wei: u128) -> Self {
+        Wei(wei)
+    }
+}
+
+impl FromStr for Wei {
+    type Err = UnitError;
+
+    /// Parse an amount such as `"1.5 ether"`, `"20gwei"` or `"21000"` (wei).
+    fn from_str(s: &str) -> Result<Self, UnitError> {
+        let s = s.trim();
+        let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+        let (amount, unit) = s.split_at(split);
+        let unit = match unit {
+            "" => Unit::Wei,
+            unit => unit.parse()?,
+        };
+        Wei::parse_in(amount.trim_end(), unit)
+    }
+}
+
+/// The exact amount in ether.
+impl fmt::Display for Wei {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.format_exact(Unit::Ether))
+    }
+}
+
+/// Serialized as a decimal string of wei, so that no precision is lost.
+impl Serialize for Wei {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+/// Deserialized from an amount string such as `"30 gwei"`, or from an integer of wei.
+impl<'de> Deserialize<'de> for Wei {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct WeiVisitor;
+
+        impl<'de> Visitor<'de> for WeiVisitor {
+            type Value = Wei;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an amount such as \"1.5 ether\" or an integer of wei")
+            }
+
+            fn visit_u64<E: DeError>(self, v: u64) -> Result<Wei, E> {
+                Ok(Wei(v as u128))
+            }
+
+            fn visit_u128<E: DeError>(self, v: u128) -> Result<Wei, E> {
+                Ok(Wei(v))
+            }
+
+            fn visit_str<E: DeError>(self, v: &str) -> Result<Wei, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(WeiVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use units::{Unit, UnitError, Wei};
+
+    #[test]
+    fn test_parse() {
+        let gwei = 1_000_000_000;
+        assert_eq!(Ok(Wei(30 * gwei)), "30 gwei".parse());
+        assert_eq!(Ok(Wei(20 * gwei)), "20gwei".parse());
+        assert_eq!(Ok(Wei(1_500_000_000_000_000_000)), "1.5 ether".parse());
+        assert_eq!(Ok(Wei(1_250_000_000_000_000_000)), " 1.25 ETH ".parse());
+        assert_eq!(Ok(Wei(500_000_000)), ".5 gwei".parse());
+        assert_eq!(Ok(Wei(1)), "0.000000001000 gwei".parse());
+        assert_eq!(Ok(Wei(21_000)), "21000".parse());
+        assert_eq!(Ok(Wei(u128::MAX)), u128::MAX.to_string().parse());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for amount in [
+            "",
+            "gwei",
+            ". gwei",
+            "-1 gwei",
+            "1.2.3 ether",
+            "1e9 wei",
+            "1,5 ether",
+        ]
+        .iter()
+        {
+            match amount.parse::<Wei>() {
+                Err(UnitError::InvalidNumber(_)) | Err(UnitError::UnknownUnit(_)) => (),
+                r => panic!("Unexpected result for {:?}: {:?}", amount, r),
+            }
+        }
+        assert_eq!(
+            Err(UnitError::UnknownUnit("gwie".into())),
+            "1 gwie".parse::<Wei>()
+        );
+        assert_eq!(
+            Err(UnitError::TooPrecise {
+                amount: "0.0000000001".into(),
+                unit: Unit::Gwei
+            }),
+            "0.0000000001 gwei".parse::<Wei>()
+        );
+        assert_eq!(
+            Err(UnitError::Overflow),
+            "1000000000000000000000 ether".parse::<Wei>()
+        );
+        assert_eq!(Err(UnitError::Overflow), Wei::new(u128::MAX, Unit::Kwei));
+    }
+
+    #[test]
+    fn test_format() {
+        let wei: Wei = "1.23456 ether".parse().unwrap();
+        assert_eq!("1.23 ether", wei.format(Unit::Ether, 2));
+        assert_eq!("1.2346 ether", wei.format(Unit::Ether, 4));
+        assert_eq!("1 ether", wei.format(Unit::Ether, 0));
+        assert_eq!("1234.56 finney", wei.format(Unit::Finney, 2));
+        assert_eq!("1234560000000000000.00 wei", wei.format(Unit::Wei, 2));
+        assert_eq!("1.23456 ether", wei.format_exact(Unit::Ether));
+        assert_eq!("1.23456 ether", wei.to_string());
+
+        // rounding carries into the whole part
+        let wei: Wei = "0.9996 ether".parse().unwrap();
+        assert_eq!("1.000 ether", wei.format(Unit::Ether, 3));
+        assert_eq!(
+            "30 gwei",
+            Wei::new(30, Unit::Gwei).unwrap().format_exact(Unit::Gwei)
+        );
+        assert_eq!("0 ether", Wei(0).to_string());
+    }
+
+    #[test]
+    fn test_serde() {
+        let wei: Wei = serde_json::from_str("\"30 gwei\"").unwrap();
+        assert_eq!(Wei(30_000_000_000), wei);
+        assert_eq!(Wei(21000), serde_json::from_str("21000").unwrap());
+        assert_eq!("\"30000000000\"", serde_json::to_string(&wei).unwrap());
+        assert!(serde_json::from_str::<Wei>("\"30 gwie\"").is_err());
+    }
+}
@@ -0,0 +1,1228 @@
+//! [ERC-4527](https://eips.ethereum.org/EIPS/eip-4527) QR code messages for offline signers.
+//!
+//! A transaction is sent to the signing device as an `eth-sign-request` and the device
+//! answers with an `eth-signature`. Both are CBOR encoded and wrapped in the
+//! [UR](https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-005-ur.md)
+//! format, which splits large messages into fountain coded parts that are shown as an
+//! animated QR code.
+//!
+//! ```
+//! use ethereum_tx_sign::ur::{EthSignRequest, EthSignature, KeyPath, UrDecoder};
+//! use ethereum_tx_sign::{FeeMarketTransaction, Transaction};
+//!
+//! let tx = FeeMarketTransaction { chain: 1, gas: 21000, ..Default::default() };
+//! let path = KeyPath::parse("m/44'/60'/0'/0/0").unwrap();
+//! let mut encoder = EthSignRequest::new(&tx, path).ur_encoder(200);
+//! let qr_frame = encoder.next_part();
+//! assert!(qr_frame.starts_with("ur:eth-sign-request/"));
+//!
+//! // scan the frames shown by the device until the signature is complete
+//! # let ecdsa = tx.ecdsa(&vec![0x35; 32]).unwrap();
+//! # let rsv = [ecdsa.r.clone(), ecdsa.s.clone(), vec![ecdsa.v as u8]].concat();
+//! # let scanned = EthSignature::new(None, rsv).to_ur();
+//! let mut decoder = UrDecoder::new();
+//! decoder.receive(&scanned).unwrap();
+//! assert!(decoder.is_complete());
+//!
+//! let signature = EthSignature::from_decoder(&decoder).unwrap();
+//! let ecdsa = signature.ecdsa(&tx).unwrap();
+//! let tx_bytes = tx.sign(&ecdsa);
+//! ```
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use {normalize_v, EcdsaSig, Error, Transaction};
+
+/// UR type of a signing request.
+pub const ETH_SIGN_REQUEST: &str = "eth-sign-request";
+/// UR type of the signature returned by the signing device.
+pub const ETH_SIGNATURE: &str = "eth-signature";
+
+const UUID_TAG: u64 = 37;
+const CRYPTO_KEYPATH_TAG: u64 = 304;
+const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Debug)]
+pub enum UrError {
+    /// The text is not a UR or not a part of one.
+    InvalidUr(String),
+    /// The payload contains something other than minimal bytewords.
+    InvalidBytewords,
+    /// The CRC32 checksum of a part or of the reassembled message is wrong.
+    Checksum,
+    /// The CBOR payload is malformed or does not have the expected structure.
+    InvalidCbor,
+    /// The UR has a different type than expected.
+    UnexpectedType {
+        expected: &'static str,
+        found: String,
+    },
+    /// A part does not belong to the same message as the parts before it.
+    InconsistentPart,
+    /// A derivation path could not be parsed.
+    InvalidKeyPath(String),
+    /// The signature cannot be used for the transaction.
+    Signature(Error),
+}
+
+impl fmt::Display for UrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UrError::InvalidUr(ref message) => write!(f, "invalid UR: {}", message),
+            UrError::InvalidBytewords => write!(f, "invalid bytewords"),
+            UrError::Checksum => write!(f, "checksum mismatch"),
+            UrError::InvalidCbor => write!(f, "invalid CBOR"),
+            UrError::UnexpectedType {
+                expected,
+                ref found,
+            } => write!(f, "expected UR type {}, found {}", expected, found),
+            UrError::InconsistentPart => write!(f, "part does not belong to the message"),
+            UrError::InvalidKeyPath(ref path) => write!(f, "invalid derivation path {}", path),
+            UrError::Signature(ref err) => write!(f, "invalid signature: {:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for UrError {}
+
+impl From<Error> for UrError {
+    fn from(error: Error) -> Self {
+        UrError::Signature(error)
+    }
+}
+
+/// A BIP-32 derivation path, the `crypto-keypath` of the signing request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPath {
+    /// Child indexes, each with whether it is hardened.
+    pub components: Vec<(u32, bool)>,
+    /// Fingerprint of the master key, which some devices require.
+    pub source_fingerprint: Option<u32>,
+}
+
+impl KeyPath {
+    /// Parse a path such as `m/44'/60'/0'/0/0`. Hardened indexes are marked with `'` or `h`.
+    pub fn parse(path: &str) -> Result<Self, UrError> {
+        let invalid = || UrError::InvalidKeyPath(path.to_string());
+        let mut segments = path.split('/');
+        if segments.next() != Some("m") {
+            return Err(invalid());
+        }
+
+        let mut components = vec![];
+        for segment in segments {
+            let (index, hardened) = match segment.strip_suffix(|c| c == '\'' || c == 'h') {
+                Some(index) => (index, true),
+                None => (segment, false),
+            };
+            if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            match index.parse::<u32>() {
+                Ok(index) if index < HARDENED => components.push((index, hardened)),
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(KeyPath {
+            components,
+            source_fingerprint: None,
+        })
+    }
+
+    pub fn source_fingerprint(mut self, source_fingerprint: u32) -> Self {
+        self.source_fingerprint = Some(source_fingerprint);
+        self
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        cbor_head(out, MAJOR_TAG, CRYPTO_KEYPATH_TAG);
+        cbor_head(out, MAJOR_MAP, 1 + self.source_fingerprint.is_some() as u64);
+        cbor_head(out, MAJOR_UINT, 1);
+        cbor_head(out, MAJOR_ARRAY, 2 * self.components.len() as u64);
+        for &(index, hardened) in self.components.iter() {
+            cbor_head(out, MAJOR_UINT, index as u64);
+            out.push(if hardened { CBOR_TRUE } else { CBOR_FALSE });
+        }
+        if let Some(fingerprint) = self.source_fingerprint {
+            cbor_head(out, MAJOR_UINT, 2);
+            cbor_head(out, MAJOR_UINT, fingerprint as u64);
+        }
+    }
+
+    fn read(reader: &mut CborReader) -> Result<Self, UrError> {
+        reader.tag(CRYPTO_KEYPATH_TAG)?;
+        let mut path = KeyPath {
+            components: vec![],
+            source_fingerprint: None,
+        };
+        for _ in 0..reader.expect(MAJOR_MAP)? {
+            match reader.uint()? {
+                1 => {
+                    let len = reader.expect(MAJOR_ARRAY)?;
+                    if len % 2 != 0 {
+                        return Err(UrError::InvalidCbor);
+                    }
+                    for _ in 0..len / 2 {
+                        // wildcards and ranges are not supported
+                        let index = reader.uint()?;
+                        if index >= HARDENED as u64 {
+                            return Err(UrError::InvalidCbor);
+                        }
+                        path.components.push((index as u32, reader.bool()?));
+                    }
+                }
+                2 => {
+                    let fingerprint = reader.uint()?;
+                    if fingerprint > u32::MAX as u64 {
+                        return Err(UrError::InvalidCbor);
+                    }
+                    path.source_fingerprint = Some(fingerprint as u32);
+                }
+                _ => reader.skip()?,
+            }
+        }
+        Ok(path)
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for &(index, hardened) in self.components.iter() {
+            write!(f, "/{}{}", index, if hardened { "'" } else { "" })?;
+        }
+        Ok(())
+    }
+}
+
+/// What the `sign_data` of a request holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignDataType {
+    /// RLP encoded unsigned legacy transaction
+    Transaction = 1,
+    /// EIP-712 typed data as JSON
+    TypedData = 2,
+    /// Message to sign with `personal_sign`
+    PersonalMessage = 3,
+    /// EIP-2718 typed transaction payload
+    TypedTransaction = 4,
+}
+
+impl SignDataType {
+    fn from_u64(data_type: u64) -> Result<Self, UrError> {
+        match data_type {
+            1 => Ok(SignDataType::Transaction),
+            2 => Ok(SignDataType::TypedData),
+            3 => Ok(SignDataType::PersonalMessage),
+            4 => Ok(SignDataType::TypedTransaction),
+            _ => Err(UrError::InvalidCbor),
+        }
+    }
+}
+
+/// An `eth-sign-request`, asking the signing device to sign some data with a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthSignRequest {
+    /// UUID that the device copies into its `eth-signature`
+    pub request_id: Option<[u8; 16]>,
+    pub sign_data: Vec<u8>,
+    pub data_type: SignDataType,
+    pub chain_id: Option<u64>,
+    /// Path of the key that should sign
+    pub derivation_path: KeyPath,
+    /// Address of the key that should sign, so the device can check it
+    pub address: Option<[u8; 20]>,
+    /// Name of the application making the request
+    pub origin: Option<String>,
+}
+
+impl EthSignRequest {
+    /// Request to sign the [`signing_payload`](::Transaction::signing_payload) of a transaction.
+    pub fn new<T: Transaction>(transaction: &T, derivation_path: KeyPath) -> Self {
+        EthSignRequest {
+            request_id: None,
+            sign_data: transaction.signing_payload(),
+            data_type: match T::transaction_type() {
+                Some(_) => SignDataType::TypedTransaction,
+                None => SignDataType::Transaction,
+            },
+            chain_id: Some(transaction.chain()),
+            derivation_path,
+            address: None,
+            origin: None,
+        }
+    }
+
+    pub fn request_id(mut self, request_id: [u8; 16]) -> Self {
+        self.request_id = Some(request_id);
+        self
+    }
+
+    pub fn address(mut self, address: [u8; 20]) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.origin = Some(origin.to_string());
+        self
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let fields = 3
+            + self.request_id.is_some() as u64
+            + self.chain_id.is_some() as u64
+            + self.address.is_some() as u64
+            + self.origin.is_some() as u64;
+        let mut out = vec![];
+        cbor_head(&mut out, MAJOR_MAP, fields);
+        if let Some(ref request_id) = self.request_id {
+            cbor_head(&mut out, MAJOR_UINT, 1);
+            cbor_head(&mut out, MAJOR_TAG, UUID_TAG);
+            cbor_bytes(&mut out, request_id);
+        }
+        cbor_head(&mut out, MAJOR_UINT, 2);
+        cbor_bytes(&mut out, &self.sign_data);
+        cbor_head(&mut out, MAJOR_UINT, 3);
+        cbor_head(&mut out, MAJOR_UINT, self.data_type as u64);
+        if let Some(chain_id) = self.chain_id {
+            cbor_head(&mut out, MAJOR_UINT, 4);
+            cbor_head(&mut out, MAJOR_UINT, chain_id);
+        }
+        cbor_head(&mut out, MAJOR_UINT, 5);
+        self.derivation_path.write(&mut out);
+        if let Some(ref address) = self.address {
+            cbor_head(&mut out, MAJOR_UINT, 6);
+            cbor_bytes(&mut out, address);
+        }
+        if let Some(ref origin) = self.origin {
+            cbor_head(&mut out, MAJOR_UINT, 7);
+            cbor_head(&mut out, MAJOR_TEXT, origin.len() as u64);
+            out.extend_from_slice(origin.as_bytes());
+        }
+        out
+    }
+
+    pub fn from_cbor(cbor: &[u8]) -> Result<Self, UrError> {
+        let mut reader = CborReader::new(cbor);
+        let mut request_id = None;
+        let mut sign_data = None;
+        let mut data_type = SignDataType::Transaction;
+        let mut chain_id = None;
+        let mut derivation_path = None;
+        let mut address = None;
+        let mut origin = None;
+        for _ in 0..reader.expect(MAJOR_MAP)? {
+            match reader.uint()? {
+                1 => request_id = Some(reader.uuid()?),
+                2 => sign_data = Some(reader.bytes()?.to_vec()),
+                3 => data_type = SignDataType::from_u64(reader.uint()?)?,
+                4 => chain_id = Some(reader.uint()?),
+                5 => derivation_path = Some(KeyPath::read(&mut reader)?),
+                6 => address = Some(reader.fixed_bytes()?),
+                7 => origin = Some(reader.text()?),
+                _ => reader.skip()?,
+            }
+        }
+        reader.finish()?;
+
+        Ok(EthSignRequest {
+            request_id,
+            sign_data: sign_data.ok_or(UrError::InvalidCbor)?,
+            data_type,
+            chain_id,
+            derivation_path: derivation_path.ok_or(UrError::InvalidCbor)?,
+            address,
+            origin,
+        })
+    }
+
+    /// The request as a single part UR, for devices that can scan large QR codes.
+    pub fn to_ur(&self) -> String {
+        encode_ur(ETH_SIGN_REQUEST, &self.to_cbor())
+    }
+
+    /// Encoder for the parts of an animated QR code, each carrying at most
+    /// `max_fragment_len` bytes of the request.
+    pub fn ur_encoder(&self, max_fragment_len: usize) -> UrEncoder {
+        UrEncoder::new(ETH_SIGN_REQUEST, &self.to_cbor(), max_fragment_len)
+    }
+
+    pub fn from_decoder(decoder: &UrDecoder) -> Result<Self, UrError> {
+        Self::from_cbor(decoded_message(decoder, ETH_SIGN_REQUEST)?)
+    }
+}
+
+/// An `eth-signature`, the signing device's answer to an [`EthSignRequest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EthSignature {
+    /// The `request_id` of the request that was signed
+    pub request_id: Option<[u8; 16]>,
+    /// `r || s || v`, where `v` may take more than one byte
+    pub signature: Vec<u8>,
+    /// Name of the signing device
+    pub origin: Option<String>,
+}
+
+impl EthSignature {
+    pub fn new(request_id: Option<[u8; 16]>, signature: Vec<u8>) -> Self {
+        EthSignature {
+            request_id,
+            signature,
+            origin: None,
+        }
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let fields = 1 + self.request_id.is_some() as u64 + self.origin.is_some() as u64;
+        let mut out = vec![];
+        cbor_head(&mut out, MAJOR_MAP, fields);
+        if let Some(ref request_id) = self.request_id {
+            cbor_head(&mut out, MAJOR_UINT, 1);
+            cbor_head(&mut out, MAJOR_TAG, UUID_TAG);
+            cbor_bytes(&mut out, request_id);
+        }
+        cbor_head(&mut out, MAJOR_UINT, 2);
+        cbor_bytes(&mut out, &self.signature);
+        if let Some(ref origin) = self.origin {
+            cbor_head(&mut out, MAJOR_UINT, 3);
+            cbor_head(&mut out, MAJOR_TEXT, origin.len() as u64);
+            out.extend_from_slice(origin.as_bytes());
+        }
+        out
+    }
+
+    pub fn from_cbor(cbor: &[u8]) -> Result<Self, UrError> {
+        let mut reader = CborReader::new(cbor);
+        let mut request_id = None;
+        let mut signature = None;
+        let mut origin = None;
+        for _ in 0..reader.expect(MAJOR_MAP)? {
+            match reader.uint()? {
+                1 => request_id = Some(reader.uuid()?),
+                2 => signature = Some(reader.bytes()?.to_vec()),
+                3 => origin = Some(reader.text()?),
+                _ => reader.skip()?,
+            }
+        }
+        reader.finish()?;
+
+        Ok(EthSignature {
+            request_id,
+            signature: signature.ok_or(UrError::InvalidCbor)?,
+            origin,
+        })
+    }
+
+    pub fn to_ur(&self) -> String {
+        encode_ur(ETH_SIGNATURE, &self.to_cbor())
+    }
+
+    pub fn from_decoder(decoder: &UrDecoder) -> Result<Self, UrError> {
+        Self::from_cbor(decoded_message(decoder, ETH_SIGNATURE)?)
+    }
+
+    /// The signature in the form [`Transaction::sign`] takes. The signer is not checked; use
+    /// [`Transaction::sign_verified`] for that.
+    pub fn ecdsa<T: Transaction>(&self, transaction: &T) -> Result<EcdsaSig, UrError> {
+        let len = self.signature.len();
+        if !(65..=72).contains(&len) {
+            return Err(Error::InvalidSignatureLength(len).into());
+        }
+
+        let v = self.signature[64..]
+            .iter()
+            .fold(0u64, |v, &b| (v << 8) | b as u64);
+        let chain = match T::transaction_type() {
+            Some(_) => None,
+            None => Some(transaction.chain()),
+        };
+
+        Ok(EcdsaSig {
+            v: normalize_v(v, chain)?,
+            r: self.signature[..32].to_vec(),
+            s: self.signature[32..64].to_vec(),
+        })
+    }
+}
+
+fn decoded_message<'a>(
+    decoder: &'a UrDecoder,
+    expected: &'static str,
+) -> Result<&'a [u8], UrError> {
+    match (decoder.ur_type(), decoder.message()) {
+        (Some(ur_type), Some(message)) if ur_type == expected => Ok(message),
+        (Some(ur_type), Some(_)) => Err(UrError::UnexpectedType {
+            expected,
+            found: ur_type.to_string(),
+        }),
+        _ => Err(UrError::InvalidUr("message is incomplete".to_string())),
+    }
+}
+
+/// Encode a message as a single part UR.
+pub fn encode_ur(ur_type: &str, message: &[u8]) -> String {
+    format!("ur:{}/{}", ur_type, bytewords_encode(message))
+}
+
+/// Splits a message into the parts of a multi-part UR.
+///
+/// The first [`seq_len`](UrEncoder::seq_len) parts carry the fragments of the message in
+/// order. The parts after that mix several fragments each, so a scanner that missed some
+/// frames can still recover the message.
+#[derive(Debug, Clone)]
+pub struct UrEncoder {
+    ur_type: String,
+    message: Vec<u8>,
+    checksum: u32,
+    fragments: Vec<Vec<u8>>,
+    seq_num: u32,
+}
+
+impl UrEncoder {
+    pub fn new(ur_type: &str, message: &[u8], max_fragment_len: usize) -> Self {
+        let max_fragment_len = max_fragment_len.max(1);
+        let fragment_count = message.len().div_ceil(max_fragment_len).max(1);
+        let fragment_len = message.len().div_ceil(fragment_count).max(1);
+
+        let mut padded = message.to_vec();
+        padded.resize(fragment_len * fragment_count, 0);
+        UrEncoder {
+            ur_type: ur_type.to_string(),
+            message: message.to_vec(),
+            checksum: crc32fast::hash(message),
+            fragments: padded.chunks(fragment_len).map(<[u8]>::to_vec).collect(),
+            seq_num: 0,
+        }
+    }
+
+    /// Number of fragments the message is split into.
+    pub fn seq_len(&self) -> usize {
+        self.fragments.len()
+    }
+
+    /// Whether the message fits in one part, in which case every part is the whole UR.
+    pub fn is_single_part(&self) -> bool {
+        self.fragments.len() == 1
+    }
+
+    /// The next part to show. Parts can be generated indefinitely.
+    pub fn next_part(&mut self) -> String {
+        if self.is_single_part() {
+            return encode_ur(&self.ur_type, &self.message);
+        }
+
+        self.seq_num = self.seq_num.wrapping_add(1).max(1);
+        let seq_len = self.fragments.len();
+        let mut data = vec![0u8; self.fragments[0].len()];
+        for i in choose_fragments(self.seq_num, seq_len, self.checksum) {
+            xor_into(&mut data, &self.fragments[i]);
+        }
+
+        let mut part = vec![];
+        cbor_head(&mut part, MAJOR_ARRAY, 5);
+        cbor_head(&mut part, MAJOR_UINT, self.seq_num as u64);
+        cbor_head(&mut part, MAJOR_UINT, seq_len as u64);
+        cbor_head(&mut part, MAJOR_UINT, self.message.len() as u64);
+        cbor_head(&mut part, MAJOR_UINT, self.checksum as u64);
+        cbor_bytes(&mut part, &data);
+        format!(
+            "ur:{}/{}-{}/{}",
+            self.ur_type,
+            self.seq_num,
+            seq_len,
+            bytewords_encode(&part)
+        )
+    }
+}
+
+/// Largest message a multi-part UR may reassemble to.
+const MAX_MESSAGE_LEN: u64 = 1 << 20;
+/// Most fragments a multi-part UR may be split into. Choosing the fragments of a mixed part
+/// takes time quadratic in this.
+const MAX_SEQ_LEN: u64 = 1 << 13;
+
+/// Reassembles a UR from its parts, in any order and with any number of missing or
+/// repeated parts.
+#[derive(Debug, Default, Clone)]
+pub struct UrDecoder {
+    ur_type: Option<String>,
+    message: Option<Vec<u8>>,
+    fountain: Option<FountainDecoder>,
+}
+
+#[derive(Debug, Clone)]
+struct FountainDecoder {
+    seq_len: usize,
+    message_len: usize,
+    checksum: u32,
+    fragment_len: usize,
+    simple: BTreeMap<usize, Vec<u8>>,
+    mixed: Vec<(BTreeSet<usize>, Vec<u8>)>,
+}
+
+impl UrDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a scanned part. Parts received after the message is complete are ignored.
+    pub fn receive(&mut self, part: &str) -> Result<(), UrError> {
+        if self.is_complete() {
+            return Ok(());
+        }
+
+        let part = part.to_ascii_lowercase();
+        let path = part
+            .strip_prefix("ur:")
+            .ok_or_else(|| UrError::InvalidUr("missing `ur:` scheme".to_string()))?;
+        let components: Vec<&str> = path.split('/').collect();
+        let ur_type = components[0];
+        if ur_type.is_empty()
+            || !ur_type
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        {
+            return Err(UrError::InvalidUr(format!("invalid type {}", ur_type)));
+        }
+        if self.ur_type.as_ref().is_some_and(|t| t != ur_type) {
+            return Err(UrError::InconsistentPart);
+        }
+
+        match components.len() {
+            2 => {
+                self.message = Some(bytewords_decode(components[1])?);
+                self.ur_type = Some(ur_type.to_string());
+                Ok(())
+            }
+            3 => {
+                let payload = bytewords_decode(components[2])?;
+                let message = self.receive_fragment(&payload)?;
+                self.ur_type = Some(ur_type.to_string());
+                self.message = message;
+                Ok(())
+            }
+            _ => Err(UrError::InvalidUr(
+                "expected `ur:<type>/<payload>`".to_string(),
+            )),
+        }
+    }
+
+    fn receive_fragment(&mut self, payload: &[u8]) -> Result<Option<Vec<u8>>, UrError> {
+        let mut reader = CborReader::new(payload);
+        if reader.expect(MAJOR_ARRAY)? != 5 {
+            return Err(UrError::InvalidCbor);
+        }
+        let seq_num = reader.uint()?;
+        let seq_len = reader.uint()?;
+        let message_len = reader.uint()?;
+        let checksum = reader.uint()?;
+        let data = reader.bytes()?.to_vec();
+        reader.finish()?;
+        // the part is untrusted, so what it claims is bounded before anything is sized by it:
+        // the fragments must exactly cover the message, as the encoder splits it
+        let fragment_len = data.len() as u64;
+        if seq_num == 0
+            || seq_num > u32::MAX as u64
+            || checksum > u32::MAX as u64
+            || message_len > MAX_MESSAGE_LEN
+            || fragment_len == 0
+            || seq_len == 0
+            || seq_len > MAX_SEQ_LEN
+            || seq_len > message_len.div_ceil(fragment_len).max(1)
+            || seq_len * fragment_len < message_len
+        {
+            return Err(UrError::InvalidCbor);
+        }
+        let seq_len = seq_len as usize;
+        let message_len = message_len as usize;
+
+        let fountain = self.fountain.get_or_insert_with(|| FountainDecoder {
+            seq_len,
+            message_len,
+            checksum: checksum as u32,
+            fragment_len: data.len(),
+            simple: BTreeMap::new(),
+            mixed: vec![],
+        });
+        if fountain.seq_len != seq_len
+            || fountain.message_len != message_len
+            || fountain.checksum != checksum as u32
+            || fountain.fragment_len != data.len()
+        {
+            return Err(UrError::InconsistentPart);
+        }
+
+        let indexes = choose_fragments(seq_num as u32, seq_len, checksum as u32);
+        fountain.receive(indexes.into_iter().collect(), data)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.message.is_some()
+    }
+
+    pub fn ur_type(&self) -> Option<&str> {
+        self.ur_type.as_deref()
+    }
+
+    /// The reassembled message, once it is complete.
+    pub fn message(&self) -> Option<&[u8]> {
+        self.message.as_deref()
+    }
+
+    /// Fraction of the message's fragments that have been recovered.
+    pub fn progress(&self) -> f64 {
+        match (self.is_complete(), self.fountain.as_ref()) {
+            (true, _) => 1.0,
+            (false, Some(fountain)) => fountain.simple.len() as f64 / fountain.seq_len as f64,
+            (false, None) => 0.0,
+        }
+    }
+}
+
+impl FountainDecoder {
+    fn receive(
+        &mut self,
+        indexes: BTreeSet<usize>,
+        data: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, UrError> {
+        let mut queue = vec![(indexes, data)];
+        while let Some((mut indexes, mut data)) = queue.pop() {
+            // remove the fragments that are already known
+            let known: Vec<usize> = indexes
+                .iter()
+                .cloned()
+                .filter(|i| self.simple.contains_key(i))
+                .collect();
+            for i in known {
+                xor_into(&mut data, &self.simple[&i]);
+                indexes.remove(&i);
+            }
+            for (mixed_indexes, mixed_data) in self.mixed.iter() {
+                if mixed_indexes.len() < indexes.len() && mixed_indexes.is_subset(&indexes) {
+                    xor_into(&mut data, mixed_data);
+                    indexes = &indexes - mixed_indexes;
+                }
+            }
+
+            if indexes.len() == 1 {
+                let index = *indexes.iter().next().unwrap();
+                self.simple.insert(index, data);
+                // the new fragment may reduce the mixed parts
+                queue.append(&mut self.mixed);
+            } else if indexes.len() > 1 && !self.mixed.iter().any(|(i, _)| *i == indexes) {
+                for (mixed_indexes, mixed_data) in std::mem::take(&mut self.mixed) {
+                    if indexes.is_subset(&mixed_indexes) {
+                        let mut reduced = mixed_data;
+                        xor_into(&mut reduced, &data);
+                        queue.push((&mixed_indexes - &indexes, reduced));
+                    } else {
+                        self.mixed.push((mixed_indexes, mixed_data));
+                    }
+                }
+                self.mixed.push((indexes, data));
+            }
+        }
+
+        if self.simple.len() < self.seq_len {
+            return Ok(None);
+        }
+        let mut message: Vec<u8> = self.simple.values().flatten().cloned().collect();
+        message.truncate(self.message_len);
+        if crc32fast::hash(&message) != self.checksum {
+            return Err(UrError::Checksum);
+        }
+        Ok(Some(message))
+    }
+}
+
+fn xor_into(data: &mut [u8], other: &[u8]) {
+    for (a, b) in data.iter_mut().zip(other.iter()) {
+        *a ^= b;
+    }
+}
+
+/// Indexes of the fragments mixed into part `seq_num`, as specified by the UR fountain code.
+fn choose_fragments(seq_num: u32, seq_len: usize, checksum: u32) -> Vec<usize> {
+    if seq_num as usize <= seq_len {
+        return vec![seq_num as usize - 1];
+    }
+
+    let mut seed = seq_num.to_be_bytes().to_vec();
+    seed.extend_from_slice(&checksum.to_be_bytes());
+    let mut rng = Xoshiro256::new(&seed);
+
+    let weights: Vec<f64> = (1..=seq_len).map(|i| 1.0 / i as f64).collect();
+    let degree = sample(&weights, &mut rng) + 1;
+
+    let mut remaining: Vec<usize> = (0..seq_len).collect();
+    let mut shuffled = Vec::with_capacity(seq_len);
+    while !remaining.is_empty() {
+        let index = rng.next_int(0, remaining.len() as u64 - 1) as usize;
+        shuffled.push(remaining.remove(index));
+    }
+    shuffled.truncate(degree);
+    shuffled
+}
+
+/// Pick an index with probability proportional to its weight, using Vose's alias method
+/// exactly as the UR reference implementation does.
+fn sample(weights: &[f64], rng: &mut Xoshiro256) -> usize {
+    let n = weights.len();
+    let sum: f64 = weights.iter().sum();
+    let mut p: Vec<f64> = weights.iter().map(|w| w * n as f64 / sum).collect();
+
+    let mut small = vec![];
+    let mut large = vec![];
+    for i in (0..n).rev() {
+        if p[i] < 1.0 {
+            small.push(i);
+        } else {
+            large.push(i);
+        }
+    }
+
+    let mut probs = vec![0.0; n];
+    let mut aliases = vec![0; n];
+    while !small.is_empty() && !large.is_empty() {
+        let a = small.pop().unwrap();
+        let g = large.pop().unwrap();
+        probs[a] = p[a];
+        aliases[a] = g;
+        p[g] += p[a] - 1.0;
+        if p[g] < 1.0 {
+            small.push(g);
+        } else {
+            large.push(g);
+        }
+    }
+    for i in large.into_iter().chain(small) {
+        probs[i] = 1.0;
+    }
+
+    let r1 = rng.next_double();
+    let r2 = rng.next_double();
+    let i = (n as f64 * r1) as usize;
+    if r2 < probs[i] {
+        i
+    } else {
+        aliases[i]
+    }
+}
+
+/// The xoshiro256** generator seeded with a SHA-256 hash, as used by the UR fountain code.
+struct Xoshiro256 {
+    s: [u64; 4],
+}
+
+impl Xoshiro256 {
+    fn new(seed: &[u8]) -> Self {
+        let hash = Sha256::digest(seed);
+        let mut s = [0u64; 4];
+        for (i, word) in s.iter_mut().enumerate() {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&hash[i * 8..i * 8 + 8]);
+            *word = u64::from_be_bytes(bytes);
+        }
+        Xoshiro256 { s }
+    }
+
+    fn next(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+        result
+    }
+
+    fn next_double(&mut self) -> f64 {
+        self.next() as f64 / (u64::MAX as f64 + 1.0)
+    }
+
+    fn next_int(&mut self, low: u64, high: u64) -> u64 {
+        (self.next_double() * (high - low + 1) as f64) as u64 + low
+    }
+}
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+const CBOR_FALSE: u8 = 0xf4;
+const CBOR_TRUE: u8 = 0xf5;
+const MAX_CBOR_DEPTH: usize = 16;
+
+fn cbor_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        out.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn cbor_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    cbor_head(out, MAJOR_BYTES, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Reader for the definite length CBOR that the UR formats use.
+struct CborReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CborReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        CborReader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8], UrError> {
+        let end = (self.pos as u64)
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len() as u64)
+            .ok_or(UrError::InvalidCbor)? as usize;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn head(&mut self) -> Result<(u8, u64), UrError> {
+        let initial = self.take(1)?[0];
+        let value = match initial & 0x1f {
+            info @ 0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => self.take(2)?.iter().fold(0, |v, &b| (v << 8) | b as u64),
+            26 => self.take(4)?.iter().fold(0, |v, &b| (v << 8) | b as u64),
+            27 => self.take(8)?.iter().fold(0, |v, &b| (v << 8) | b as u64),
+            _ => return Err(UrError::InvalidCbor),
+        };
+        Ok((initial >> 5, value))
+    }
+
+    fn expect(&mut self, major: u8) -> Result<u64, UrError> {
+        match self.head()? {
+            (m, value) if m == major => Ok(value),
+            _ => Err(UrError::InvalidCbor),
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64, UrError> {
+        self.expect(MAJOR_UINT)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], UrError> {
+        let len = self.expect(MAJOR_BYTES)?;
+        self.take(len)
+    }
+
+    fn fixed_bytes<T: Default + AsMut<[u8]>>(&mut self) -> Result<T, UrError> {
+        let bytes = self.bytes()?;
+        let mut fixed = T::default();
+        if fixed.as_mut().len() != bytes.len() {
+            return Err(UrError::InvalidCbor);
+        }
+        fixed.as_mut().copy_from_slice(bytes);
+        Ok(fixed)
+    }
+
+    fn text(&mut self) -> Result<String, UrError> {
+        let len = self.expect(MAJOR_TEXT)?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| UrError::InvalidCbor)
+    }
+
+    fn bool(&mut self) -> Result<bool, UrError> {
+        match self.head()? {
+            (MAJOR_SIMPLE, 20) => Ok(false),
+            (MAJOR_SIMPLE, 21) => Ok(true),
+            _ => Err(UrError::InvalidCbor),
+        }
+    }
+
+    fn tag(&mut self, tag: u64) -> Result<(), UrError> {
+        match self.expect(MAJOR_TAG)? {
+            t if t == tag => Ok(()),
+            _ => Err(UrError::InvalidCbor),
+        }
+    }
+
+    /// A UUID, which should be tagged but is accepted without the tag.
+    fn uuid(&mut self) -> Result<[u8; 16], UrError> {
+        if self.data.get(self.pos).map(|b| b >> 5) == Some(MAJOR_TAG) {
+            self.tag(UUID_TAG)?;
+        }
+        self.fixed_bytes()
+    }
+
+    fn skip(&mut self) -> Result<(), UrError> {
+        self.skip_nested(0)
+    }
+
+    fn skip_nested(&mut self, depth: usize) -> Result<(), UrError> {
+        if depth > MAX_CBOR_DEPTH {
+            return Err(UrError::InvalidCbor);
+        }
+        let (major, value) = self.head()?;
+        match major {
+            MAJOR_BYTES | MAJOR_TEXT => {
+                self.take(value)?;
+            }
+            MAJOR_ARRAY | MAJOR_MAP => {
+                let items = if major == MAJOR_MAP {
+                    value.checked_mul(2).ok_or(UrError::InvalidCbor)?
+                } else {
+                    value
+                };
+                for _ in 0..items {
+                    self.skip_nested(depth + 1)?;
+                }
+            }
+            MAJOR_TAG => self.skip_nested(depth + 1)?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), UrError> {
+        match self.pos == self.data.len() {
+            true => Ok(()),
+            false => Err(UrError::InvalidCbor),
+        }
+    }
+}
+
+#[rustfmt::skip]
+const BYTEWORDS: [&str; 256] = [
+    "able", "acid", "also", "apex", "aqua", "arch", "atom", "aunt", "away", "axis", "back", "bald",
+    "barn", "belt", "beta", "bias", "blue", "body", "brag", "brew", "bulb", "buzz", "calm", "cash",
+    "cats", "chef", "city", "claw", "code", "cola", "cook", "cost", "crux", "curl", "cusp", "cyan",
+    "dark", "data", "days", "deli", "dice", "diet", "door", "down", "draw", "drop", "drum", "dull",
+    "duty", "each", "easy", "echo", "edge", "epic", "even", "exam", "exit", "eyes", "fact", "fair",
+    "fern", "figs", "film", "fish", "fizz", "flap", "flew", "flux", "foxy", "free", "frog", "fuel",
+    "fund", "gala", "game", "gear", "gems", "gift", "girl", "glow", "good", "gray", "grim", "guru",
+    "gush", "gyro", "half", "hang", "hard", "hawk", "heat", "help", "high", "hill", "holy", "hope",
+    "horn", "huts", "iced", "idea", "idle", "inch", "inky", "into", "iris", "iron", "item", "jade",
+    "jazz", "join", "jolt", "jowl", "judo", "jugs", "jump", "junk", "jury", "keep", "keno", "kept",
+    "keys", "kick", "kiln", "king", "kite", "kiwi", "knob", "lamb", "lava", "lazy", "leaf", "legs",
+    "liar", "limp", "lion", "list", "logo", "loud", "love", "luau", "luck", "lung", "main", "many",
+    "math", "maze", "memo", "menu", "meow", "mild", "mint", "miss", "monk", "nail", "navy", "need",
+    "news", "next", "noon", "note", "numb", "obey", "oboe", "omit", "onyx", "open", "oval", "owls",
+    "paid", "part", "peck", "play", "plus", "poem", "pool", "pose", "puff", "puma", "purr", "quad",
+    "quiz", "race", "ramp", "real", "redo", "rich", "road", "rock", "roof", "ruby", "ruin", "runs",
+    "rust", "safe", "saga", "scar", "sets", "silk", "skew", "slot", "soap", "solo", "song", "stub",
+    "surf", "swan", "taco", "task", "taxi", "tent", "tied", "time", "tiny", "toil", "tomb", "toys",
+    "trip", "tuna", "twin", "ugly", "undo", "unit", "urge", "user", "vast", "very", "veto", "vial",
+    "vibe", "view", "visa", "void", "vows", "wall", "wand", "warm", "wasp", "wave", "waxy", "webs",
+    "what", "when", "whiz", "wolf", "work", "yank", "yawn", "yell", "yoga", "yurt", "zaps", "zero",
+    "zest", "zinc", "zone", "zoom",
+];
+
+/// Minimal bytewords (first and last letter of each word) of the bytes and their CRC32.
+fn bytewords_encode(bytes: &[u8]) -> String {
+    let checksum = crc32fast::hash(bytes).to_be_bytes();
+    bytes
+        .iter()
+        .chain(checksum.iter())
+        .flat_map(|&b| {
+            let word = BYTEWORDS[b as usize].as_bytes();
+            vec![word[0] as char, word[3] as char]
+        })
+        .collect()
+}
+
+fn bytewords_decode(text: &str) -> Result<Vec<u8>, UrError> {
+    let text = text.as_bytes();
+    if text.len() % 2 != 0 || text.len() < 10 {
+        return Err(UrError::InvalidBytewords);
+    }
+
+    let mut bytes = text
+        .chunks(2)
+        .map(|pair| {
+            BYTEWORDS
+                .iter()
+                .position(|word| word.as_bytes()[0] == pair[0] && word.as_bytes()[3] == pair[1])
+                .map(|b| b as u8)
+                .ok_or(UrError::InvalidBytewords)
+        })
+        .collect::<Result<Vec<u8>, UrError>>()?;
+
+    let checksum = bytes.split_off(bytes.len() - 4);
+    if crc32fast::hash(&bytes).to_be_bytes()[..] != checksum[..] {
+        return Err(UrError::Checksum);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+    use ur::{
+        bytewords_decode, bytewords_encode, cbor_bytes, cbor_head, EthSignRequest, EthSignature,
+        KeyPath, SignDataType, UrDecoder, UrEncoder, UrError, Xoshiro256, BYTEWORDS, MAJOR_ARRAY,
+        MAJOR_UINT,
+    };
+    use {FeeMarketTransaction, LegacyTransaction, Transaction};
+
+    #[test]
+    fn test_bytewords() {
+        let minimal: BTreeSet<(u8, u8)> = BYTEWORDS
+            .iter()
+            .map(|w| (w.as_bytes()[0], w.as_bytes()[3]))
+            .collect();
+        assert_eq!(256, minimal.len());
+
+        // example from BCR-2020-012
+        let encoded = bytewords_encode(&[0, 1, 2, 128, 255]);
+        assert_eq!("aeadaolazmjendeoti", encoded);
+        assert_eq!(vec![0, 1, 2, 128, 255], bytewords_decode(&encoded).unwrap());
+        match bytewords_decode("aeadaolazmjendeota") {
+            Err(UrError::Checksum) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_untrusted_part() {
+        let part = |seq_num: u64, seq_len: u64, message_len: u64, data: &[u8]| {
+            let mut cbor = vec![];
+            cbor_head(&mut cbor, MAJOR_ARRAY, 5);
+            cbor_head(&mut cbor, MAJOR_UINT, seq_num);
+            cbor_head(&mut cbor, MAJOR_UINT, seq_len);
+            cbor_head(&mut cbor, MAJOR_UINT, message_len);
+            cbor_head(&mut cbor, MAJOR_UINT, 0);
+            cbor_bytes(&mut cbor, data);
+            format!(
+                "ur:bytes/{}-{}/{}",
+                seq_num,
+                seq_len,
+                bytewords_encode(&cbor)
+            )
+        };
+
+        // more fragments than the message needs, a mixed part of which would otherwise
+        // make the decoder size its tables by `seq_len`
+        let too_many = [
+            part(u32::MAX as u64, u64::MAX / 2, 1, &[0]),
+            part(u32::MAX as u64, 1 << 40, 1 << 20, &[0; 100]),
+            part(2, 3, 10, &[0; 5]),
+            // too large a message
+            part(1, (1 << 20) + 1, (1 << 20) + 1, &[0]),
+            // or too few fragments
+            part(1, 1, 10, &[0; 5]),
+        ];
+        for ur in too_many.iter() {
+            match UrDecoder::new().receive(ur) {
+                Err(UrError::InvalidCbor) => (),
+                r => panic!("Unexpected result: {:?}", r),
+            }
+        }
+        assert!(UrDecoder::new().receive(&part(2, 2, 10, &[0; 5])).is_ok());
+    }
+
+    #[test]
+    fn test_xoshiro256() {
+        // reference values from the UR implementation's tests
+        let mut rng = Xoshiro256::new(b"Wolf");
+        let numbers: Vec<u64> = (0..10).map(|_| rng.next() % 100).collect();
+        assert_eq!(vec![42, 81, 85, 8, 82, 84, 76, 73, 70, 88], numbers);
+    }
+
+    #[test]
+    fn test_fountain_round_trip() {
+        let message: Vec<u8> = (0..1000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+        let mut encoder = UrEncoder::new("bytes", &message, 100);
+        assert_eq!(10, encoder.seq_len());
+
+        // drop the first parts, which hold the fragments in order
+        let mut decoder = UrDecoder::new();
+        for part in (0..200).map(|_| encoder.next_part()).skip(4) {
+            decoder.receive(&part.to_uppercase()).unwrap();
+            if decoder.is_complete() {
+                break;
+            }
+        }
+        assert!(decoder.is_complete());
+        assert_eq!(Some("bytes"), decoder.ur_type());
+        assert_eq!(Some(&message[..]), decoder.message());
+    }
+
+    #[test]
+    fn test_eth_sign_request() {
+        let tx = FeeMarketTransaction {
+            chain: 1,
+            nonce: 7,
+            max_fee_per_gas: 30_000_000_000,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            value: 1000,
+            ..Default::default()
+        };
+        let path = KeyPath::parse("m/44'/60'/0'/0/0")
+            .unwrap()
+            .source_fingerprint(0x1234_5678);
+        assert_eq!("m/44'/60'/0'/0/0", path.to_string());
+
+        let request = EthSignRequest::new(&tx, path)
+            .request_id([0x9b; 16])
+            .address([0x11; 20])
+            .origin("ethereum-tx-sign");
+        assert_eq!(SignDataType::TypedTransaction, request.data_type);
+        assert_eq!(tx.signing_payload(), request.sign_data);
+
+        let mut encoder = request.ur_encoder(20);
+        let mut decoder = UrDecoder::new();
+        while !decoder.is_complete() {
+            decoder.receive(&encoder.next_part()).unwrap();
+        }
+        assert_eq!(request, EthSignRequest::from_decoder(&decoder).unwrap());
+
+        match EthSignature::from_decoder(&decoder) {
+            Err(UrError::UnexpectedType { .. }) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        assert!(KeyPath::parse("44'/60'").is_err());
+        assert!(KeyPath::parse("m/2147483648").is_err());
+    }
+
+    #[test]
+    fn test_eth_signature() {
+        let tx = LegacyTransaction {
+            chain: 137,
+            gas: 21000,
+            to: Some([0x45; 20]),
+            ..Default::default()
+        };
+        let ecdsa = tx.ecdsa(&[0x35; 32]).unwrap();
+
+        // devices append an EIP-155 `v`, which takes two bytes on this chain
+        let v = (ecdsa.v as u16).to_be_bytes();
+        let rsv = [&ecdsa.r[..], &ecdsa.s[..], &v[..]].concat();
+        let ur = EthSignature::new(Some([0x9b; 16]), rsv).to_ur();
+
+        let mut decoder = UrDecoder::new();
+        decoder.receive(&ur).unwrap();
+        let signature = EthSignature::from_decoder(&decoder).unwrap();
+        assert_eq!(Some([0x9b; 16]), signature.request_id);
+        assert_eq!(ecdsa, signature.ecdsa(&tx).unwrap());
+    }
+}
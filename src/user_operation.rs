@@ -0,0 +1,720 @@
+//! [ERC-4337](https://eips.ethereum.org/EIPS/eip-4337) user operations for smart accounts.
+//!
+//! [`UserOperation`] is the format of EntryPoint v0.6 and [`PackedUserOperation`] the
+//! format of EntryPoint v0.7. Both serialize to the JSON that bundlers take in
+//! `eth_sendUserOperation`.
+//!
+//! ```
+//! use ethereum_tx_sign::user_operation::{PackedUserOperation, ENTRY_POINT_V07};
+//!
+//! let op = PackedUserOperation {
+//!     sender: [0x5a; 20],
+//!     call_data: vec![0xb6, 0x1d, 0x27, 0xf6],
+//!     call_gas_limit: 100_000,
+//!     verification_gas_limit: 150_000,
+//!     pre_verification_gas: 50_000,
+//!     max_fee_per_gas: 30_000_000_000,
+//!     max_priority_fee_per_gas: 1_000_000_000,
+//!     ..Default::default()
+//! };
+//! let ecdsa = op.sign(&ENTRY_POINT_V07, 1, &[0x35; 32]).unwrap();
+//! let op = op.with_signature(&ecdsa).unwrap();
+//!
+//! let json = serde_json::to_value(&op).unwrap();
+//! assert_eq!("0x186a0", json["callGasLimit"]);
+//! ```
+use serde::ser::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use abi::{self, Token};
+use rpc::{self, parse_word};
+use {eth_signed_message_hash, keccak256_hash, EcdsaSig, Error, ToPrivateKey};
+
+/// Address of EntryPoint v0.6, the same on all chains.
+pub const ENTRY_POINT_V06: [u8; 20] = [
+    0x5f, 0xf1, 0x37, 0xd4, 0xb0, 0xfd, 0xcd, 0x49, 0xdc, 0xa3, 0x0c, 0x7c, 0xf5, 0x7e, 0x57, 0x8a,
+    0x02, 0x6d, 0x27, 0x89,
+];
+/// Address of EntryPoint v0.7, the same on all chains.
+pub const ENTRY_POINT_V07: [u8; 20] = [
+    0x00, 0x00, 0x00, 0x00, 0x71, 0x72, 0x7d, 0xe2, 0x2e, 0x5e, 0x9d, 0x8b, 0xaf, 0x0e, 0xda, 0xc6,
+    0xf3, 0x7d, 0xa0, 0x32,
+];
+
+/// Selector of `handleOps((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes)[],address)`
+const HANDLE_OPS_V06_SELECTOR: [u8; 4] = [0x1f, 0xad, 0x94, 0x8c];
+/// Selector of `handleOps((address,uint256,bytes,bytes,bytes32,uint256,bytes32,bytes,bytes)[],address)`
+const HANDLE_OPS_V07_SELECTOR: [u8; 4] = [0x76, 0x5e, 0x82, 0x7f];
+
+/// A user operation for EntryPoint v0.6.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    /// The smart account
+    #[serde(serialize_with = "address_serialize")]
+    #[serde(deserialize_with = "::array_u8_20_deserialize")]
+    pub sender: [u8; 20],
+    /// Big-endian 256 bit nonce: a 192 bit key followed by a 64 bit sequence number
+    #[serde(serialize_with = "word_serialize")]
+    #[serde(deserialize_with = "word_deserialize")]
+    pub nonce: [u8; 32],
+    /// Factory address and calldata deploying the account, empty if it exists
+    #[serde(serialize_with = "bytes_serialize")]
+    #[serde(deserialize_with = "::slice_u8_deserialize")]
+    pub init_code: Vec<u8>,
+    /// Call the account makes
+    #[serde(serialize_with = "bytes_serialize")]
+    #[serde(deserialize_with = "::slice_u8_deserialize")]
+    pub call_data: Vec<u8>,
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub call_gas_limit: u128,
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub verification_gas_limit: u128,
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub pre_verification_gas: u128,
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub max_fee_per_gas: u128,
+    #[serde(serialize_with = "quantity_serialize")]
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub max_priority_fee_per_gas: u128,
+    /// Paymaster address followed by its data, empty if the account pays
+    #[serde(serialize_with = "bytes_serialize")]
+    #[serde(deserialize_with = "::slice_u8_deserialize")]
+    pub paymaster_and_data: Vec<u8>,
+    #[serde(serialize_with = "bytes_serialize")]
+    #[serde(deserialize_with = "::slice_u8_deserialize")]
+    pub signature: Vec<u8>,
+}
+
+impl UserOperation {
+    /// The `getUserOpHash` of the operation for the EntryPoint at `entry_point`.
+    pub fn hash(&self, entry_point: &[u8; 20], chain: u64) -> [u8; 32] {
+        let packed = abi::encode(&[
+            Token::address(&self.sender),
+            Token::Word(self.nonce),
+            Token::Word(keccak256_hash(&self.init_code)),
+            Token::Word(keccak256_hash(&self.call_data)),
+            Token::uint(self.call_gas_limit),
+            Token::uint(self.verification_gas_limit),
+            Token::uint(self.pre_verification_gas),
+            Token::uint(self.max_fee_per_gas),
+            Token::uint(self.max_priority_fee_per_gas),
+            Token::Word(keccak256_hash(&self.paymaster_and_data)),
+        ]);
+        user_op_hash(&packed, entry_point, chain)
+    }
+
+    /// Sign the operation hash as a personal message, the way ECDSA-validating accounts
+    /// such as SimpleAccount check it. `v` is 27 or 28.
+    pub fn sign<K: ToPrivateKey + ?Sized>(
+        &self,
+        entry_point: &[u8; 20],
+        chain: u64,
+        private_key: &K,
+    ) -> Result<EcdsaSig, Error> {
+        sign_user_op_hash(self.hash(entry_point, chain), private_key)
+    }
+
+    /// The operation with `signature` set to `r || s || v`, with `v` as 27 or 28 whichever
+    /// encoding `ecdsa` uses. Fails if `r` or `s` is longer than 32 bytes or `v` is invalid.
+    pub fn with_signature(self, ecdsa: &EcdsaSig) -> Result<Self, Error> {
+        Ok(UserOperation {
+            signature: signature_bytes(ecdsa)?,
+            ..self
+        })
+    }
+
+    /// Input data of the EntryPoint's `handleOps`, for submitting operations directly.
+    /// `beneficiary` receives the gas fees.
+    pub fn handle_ops_data(ops: &[UserOperation], beneficiary: &[u8; 20]) -> Vec<u8> {
+        let ops = ops
+            .iter()
+            .map(|op| {
+                Token::Tuple(vec![
+                    Token::address(&op.sender),
+                    Token::Word(op.nonce),
+                    Token::Bytes(&op.init_code),
+                    Token::Bytes(&op.call_data),
+                    Token::uint(op.call_gas_limit),
+                    Token::uint(op.verification_gas_limit),
+                    Token::uint(op.pre_verification_gas),
+                    Token::uint(op.max_fee_per_gas),
+                    Token::uint(op.max_priority_fee_per_gas),
+                    Token::Bytes(&op.paymaster_and_data),
+                    Token::Bytes(&op.signature),
+                ])
+            })
+            .collect();
+        abi::encode_call(
+            HANDLE_OPS_V06_SELECTOR,
+            &[Token::Array(ops), Token::address(beneficiary)],
+        )
+    }
+}
+
+/// A user operation for EntryPoint v0.7.
+///
+/// The fields are those of the bundler JSON-RPC API. The EntryPoint takes them packed
+/// into fewer fields, which [`init_code`](PackedUserOperation::init_code) and the
+/// methods after it compute.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackedUserOperation {
+    /// The smart account
+    #[serde(deserialize_with = "::array_u8_20_deserialize")]
+    pub sender: [u8; 20],
+    /// Big-endian 256 bit nonce: a 192 bit key followed by a 64 bit sequence number
+    #[serde(deserialize_with = "word_deserialize")]
+    pub nonce: [u8; 32],
+    /// Factory deploying the account, if it does not exist yet
+    #[serde(default)]
+    #[serde(deserialize_with = "::option_array_u8_deserialize")]
+    pub factory: Option<[u8; 20]>,
+    #[serde(default)]
+    #[serde(deserialize_with = "option_bytes_deserialize")]
+    pub factory_data: Vec<u8>,
+    /// Call the account makes
+    #[serde(deserialize_with = "::slice_u8_deserialize")]
+    pub call_data: Vec<u8>,
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub call_gas_limit: u128,
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub verification_gas_limit: u128,
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub pre_verification_gas: u128,
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub max_fee_per_gas: u128,
+    #[serde(deserialize_with = "::u128_quantity_deserialize")]
+    pub max_priority_fee_per_gas: u128,
+    /// Paymaster paying for the operation, if the account does not pay itself
+    #[serde(default)]
+    #[serde(deserialize_with = "::option_array_u8_deserialize")]
+    pub paymaster: Option<[u8; 20]>,
+    #[serde(default)]
+    #[serde(deserialize_with = "option_quantity_deserialize")]
+    pub paymaster_verification_gas_limit: u128,
+    #[serde(default)]
+    #[serde(deserialize_with = "option_quantity_deserialize")]
+    pub paymaster_post_op_gas_limit: u128,
+    #[serde(default)]
+    #[serde(deserialize_with = "option_bytes_deserialize")]
+    pub paymaster_data: Vec<u8>,
+    #[serde(deserialize_with = "::slice_u8_deserialize")]
+    pub signature: Vec<u8>,
+}
+
+impl PackedUserOperation {
+    /// Factory address followed by the factory data, or empty without a factory.
+    pub fn init_code(&self) -> Vec<u8> {
+        match self.factory {
+            Some(ref factory) => [&factory[..], &self.factory_data[..]].concat(),
+            None => vec![],
+        }
+    }
+
+    /// Verification gas limit and call gas limit, 128 bits each.
+    pub fn account_gas_limits(&self) -> [u8; 32] {
+        pack_u128s(self.verification_gas_limit, self.call_gas_limit)
+    }
+
+    /// Priority fee and fee cap, 128 bits each.
+    pub fn gas_fees(&self) -> [u8; 32] {
+        pack_u128s(self.max_priority_fee_per_gas, self.max_fee_per_gas)
+    }
+
+    /// Paymaster address, its gas limits and its data, or empty without a paymaster.
+    pub fn paymaster_and_data(&self) -> Vec<u8> {
+        match self.paymaster {
+            Some(ref paymaster) => [
+                &paymaster[..],
+                &pack_u128s(
+                    self.paymaster_verification_gas_limit,
+                    self.paymaster_post_op_gas_limit,
+                ),
+                &self.paymaster_data[..],
+            ]
+            .concat(),
+            None => vec![],
+        }
+    }
+
+    /// The `getUserOpHash` of the operation for the EntryPoint at `entry_point`.
+    pub fn hash(&self, entry_point: &[u8; 20], chain: u64) -> [u8; 32] {
+        let packed = abi::encode(&[
+            Token::address(&self.sender),
+            Token::Word(self.nonce),
+            Token::Word(keccak256_hash(&self.init_code())),
+            Token::Word(keccak256_hash(&self.call_data)),
+            Token::Word(self.account_gas_limits()),
+            Token::uint(self.pre_verification_gas),
+            Token::Word(self.gas_fees()),
+            Token::Word(keccak256_hash(&self.paymaster_and_data())),
+        ]);
+        user_op_hash(&packed, entry_point, chain)
+    }
+
+    /// Sign the operation hash as a personal message, the way ECDSA-validating accounts
+    /// such as SimpleAccount check it. `v` is 27 or 28.
+    pub fn sign<K: ToPrivateKey + ?Sized>(
+        &self,
+        entry_point: &[u8; 20],
+        chain: u64,
+        private_key: &K,
+    ) -> Result<EcdsaSig, Error> {
+        sign_user_op_hash(self.hash(entry_point, chain), private_key)
+    }
+
+    /// The operation with `signature` set to `r || s || v`, with `v` as 27 or 28 whichever
+    /// encoding `ecdsa` uses. Fails if `r` or `s` is longer than 32 bytes or `v` is invalid.
+    pub fn with_signature(self, ecdsa: &EcdsaSig) -> Result<Self, Error> {
+        Ok(PackedUserOperation {
+            signature: signature_bytes(ecdsa)?,
+            ..self
+        })
+    }
+
+    /// Input data of the EntryPoint's `handleOps`, for submitting operations directly.
+    /// `beneficiary` receives the gas fees.
+    pub fn handle_ops_data(ops: &[PackedUserOperation], beneficiary: &[u8; 20]) -> Vec<u8> {
+        let packed: Vec<(Vec<u8>, Vec<u8>)> = ops
+            .iter()
+            .map(|op| (op.init_code(), op.paymaster_and_data()))
+            .collect();
+        let ops = ops
+            .iter()
+            .zip(packed.iter())
+            .map(|(op, (init_code, paymaster_and_data))| {
+                Token::Tuple(vec![
+                    Token::address(&op.sender),
+                    Token::Word(op.nonce),
+                    Token::Bytes(init_code),
+                    Token::Bytes(&op.call_data),
+                    Token::Word(op.account_gas_limits()),
+                    Token::uint(op.pre_verification_gas),
+                    Token::Word(op.gas_fees()),
+                    Token::Bytes(paymaster_and_data),
+                    Token::Bytes(&op.signature),
+                ])
+            })
+            .collect();
+        abi::encode_call(
+            HANDLE_OPS_V07_SELECTOR,
+            &[Token::Array(ops), Token::address(beneficiary)],
+        )
+    }
+}
+
+impl Serialize for PackedUserOperation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // bundlers expect the factory and paymaster fields together or not at all
+        let mut object = Map::new();
+        object.insert("sender".into(), rpc::bytes(&self.sender).into());
+        object.insert("nonce".into(), rpc::quantity_bytes(&self.nonce).into());
+        if let Some(ref factory) = self.factory {
+            object.insert("factory".into(), rpc::bytes(factory).into());
+            object.insert("factoryData".into(), rpc::bytes(&self.factory_data).into());
+        }
+        object.insert("callData".into(), rpc::bytes(&self.call_data).into());
+        let gas_fields = [
+            ("callGasLimit", self.call_gas_limit),
+            ("verificationGasLimit", self.verification_gas_limit),
+            ("preVerificationGas", self.pre_verification_gas),
+            ("maxFeePerGas", self.max_fee_per_gas),
+            ("maxPriorityFeePerGas", self.max_priority_fee_per_gas),
+        ];
+        for &(name, value) in gas_fields.iter() {
+            object.insert(name.into(), rpc::quantity(value).into());
+        }
+        if let Some(ref paymaster) = self.paymaster {
+            object.insert("paymaster".into(), rpc::bytes(paymaster).into());
+            object.insert(
+                "paymasterVerificationGasLimit".into(),
+                rpc::quantity(self.paymaster_verification_gas_limit).into(),
+            );
+            object.insert(
+                "paymasterPostOpGasLimit".into(),
+                rpc::quantity(self.paymaster_post_op_gas_limit).into(),
+            );
+            object.insert(
+                "paymasterData".into(),
+                rpc::bytes(&self.paymaster_data).into(),
+            );
+        }
+        object.insert("signature".into(), rpc::bytes(&self.signature).into());
+        Value::Object(object).serialize(serializer)
+    }
+}
+
+fn user_op_hash(packed: &[u8], entry_point: &[u8; 20], chain: u64) -> [u8; 32] {
+    keccak256_hash(&abi::encode(&[
+        Token::Word(keccak256_hash(packed)),
+        Token::address(entry_point),
+        Token::uint(chain as u128),
+    ]))
+}
+
+fn sign_user_op_hash<K: ToPrivateKey + ?Sized>(
+    hash: [u8; 32],
+    private_key: &K,
+) -> Result<EcdsaSig, Error> {
+    let ecdsa = EcdsaSig::generate(eth_signed_message_hash(&hash), private_key, None)?;
+    Ok(EcdsaSig {
+        v: ecdsa.v + 27,
+        ..ecdsa
+    })
+}
+
+fn signature_bytes(ecdsa: &EcdsaSig) -> Result<Vec<u8>, Error> {
+    let mut signature = ecdsa.rs()?.to_vec();
+    signature.push(27 + ecdsa.y_parity()?);
+    Ok(signature)
+}
+
+fn pack_u128s(high: u128, low: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[..16].copy_from_slice(&high.to_be_bytes());
+    word[16..].copy_from_slice(&low.to_be_bytes());
+    word
+}
+
+fn address_serialize<S: Serializer>(address: &[u8; 20], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&rpc::bytes(address))
+}
+
+fn bytes_serialize<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&rpc::bytes(bytes))
+}
+
+fn quantity_serialize<S: Serializer>(quantity: &u128, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&rpc::quantity(*quantity))
+}
+
+fn word_serialize<S: Serializer>(word: &[u8; 32], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&rpc::quantity_bytes(word))
+}
+
+fn word_deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<[u8; 32], D::Error> {
+    let s = String::deserialize(d)?;
+    parse_word(&s).ok_or_else(|| {
+        ::serde::de::Error::invalid_value(
+            ::serde::de::Unexpected::Str(&s),
+            &"a 0x-prefixed quantity of up to 256 bits",
+        )
+    })
+}
+
+fn option_bytes_deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+    match Option::<Value>::deserialize(d)? {
+        Some(value) => ::slice_u8_deserialize(value).map_err(::serde::de::Error::custom),
+        None => Ok(vec![]),
+    }
+}
+
+fn option_quantity_deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u128, D::Error> {
+    match Option::<Value>::deserialize(d)? {
+        Some(value) => ::u128_quantity_deserialize(value).map_err(::serde::de::Error::custom),
+        None => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{self, json};
+    use user_operation::{
+        PackedUserOperation, UserOperation, ENTRY_POINT_V06, ENTRY_POINT_V07,
+        HANDLE_OPS_V06_SELECTOR, HANDLE_OPS_V07_SELECTOR,
+    };
+    use {eth_signed_message_hash, keccak256_hash, EcdsaSig, Error};
+
+    fn user_operation() -> UserOperation {
+        let mut nonce = [0u8; 32];
+        nonce[31] = 3;
+        UserOperation {
+            sender: [0x5a; 20],
+            nonce,
+            init_code: vec![],
+            call_data: vec![0xb6, 0x1d, 0x27, 0xf6],
+            call_gas_limit: 100_000,
+            verification_gas_limit: 150_000,
+            pre_verification_gas: 50_000,
+            max_fee_per_gas: 30_000_000_000,
+            max_priority_fee_per_gas: 1_000_000_000,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        }
+    }
+
+    fn packed_user_operation() -> PackedUserOperation {
+        let op = user_operation();
+        PackedUserOperation {
+            sender: op.sender,
+            nonce: op.nonce,
+            call_data: op.call_data,
+            call_gas_limit: op.call_gas_limit,
+            verification_gas_limit: op.verification_gas_limit,
+            pre_verification_gas: op.pre_verification_gas,
+            max_fee_per_gas: op.max_fee_per_gas,
+            max_priority_fee_per_gas: op.max_priority_fee_per_gas,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_handle_ops_selectors() {
+        let v06 = keccak256_hash(
+            b"handleOps((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,\
+              bytes,bytes)[],address)",
+        );
+        let v07 = keccak256_hash(
+            b"handleOps((address,uint256,bytes,bytes,bytes32,uint256,bytes32,bytes,bytes)[],address)",
+        );
+        assert_eq!(HANDLE_OPS_V06_SELECTOR, v06[..4]);
+        assert_eq!(HANDLE_OPS_V07_SELECTOR, v07[..4]);
+    }
+
+    #[test]
+    fn test_sign_user_operation() {
+        let op = user_operation();
+        let hash = op.hash(&ENTRY_POINT_V06, 1);
+        assert_ne!(hash, op.hash(&ENTRY_POINT_V07, 1));
+        assert_ne!(hash, op.hash(&ENTRY_POINT_V06, 10));
+
+        let ecdsa = op.sign(&ENTRY_POINT_V06, 1, &[0x35; 32]).unwrap();
+        assert!(ecdsa.v == 27 || ecdsa.v == 28);
+        let expected = EcdsaSig::generate(eth_signed_message_hash(&hash), &[0x35; 32], None);
+        assert_eq!(expected.unwrap().r, ecdsa.r);
+
+        let op = op.with_signature(&ecdsa).unwrap();
+        assert_eq!(65, op.signature.len());
+        assert_eq!(ecdsa.v as u8, op.signature[64]);
+        // the signature is not part of the hash
+        assert_eq!(hash, op.hash(&ENTRY_POINT_V06, 1));
+
+        // a y-parity `v`, as `EcdsaSig::generate` returns, is written as 27 or 28
+        let parity = EcdsaSig {
+            v: ecdsa.v - 27,
+            ..ecdsa.clone()
+        };
+        let signed = op.clone().with_signature(&parity).unwrap();
+        assert_eq!(ecdsa.v as u8, signed.signature[64]);
+
+        let long = EcdsaSig {
+            r: vec![0x01; 33],
+            ..ecdsa
+        };
+        match op.with_signature(&long) {
+            Err(Error::InvalidSignatureLength(33)) => (),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    /// `getUserOpHash` of the v0.6 EntryPoint for an operation on Polygon Mumbai, the
+    /// vector of Alchemy's aa-sdk.
+    #[test]
+    fn test_user_operation_hash() {
+        let mut sender = [0u8; 20];
+        sender.copy_from_slice(&hex::decode("b856dbd4fa1a79a46d426f537455e7d3e79ab7c4").unwrap());
+        let mut nonce = [0u8; 32];
+        nonce[31] = 0x1f;
+        let op = UserOperation {
+            sender,
+            nonce,
+            init_code: vec![],
+            call_data: hex::decode(
+                "b61d27f6000000000000000000000000b856dbd4fa1a79a46d426f537455e7d3e79ab7c4\
+                 0000000000000000000000000000000000000000000000000000000000000000\
+                 0000000000000000000000000000000000000000000000000000000000000060\
+                 0000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap(),
+            call_gas_limit: 0x2f6c,
+            verification_gas_limit: 0x0114c2,
+            pre_verification_gas: 0xa890,
+            max_fee_per_gas: 0x59682f1e,
+            max_priority_fee_per_gas: 0x59682f00,
+            paymaster_and_data: vec![],
+            signature: vec![],
+        };
+        assert_eq!(
+            "a70d0af2ebb03a44dcd0714a8724f622e3ab876d0aa312f0ee04823285d6fb1b",
+            hex::encode(op.hash(&ENTRY_POINT_V06, 80001))
+        );
+    }
+
+    /// `getUserOpHash` and `handleOps` calldata of an operation from a geth ERC-7562 trace of
+    /// the v0.7 EntryPoint, on a development chain.
+    #[test]
+    fn test_packed_user_operation_hash() {
+        let mut sender = [0u8; 20];
+        sender.copy_from_slice(&hex::decode("8c9d927336adc963536122f8e0d269319e79ed7a").unwrap());
+        let op = PackedUserOperation {
+            sender,
+            call_data: hex::decode(
+                "a9e966b7000000000000000000000000000000000000000000000000000000000010f447",
+            )
+            .unwrap(),
+            call_gas_limit: 300_000,
+            verification_gas_limit: 1_000_000,
+            pre_verification_gas: 300_000,
+            max_fee_per_gas: 4_000_000_000,
+            max_priority_fee_per_gas: 3_000_000_000,
+            signature: vec![0xfa, 0xce],
+            ..Default::default()
+        };
+        assert_eq!(
+            "88a9b2626e43da02f978ae6cc89feffb68afcd5860cb9239337352db4b694fe1",
+            hex::encode(op.hash(&ENTRY_POINT_V07, 1337))
+        );
+
+        let mut beneficiary = [0u8; 20];
+        beneficiary
+            .copy_from_slice(&hex::decode("f39fd6e51aad88f6f4ce6ab8827279cfffb92266").unwrap());
+        let data = PackedUserOperation::handle_ops_data(&[op], &beneficiary);
+        assert_eq!(
+            "765e827f\
+             0000000000000000000000000000000000000000000000000000000000000040\
+             000000000000000000000000f39fd6e51aad88f6f4ce6ab8827279cfffb92266\
+             0000000000000000000000000000000000000000000000000000000000000001\
+             0000000000000000000000000000000000000000000000000000000000000020\
+             0000000000000000000000008c9d927336adc963536122f8e0d269319e79ed7a\
+             0000000000000000000000000000000000000000000000000000000000000000\
+             0000000000000000000000000000000000000000000000000000000000000120\
+             0000000000000000000000000000000000000000000000000000000000000140\
+             000000000000000000000000000f4240000000000000000000000000000493e0\
+             00000000000000000000000000000000000000000000000000000000000493e0\
+             000000000000000000000000b2d05e00000000000000000000000000ee6b2800\
+             00000000000000000000000000000000000000000000000000000000000001a0\
+             00000000000000000000000000000000000000000000000000000000000001c0\
+             0000000000000000000000000000000000000000000000000000000000000000\
+             0000000000000000000000000000000000000000000000000000000000000024\
+             a9e966b700000000000000000000000000000000000000000000000000000000\
+             0010f44700000000000000000000000000000000000000000000000000000000\
+             0000000000000000000000000000000000000000000000000000000000000000\
+             0000000000000000000000000000000000000000000000000000000000000002\
+             face000000000000000000000000000000000000000000000000000000000000",
+            hex::encode(data)
+        );
+    }
+
+    /// An operation deploying its account through a factory and sponsored by a paymaster,
+    /// hashed by the v0.7 EntryPoint's own `getUserOpHash`.
+    #[test]
+    fn test_packed_user_operation_hash_factory_paymaster() {
+        let address = |hex: &str| {
+            let mut address = [0u8; 20];
+            address.copy_from_slice(&hex::decode(hex).unwrap());
+            address
+        };
+        let mut nonce = [0u8; 32];
+        nonce[..24].copy_from_slice(&[0x01; 24]);
+        nonce[31] = 7;
+        let op = PackedUserOperation {
+            sender: address("b856dbd4fa1a79a46d426f537455e7d3e79ab7c4"),
+            nonce,
+            factory: Some(address("9406cc6185a346906296840746125a0e44976454")),
+            factory_data: hex::decode(
+                "5fbfb9cf000000000000000000000000e9d5bd03a7ce2c2b4b4b8a6bd0b2f6a8ccff2a61\
+                 0000000000000000000000000000000000000000000000000000000000000000",
+            )
+            .unwrap(),
+            call_data: vec![0xb6, 0x1d, 0x27, 0xf6],
+            call_gas_limit: 100_000,
+            verification_gas_limit: 150_000,
+            pre_verification_gas: 50_000,
+            max_fee_per_gas: 30_000_000_000,
+            max_priority_fee_per_gas: 1_000_000_000,
+            paymaster: Some(address("0000000000000039cd5e8ae05257ce51c473ddd1")),
+            paymaster_verification_gas_limit: 100_000,
+            paymaster_post_op_gas_limit: 50_000,
+            paymaster_data: vec![0xde, 0xad, 0xbe, 0xef],
+            signature: vec![],
+        };
+        assert_eq!(
+            "869ae5dd3f71a302bef89ad1e0b55d2d067416b47b7ff2da6f7c90ce93ea2cf3",
+            hex::encode(op.hash(&ENTRY_POINT_V07, 10))
+        );
+    }
+
+    #[test]
+    fn test_user_operation_json() {
+        let op = user_operation();
+        let json = serde_json::to_value(&op).unwrap();
+        assert_eq!(
+            json!({
+                "sender": "0x5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a",
+                "nonce": "0x3",
+                "initCode": "0x",
+                "callData": "0xb61d27f6",
+                "callGasLimit": "0x186a0",
+                "verificationGasLimit": "0x249f0",
+                "preVerificationGas": "0xc350",
+                "maxFeePerGas": "0x6fc23ac00",
+                "maxPriorityFeePerGas": "0x3b9aca00",
+                "paymasterAndData": "0x",
+                "signature": "0x"
+            }),
+            json
+        );
+        assert_eq!(op, serde_json::from_value(json).unwrap());
+    }
+
+    #[test]
+    fn test_packed_user_operation() {
+        let mut op = packed_user_operation();
+        assert!(op.init_code().is_empty());
+        assert!(op.paymaster_and_data().is_empty());
+        assert_eq!(
+            "000000000000000000000000000249f0000000000000000000000000000186a0",
+            hex::encode(op.account_gas_limits())
+        );
+
+        op.factory = Some([0xfa; 20]);
+        op.factory_data = vec![0x01, 0x02];
+        op.paymaster = Some([0x9a; 20]);
+        op.paymaster_verification_gas_limit = 60_000;
+        op.paymaster_data = vec![0x03];
+        assert_eq!(22, op.init_code().len());
+        assert_eq!(20 + 32 + 1, op.paymaster_and_data().len());
+
+        let json = serde_json::to_value(&op).unwrap();
+        assert_eq!("0x0", json["paymasterPostOpGasLimit"]);
+        assert_eq!("0x0102", json["factoryData"]);
+        assert_eq!(op, serde_json::from_value(json).unwrap());
+
+        let json = serde_json::to_value(packed_user_operation()).unwrap();
+        assert!(json.get("factory").is_none());
+        assert!(json.get("paymasterData").is_none());
+        assert_eq!(
+            packed_user_operation(),
+            serde_json::from_value(json).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_handle_ops_data() {
+        let ecdsa = user_operation()
+            .sign(&ENTRY_POINT_V06, 1, &[0x35; 32])
+            .unwrap();
+        let op = user_operation().with_signature(&ecdsa).unwrap();
+        let data = UserOperation::handle_ops_data(&[op], &[0xbe; 20]);
+
+        assert_eq!(HANDLE_OPS_V06_SELECTOR, data[..4]);
+        let words: Vec<&[u8]> = data[4..].chunks(32).collect();
+        assert_eq!(0x40, words[0][31]);
+        assert_eq!(&[0xbe; 20][..], &words[1][12..]);
+        // one operation, at offset 0x20 from the start of the offsets
+        assert_eq!(1, words[2][31]);
+        assert_eq!(0x20, words[3][31]);
+        assert_eq!(&[0x5a; 20][..], &words[4][12..]);
+
+        let data = PackedUserOperation::handle_ops_data(&[packed_user_operation()], &[0xbe; 20]);
+        assert_eq!(HANDLE_OPS_V07_SELECTOR, data[..4]);
+        let words: Vec<&[u8]> = data[4..].chunks(32).collect();
+        assert_eq!(&packed_user_operation().account_gas_limits()[..], words[8]);
+    }
+}
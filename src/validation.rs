@@ -0,0 +1,415 @@
+use hex;
+use std::collections::HashSet;
+use std::fmt;
+
+use {
+    AccessList, AccessListTransaction, EcdsaSig, FeeMarketTransaction, LegacyTransaction,
+    Transaction,
+};
+
+/// Base cost of every transaction.
+const TX_GAS: u128 = 21_000;
+/// Additional cost of a transaction that creates a contract.
+const TX_CREATE_GAS: u128 = 32_000;
+/// Cost per zero byte of input data.
+const TX_DATA_ZERO_GAS: u128 = 4;
+/// Cost per non-zero byte of input data ([EIP-2028](https://eips.ethereum.org/EIPS/eip-2028)).
+const TX_DATA_NON_ZERO_GAS: u128 = 16;
+/// Cost per address in the access list ([EIP-2930](https://eips.ethereum.org/EIPS/eip-2930)).
+const TX_ACCESS_LIST_ADDRESS_GAS: u128 = 2_400;
+/// Cost per storage key in the access list ([EIP-2930](https://eips.ethereum.org/EIPS/eip-2930)).
+const TX_ACCESS_LIST_STORAGE_KEY_GAS: u128 = 1_900;
+/// Cost per 32-byte word of initcode ([EIP-3860](https://eips.ethereum.org/EIPS/eip-3860)).
+const INITCODE_WORD_GAS: u128 = 2;
+
+/// Maximum size of initcode ([EIP-3860](https://eips.ethereum.org/EIPS/eip-3860)).
+pub const MAX_INITCODE_SIZE: usize = 49_152;
+/// Maximum size of a signed transaction accepted by the geth transaction pool.
+pub const MAX_TX_SIZE: usize = 128 * 1024;
+
+/// A problem that would make a transaction invalid or cause nodes to reject it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The chain ID is zero.
+    ZeroChainId,
+    /// The chain ID is so large that the [EIP-155](https://eips.ethereum.org/EIPS/eip-155)
+    /// `v` value (`chain * 2 + 35`) overflows.
+    ChainIdOverflow(u64),
+    /// The nonce is not below `2^64 - 1` ([EIP-2681](https://eips.ethereum.org/EIPS/eip-2681)).
+    NonceOverflow(u128),
+    /// The gas limit does not fit in 64 bits.
+    GasLimitOverflow(u128),
+    /// The gas limit is below the intrinsic gas of the transaction.
+    IntrinsicGasTooLow { gas: u128, intrinsic_gas: u128 },
+    /// `max_priority_fee_per_gas` is greater than `max_fee_per_gas`.
+    PriorityFeeAboveMaxFee {
+        max_priority_fee_per_gas: u128,
+        max_fee_per_gas: u128,
+    },
+    /// The contract creation code is larger than [`MAX_INITCODE_SIZE`].
+    InitcodeTooLarge(usize),
+    /// The signed transaction would be larger than [`MAX_TX_SIZE`].
+    TransactionTooLarge(usize),
+    /// An address appears more than once in the access list.
+    DuplicateAccessListAddress([u8; 20]),
+    /// A storage key appears more than once for the same address in the access list.
+    DuplicateStorageKey {
+        address: [u8; 20],
+        storage_key: [u8; 32],
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::ZeroChainId => write!(f, "chain ID must not be zero"),
+            ValidationError::ChainIdOverflow(chain) => {
+                write!(f, "chain ID {} is too large to encode in v", chain)
+            }
+            ValidationError::NonceOverflow(nonce) => {
+                write!(f, "nonce {} is not below 2^64 - 1", nonce)
+            }
+            ValidationError::GasLimitOverflow(gas) => {
+                write!(f, "gas limit {} does not fit in 64 bits", gas)
+            }
+            ValidationError::IntrinsicGasTooLow { gas, intrinsic_gas } => write!(
+                f,
+                "gas limit {} is below the intrinsic gas of {}",
+                gas, intrinsic_gas
+            ),
+            ValidationError::PriorityFeeAboveMaxFee {
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+            } => write!(
+                f,
+                "max priority fee per gas {} is greater than max fee per gas {}",
+                max_priority_fee_per_gas, max_fee_per_gas
+            ),
+            ValidationError::InitcodeTooLarge(size) => write!(
+                f,
+                "initcode is {} bytes, the maximum is {}",
+                size, MAX_INITCODE_SIZE
+            ),
+            ValidationError::TransactionTooLarge(size) => write!(
+                f,
+                "signed transaction is {} bytes, the maximum is {}",
+                size, MAX_TX_SIZE
+            ),
+            ValidationError::DuplicateAccessListAddress(ref address) => write!(
+                f,
+                "address 0x{} appears more than once in the access list",
+                hex::encode(address)
+            ),
+            ValidationError::DuplicateStorageKey {
+                ref address,
+                ref storage_key,
+            } => write!(
+                f,
+                "storage key 0x{} appears more than once for address 0x{}",
+                hex::encode(storage_key),
+                hex::encode(address)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl LegacyTransaction {
+    /// Gas consumed by the transaction before any code is executed.
+    pub fn intrinsic_gas(&self) -> u128 {
+        intrinsic_gas(self.to.is_none(), &self.data, None)
+    }
+
+    /// Check the transaction against consensus and transaction pool rules.
+    /// Every problem found is returned, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        validate_chain(self.chain, true, &mut errors);
+        validate_common(
+            self,
+            self.nonce,
+            self.gas,
+            self.intrinsic_gas(),
+            self.to.is_none(),
+            &self.data,
+            &mut errors,
+        );
+        into_result(errors)
+    }
+}
+
+impl AccessListTransaction {
+    /// Gas consumed by the transaction before any code is executed.
+    pub fn intrinsic_gas(&self) -> u128 {
+        intrinsic_gas(self.to.is_none(), &self.data, Some(&self.access_list))
+    }
+
+    /// Check the transaction against consensus and transaction pool rules.
+    /// Every problem found is returned, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        validate_chain(self.chain, false, &mut errors);
+        validate_common(
+            self,
+            self.nonce,
+            self.gas,
+            self.intrinsic_gas(),
+            self.to.is_none(),
+            &self.data,
+            &mut errors,
+        );
+        validate_access_list(&self.access_list, &mut errors);
+        into_result(errors)
+    }
+}
+
+impl FeeMarketTransaction {
+    /// Gas consumed by the transaction before any code is executed.
+    pub fn intrinsic_gas(&self) -> u128 {
+        intrinsic_gas(self.to.is_none(), &self.data, Some(&self.access_list))
+    }
+
+    /// Check the transaction against consensus and transaction pool rules.
+    /// Every problem found is returned, not just the first.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        validate_chain(self.chain, false, &mut errors);
+        if self.max_priority_fee_per_gas > self.max_fee_per_gas {
+            errors.push(ValidationError::PriorityFeeAboveMaxFee {
+                max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+                max_fee_per_gas: self.max_fee_per_gas,
+            });
+        }
+        validate_common(
+            self,
+            self.nonce,
+            self.gas,
+            self.intrinsic_gas(),
+            self.to.is_none(),
+            &self.data,
+            &mut errors,
+        );
+        validate_access_list(&self.access_list, &mut errors);
+        into_result(errors)
+    }
+}
+
+fn intrinsic_gas(is_create: bool, data: &[u8], access_list: Option<&AccessList>) -> u128 {
+    let zero_bytes = data.iter().filter(|b| **b == 0).count() as u128;
+    let non_zero_bytes = data.len() as u128 - zero_bytes;
+
+    let mut gas = TX_GAS + zero_bytes * TX_DATA_ZERO_GAS + non_zero_bytes * TX_DATA_NON_ZERO_GAS;
+
+    if is_create {
+        let words = (data.len() as u128).div_ceil(32);
+        gas += TX_CREATE_GAS + words * INITCODE_WORD_GAS;
+    }
+
+    if let Some(access_list) = access_list {
+        for access in access_list.0.iter() {
+            gas += TX_ACCESS_LIST_ADDRESS_GAS;
+            gas += access.storage_keys.len() as u128 * TX_ACCESS_LIST_STORAGE_KEY_GAS;
+        }
+    }
+
+    gas
+}
+
+fn validate_chain(chain: u64, is_legacy: bool, errors: &mut Vec<ValidationError>) {
+    if chain == 0 {
+        errors.push(ValidationError::ZeroChainId);
+    }
+    if is_legacy
+        && chain
+            .checked_mul(2)
+            .and_then(|c| c.checked_add(36))
+            .is_none()
+    {
+        errors.push(ValidationError::ChainIdOverflow(chain));
+    }
+}
+
+fn validate_common<T: Transaction>(
+    tx: &T,
+    nonce: u128,
+    gas: u128,
+    intrinsic_gas: u128,
+    is_create: bool,
+    data: &[u8],
+    errors: &mut Vec<ValidationError>,
+) {
+    if nonce >= u64::MAX as u128 {
+        errors.push(ValidationError::NonceOverflow(nonce));
+    }
+    if gas > u64::MAX as u128 {
+        errors.push(ValidationError::GasLimitOverflow(gas));
+    }
+    if gas < intrinsic_gas {
+        errors.push(ValidationError::IntrinsicGasTooLow { gas, intrinsic_gas });
+    }
+    if is_create && data.len() > MAX_INITCODE_SIZE {
+        errors.push(ValidationError::InitcodeTooLarge(data.len()));
+    }
+
+    // measure with the largest possible signature so the limit is never underestimated
+    let placeholder = EcdsaSig {
+        v: u64::MAX,
+        r: vec![0xff; 32],
+        s: vec![0xff; 32],
+    };
+    let size = tx.sign(&placeholder).len();
+    if size > MAX_TX_SIZE {
+        errors.push(ValidationError::TransactionTooLarge(size));
+    }
+}
+
+fn validate_access_list(access_list: &AccessList, errors: &mut Vec<ValidationError>) {
+    let mut addresses = HashSet::new();
+    let mut storage_keys = HashSet::new();
+
+    for access in access_list.0.iter() {
+        if !addresses.insert(access.address) {
+            errors.push(ValidationError::DuplicateAccessListAddress(access.address));
+        }
+        for storage_key in access.storage_keys.iter() {
+            if !storage_keys.insert((access.address, *storage_key)) {
+                errors.push(ValidationError::DuplicateStorageKey {
+                    address: access.address,
+                    storage_key: *storage_key,
+                });
+            }
+        }
+    }
+}
+
+fn into_result(errors: Vec<ValidationError>) -> Result<(), Vec<ValidationError>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use validation::{ValidationError, MAX_INITCODE_SIZE};
+    use {Access, AccessList, FeeMarketTransaction, LegacyTransaction};
+
+    fn transfer() -> FeeMarketTransaction {
+        FeeMarketTransaction {
+            chain: 1,
+            nonce: 0,
+            max_priority_fee_per_gas: 2_000_000_000,
+            max_fee_per_gas: 30_000_000_000,
+            gas: 21_000,
+            to: Some([0x45; 20]),
+            value: 1000,
+            data: vec![],
+            access_list: AccessList(vec![]),
+        }
+    }
+
+    #[test]
+    fn test_valid_transfer() {
+        assert_eq!(Ok(()), transfer().validate());
+    }
+
+    #[test]
+    fn test_intrinsic_gas() {
+        let tx = FeeMarketTransaction {
+            to: None,
+            data: vec![0, 0, 1, 2, 3],
+            access_list: AccessList(vec![Access {
+                address: [0x01; 20],
+                storage_keys: vec![[0x02; 32], [0x03; 32]],
+            }]),
+            ..transfer()
+        };
+        // 21000 + 32000 + 2 * 4 + 3 * 16 + 1 word * 2 + 2400 + 2 * 1900
+        assert_eq!(59_258, tx.intrinsic_gas());
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let tx = FeeMarketTransaction {
+            chain: 0,
+            max_priority_fee_per_gas: 31_000_000_000,
+            gas: 21_000,
+            to: None,
+            data: vec![0x60; MAX_INITCODE_SIZE + 1],
+            access_list: AccessList(vec![
+                Access {
+                    address: [0x01; 20],
+                    storage_keys: vec![[0x02; 32], [0x02; 32]],
+                },
+                Access {
+                    address: [0x01; 20],
+                    storage_keys: vec![],
+                },
+            ]),
+            ..transfer()
+        };
+
+        let errors = tx.validate().unwrap_err();
+
+        assert_eq!(
+            vec![
+                ValidationError::ZeroChainId,
+                ValidationError::PriorityFeeAboveMaxFee {
+                    max_priority_fee_per_gas: 31_000_000_000,
+                    max_fee_per_gas: 30_000_000_000,
+                },
+                ValidationError::IntrinsicGasTooLow {
+                    gas: 21_000,
+                    intrinsic_gas: 21_000 + 32_000 + 49_153 * 16 + 1537 * 2 + 2 * 2400 + 2 * 1900,
+                },
+                ValidationError::InitcodeTooLarge(MAX_INITCODE_SIZE + 1),
+                ValidationError::DuplicateStorageKey {
+                    address: [0x01; 20],
+                    storage_key: [0x02; 32],
+                },
+                ValidationError::DuplicateAccessListAddress([0x01; 20]),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_legacy_chain_overflow() {
+        let tx = LegacyTransaction {
+            chain: u64::MAX / 2,
+            nonce: u64::MAX as u128,
+            to: Some([0x45; 20]),
+            value: 1000,
+            gas_price: 20_000_000_000,
+            gas: 21_000,
+            data: vec![],
+        };
+
+        assert_eq!(
+            Err(vec![
+                ValidationError::ChainIdOverflow(u64::MAX / 2),
+                ValidationError::NonceOverflow(u64::MAX as u128),
+            ]),
+            tx.validate()
+        );
+    }
+
+    #[test]
+    fn test_transaction_too_large() {
+        let tx = FeeMarketTransaction {
+            gas: 10_000_000,
+            data: vec![0; 128 * 1024],
+            ..transfer()
+        };
+
+        match tx.validate() {
+            Err(ref errors) => match errors[..] {
+                [ValidationError::TransactionTooLarge(size)] => assert!(size > 128 * 1024),
+                _ => panic!("Unexpected errors: {:?}", errors),
+            },
+            Ok(()) => panic!("Expected the transaction to be too large"),
+        }
+    }
+}